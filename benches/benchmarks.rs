@@ -22,7 +22,7 @@ fn bench_gen_perl(c: &mut Criterion) {
 }
 
 fn bench_built_in(c: &mut Criterion) {
-    c.bench_function("bench_gen_perl", |b| {
+    c.bench_function("bench_built_in", |b| {
         b.iter(|| {
             TmplBuilder::new("perl")
                 .set_type(PkgType::PerlDist)
@@ -30,6 +30,35 @@ fn bench_built_in(c: &mut Criterion) {
                 .unwrap()
         })
     });
+
+    // gen_deps checks every dependency of a package like this
+    c.bench_function("bench_built_in_deps", |b| {
+        b.iter(|| {
+            for dep in &[
+                "Carp",
+                "Class-Load",
+                "Data-OptList",
+                "Devel-StackTrace",
+                "Exporter",
+                "Module-Runtime",
+                "Scalar-List-Utils",
+                "Try-Tiny",
+            ] {
+                TmplBuilder::new(dep)
+                    .set_type(PkgType::PerlDist)
+                    .is_built_in()
+                    .unwrap();
+            }
+        })
+    });
+
+    c.bench_function("bench_license_correction", |b| {
+        b.iter(|| {
+            KnownData::get()
+                .license_correction("perl_5", PkgType::PerlDist)
+                .unwrap()
+        })
+    });
 }
 
 fn bench_gen_deps_perl(c: &mut Criterion) {
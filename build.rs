@@ -13,9 +13,62 @@
 //You should have received a copy of the GNU General Public License
 //along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
 
+use clap::{App, Shell, YamlLoader};
 use std::fs::OpenOptions;
 use std::io::Read;
 use std::io::Write;
+use std::path::Path;
+use yaml_rust::Yaml;
+
+/// The subcommands whose PKGNAMEs are templates in $XBPS_DISTDIR/srcpkgs, which the completions
+/// complete
+const SRCPKGS_SUBCOMMANDS: &[&str] = &["update", "check"];
+
+const BASH_PKGNAMES: &str = r#"
+_tmplgen_srcpkgs() {
+    local distdir="${XBPS_DISTDIR/#\~/$HOME}"
+    if [[ -d "${distdir}/srcpkgs" ]]; then
+        ls "${distdir}/srcpkgs"
+    fi
+}
+
+_tmplgen_pkgnames() {
+    local cur prev word
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+
+    if [[ "${cur}" != -* && "${prev}" != @(@value_options@) ]]; then
+        for word in "${COMP_WORDS[@]:1:COMP_CWORD-1}"; do
+            if [[ "${word}" == @(@srcpkgs_subcommands@) ]]; then
+                COMPREPLY=( $(compgen -W "$(_tmplgen_srcpkgs)" -- "${cur}") )
+                return 0
+            fi
+        done
+    fi
+
+    _tmplgen "$@"
+}
+
+complete -F _tmplgen_pkgnames -o bashdefault -o default tmplgen
+"#;
+
+const ZSH_PKGNAMES: &str = r#"
+_tmplgen_pkgnames() {
+    local distdir="${XBPS_DISTDIR/#\~/$HOME}"
+    compadd -- "${distdir}"/srcpkgs/*(N:t)
+}
+"#;
+
+const FISH_PKGNAMES: &str = r#"
+function __tmplgen_srcpkgs
+    set -l distdir (string replace -r '^~' $HOME -- $XBPS_DISTDIR)
+    if test -d "$distdir/srcpkgs"
+        ls "$distdir/srcpkgs"
+    end
+end
+
+complete -c tmplgen -n "__fish_seen_subcommand_from @srcpkgs_subcommands@" -f -a "(__tmplgen_srcpkgs)"
+"#;
 
 fn main() {
     let version = env!("CARGO_PKG_VERSION");
@@ -31,14 +84,394 @@ fn main() {
     cli_string = cli_string.replace("@version_string@", version);
 
     let out_dir = std::env::var("OUT_DIR").unwrap();
-    let dest_path = std::path::Path::new(&out_dir).join("cli_gen.yml");
 
-    let mut cli_yml_out = OpenOptions::new()
+    write_out(&out_dir, "cli_gen.yml", &cli_string);
+
+    let cli_yaml = YamlLoader::load_from_str(&cli_string).unwrap();
+    let cli_args = CliArgs::from_yaml(&cli_yaml[0]);
+
+    for shell in &[Shell::Bash, Shell::Fish, Shell::Zsh] {
+        let mut app = App::from_yaml(&cli_yaml[0]);
+        let mut completions = Vec::new();
+        app.gen_completions_to("tmplgen", *shell, &mut completions);
+
+        let (file_name, completions) =
+            add_pkgname_completion(*shell, &cli_args, &String::from_utf8(completions).unwrap());
+
+        write_out(&out_dir, file_name, &completions);
+    }
+
+    let mut man_in = String::new();
+    OpenOptions::new()
+        .read(true)
+        .open("man/tmplgen.1.scd")
+        .unwrap()
+        .read_to_string(&mut man_in)
+        .unwrap();
+
+    write_out(
+        &out_dir,
+        "tmplgen.1",
+        &gen_man_page(&cli_yaml[0], &cli_args, &man_in),
+    );
+}
+
+fn write_out(out_dir: &str, file_name: &str, content: &str) {
+    let dest_path = Path::new(out_dir).join(file_name);
+
+    let mut out_file = OpenOptions::new()
         .write(true)
         .create(true)
-        .append(false)
+        .truncate(true)
         .open(dest_path)
         .unwrap();
 
-    cli_yml_out.write_all(cli_string.as_bytes()).unwrap();
+    out_file.write_all(content.as_bytes()).unwrap();
+}
+
+struct CliArg {
+    name: String,
+    short: Option<String>,
+    long: Option<String>,
+    value_name: Option<String>,
+    help: String,
+    index: bool,
+    required: bool,
+    multiple: bool,
+}
+
+struct CliArgs {
+    args: Vec<CliArg>,
+    subcommands: Vec<(String, String, Vec<CliArg>)>,
+}
+
+impl CliArg {
+    fn from_yaml(arg: &Yaml) -> Vec<Self> {
+        arg.as_vec()
+            .map(|x| x.iter().collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|x| x.as_hash())
+            .flat_map(|x| x.iter())
+            .map(|(name, settings)| {
+                let value_of = |key: &str| settings[key].as_str().map(|x| x.to_string());
+
+                CliArg {
+                    name: name.as_str().unwrap().to_string(),
+                    short: value_of("short"),
+                    long: value_of("long"),
+                    value_name: value_of("value_name"),
+                    help: value_of("help").unwrap_or_default(),
+                    index: !settings["index"].is_badvalue(),
+                    required: settings["required"].as_bool().unwrap_or(false),
+                    multiple: settings["multiple"].as_bool().unwrap_or(false),
+                }
+            })
+            .collect()
+    }
+
+    /// How the argument is shown in the usage, e.g. `<PKGNAME>...`
+    fn usage(&self) -> String {
+        let usage = if self.required {
+            format!("<{}>", self.name)
+        } else {
+            format!("[{}]", self.name)
+        };
+
+        if self.multiple {
+            usage + "..."
+        } else {
+            usage
+        }
+    }
+
+    /// The switches of the argument, e.g. `["-f", "--force"]`
+    fn switches(&self) -> Vec<String> {
+        self.short
+            .iter()
+            .map(|x| format!("-{}", x))
+            .chain(self.long.iter().map(|x| format!("--{}", x)))
+            .collect()
+    }
+}
+
+impl CliArgs {
+    fn from_yaml(cli_yaml: &Yaml) -> Self {
+        let subcommands = cli_yaml["subcommands"]
+            .as_vec()
+            .map(|x| x.iter().collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|x| x.as_hash())
+            .flat_map(|x| x.iter())
+            .map(|(name, settings)| {
+                (
+                    name.as_str().unwrap().to_string(),
+                    settings["about"].as_str().unwrap_or_default().to_string(),
+                    CliArg::from_yaml(&settings["args"]),
+                )
+            })
+            .collect();
+
+        CliArgs {
+            args: CliArg::from_yaml(&cli_yaml["args"]),
+            subcommands,
+        }
+    }
+
+    /// The switches of all options that take a value, including those of subcommands
+    fn value_options(&self) -> Vec<String> {
+        let mut value_options = self
+            .args
+            .iter()
+            .chain(self.subcommands.iter().flat_map(|x| x.2.iter()))
+            .filter(|x| x.value_name.is_some())
+            .flat_map(|x| x.switches())
+            .collect::<Vec<_>>();
+
+        value_options.sort();
+        value_options.dedup();
+        value_options
+    }
+}
+
+/// Adds the completion of the names of the templates in srcpkgs for the PKGNAMEs of the
+/// [SRCPKGS_SUBCOMMANDS](SRCPKGS_SUBCOMMANDS) to the completions clap generated for `shell` and
+/// returns the file name they should be saved as
+fn add_pkgname_completion(
+    shell: Shell,
+    cli_args: &CliArgs,
+    completions: &str,
+) -> (&'static str, String) {
+    match shell {
+        Shell::Bash => {
+            let pkgnames = BASH_PKGNAMES
+                .replace("@value_options@", &cli_args.value_options().join("|"))
+                .replace("@srcpkgs_subcommands@", &SRCPKGS_SUBCOMMANDS.join("|"));
+
+            ("tmplgen.bash", format!("{}{}", completions, pkgnames))
+        }
+        Shell::Zsh => {
+            let mut subcommand = String::new();
+            let mut replaced = false;
+
+            // The arguments of each subcommand are listed after its `(name)` case
+            let mut completions = completions
+                .lines()
+                .map(|x| {
+                    let trimmed = x.trim();
+
+                    if trimmed.starts_with('(') && trimmed.ends_with(')') {
+                        subcommand = trimmed[1..trimmed.len() - 1].to_string();
+                    }
+
+                    if SRCPKGS_SUBCOMMANDS.contains(&subcommand.as_str())
+                        && x.contains(":PKGNAME")
+                        && x.contains(":_files'")
+                    {
+                        replaced = true;
+                        x.replace(":_files'", ":_tmplgen_pkgnames'")
+                    } else {
+                        x.to_string()
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            if replaced {
+                completions.insert(1, ZSH_PKGNAMES.to_string());
+            } else {
+                println!(
+                    "cargo:warning=Couldn't add the completion of PKGNAME to the zsh completions"
+                );
+            }
+
+            ("_tmplgen", completions.join("\n") + "\n")
+        }
+        _ => {
+            let pkgnames =
+                FISH_PKGNAMES.replace("@srcpkgs_subcommands@", &SRCPKGS_SUBCOMMANDS.join(" "));
+
+            ("tmplgen.fish", format!("{}{}", completions, pkgnames))
+        }
+    }
+}
+
+/// Escapes the characters roff would interpret in `text`
+fn roff_chars(text: &str) -> String {
+    text.replace('\\', "\\e").replace('-', "\\-")
+}
+
+/// Escapes `text` so roff prints it as-is, even at the beginning of a line
+fn roff_escape(text: &str) -> String {
+    let escaped = roff_chars(text);
+
+    if escaped.starts_with('.') || escaped.starts_with('\'') {
+        format!("\\&{}", escaped)
+    } else {
+        escaped
+    }
+}
+
+/// Converts the inline markup of scdoc (`*bold*`, `_underlined_` and `\` escapes) to roff
+fn scd_inline(line: &str) -> String {
+    let mut roff = String::new();
+    let mut chars = line.chars();
+    let mut bold = false;
+    let mut underlined = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => roff.push_str(&roff_chars(&chars.next().unwrap_or('\\').to_string())),
+            '*' => {
+                bold = !bold;
+                roff.push_str(if bold { "\\fB" } else { "\\fR" });
+            }
+            '_' => {
+                underlined = !underlined;
+                roff.push_str(if underlined { "\\fI" } else { "\\fR" });
+            }
+            c => roff.push_str(&roff_chars(&c.to_string())),
+        }
+    }
+
+    if roff.starts_with('.') || roff.starts_with('\'') {
+        format!("\\&{}", roff)
+    } else {
+        roff
+    }
+}
+
+/// Converts the sections of the scdoc page `scd` to roff. Only supports the subset of scdoc the
+/// man page uses: headings, paragraphs, lists and literal blocks
+fn scd_to_roff(scd: &str) -> String {
+    let mut roff = String::new();
+    let mut literal = false;
+
+    // Skip the `tmplgen(1)` header
+    for line in scd.lines().skip(1) {
+        if line == "```" {
+            roff.push_str(if literal {
+                ".fi\n.RE\n"
+            } else {
+                ".RS 4\n.nf\n"
+            });
+            literal = !literal;
+        } else if literal {
+            roff.push_str(&roff_escape(line));
+            roff.push('\n');
+        } else if line.starts_with(';') {
+            continue;
+        } else if line.starts_with("# ") {
+            roff.push_str(&format!(".SH {}\n", &line[2..]));
+        } else if line.trim().is_empty() {
+            roff.push_str(".P\n");
+        } else if line.starts_with("- ") {
+            roff.push_str(&format!(".IP \\(bu 2\n{}\n", scd_inline(&line[2..])));
+        } else {
+            roff.push_str(&scd_inline(line.trim()));
+            roff.push('\n');
+        }
+    }
+
+    roff
+}
+
+fn roff_args(args: &[&CliArg]) -> String {
+    args.iter()
+        .map(|x| {
+            let mut switches = x
+                .switches()
+                .iter()
+                .map(|x| format!("\\fB{}\\fR", roff_escape(x)))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            if let Some(value_name) = &x.value_name {
+                switches.push_str(&format!(" <{}>", roff_escape(value_name)));
+            } else if x.index {
+                switches = x.usage();
+            }
+
+            format!(".TP\n{}\n{}\n", switches, roff_escape(&x.help))
+        })
+        .collect()
+}
+
+/// Generates the man page from the CLI definition and the hand-written sections in `man_scd`
+fn gen_man_page(cli_yaml: &Yaml, cli_args: &CliArgs, man_scd: &str) -> String {
+    let help_flag = CliArg {
+        name: "help".to_string(),
+        short: Some("h".to_string()),
+        long: Some("help".to_string()),
+        value_name: None,
+        help: "Prints help information".to_string(),
+        index: false,
+        required: false,
+        multiple: false,
+    };
+    let version_flag = CliArg {
+        name: "version".to_string(),
+        short: Some("V".to_string()),
+        long: Some("version".to_string()),
+        value_name: None,
+        help: "Prints version information".to_string(),
+        index: false,
+        required: false,
+        multiple: false,
+    };
+
+    let mut man = format!(
+        ".TH \"TMPLGEN\" \"1\" \"\" \"tmplgen {}\" \"User Commands\"\n",
+        cli_yaml["version"].as_str().unwrap_or_default()
+    );
+
+    man.push_str(&format!(
+        ".SH NAME\ntmplgen \\- {}\n",
+        roff_escape(cli_yaml["about"].as_str().unwrap_or_default())
+    ));
+
+    man.push_str(".SH SYNOPSIS\n\\fBtmplgen\\fR [FLAGS] [OPTIONS] <SUBCOMMAND>\n");
+
+    for (name, _, args) in &cli_args.subcommands {
+        man.push_str(&format!(
+            ".br\n\\fBtmplgen {}\\fR [FLAGS] [OPTIONS] {}\n",
+            name,
+            args.iter()
+                .filter(|x| x.index)
+                .map(|x| x.usage())
+                .collect::<Vec<_>>()
+                .join(" ")
+        ));
+    }
+
+    let mut flags = cli_args
+        .args
+        .iter()
+        .filter(|x| x.value_name.is_none() && !x.index)
+        .collect::<Vec<_>>();
+    flags.push(&help_flag);
+    flags.push(&version_flag);
+    flags.sort_by_key(|x| x.long.clone());
+
+    let options = cli_args
+        .args
+        .iter()
+        .filter(|x| x.value_name.is_some())
+        .collect::<Vec<_>>();
+
+    man.push_str(&format!(".SH FLAGS\n{}", roff_args(&flags)));
+    man.push_str(&format!(".SH OPTIONS\n{}", roff_args(&options)));
+
+    if !cli_args.subcommands.is_empty() {
+        man.push_str(".SH SUBCOMMANDS\n");
+
+        for (name, about, args) in &cli_args.subcommands {
+            man.push_str(&format!(".SS {}\n{}\n", name, roff_escape(about)));
+            man.push_str(&roff_args(&args.iter().collect::<Vec<_>>()));
+        }
+    }
+
+    man.push_str(&scd_to_roff(man_scd));
+
+    man
 }
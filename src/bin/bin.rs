@@ -16,25 +16,62 @@
 use clap::{App, YamlLoader};
 use env_logger::Builder;
 use libtmplgen::*;
+use rayon::prelude::*;
+use serde_json::json;
 use std::fs::{create_dir_all, File};
 use std::io::prelude::*;
 use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
 
-use log::{error, warn};
+use log::{error, info, warn};
+
+mod interactive;
 
 #[cfg(test)]
 mod tests;
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Subcommand {
+    New,
+    Update,
+    Check,
+    Info,
+    Search,
+    Deps,
+    Completions(String),
+    Man,
+    PrintData,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone)]
 pub(crate) struct BinOptions {
-    pub pkg_name: String,
+    pub subcommand: Subcommand,
+    pub pkg_names: Vec<String>,
     pub tmpl_type: Option<PkgType>,
+    pub xdist_dir: Option<String>,
+    pub format: OutputFormat,
     pub force_overwrite: bool,
+    pub interactive: bool,
     pub verbose: bool,
     pub debug: bool,
-    pub update_ver: bool,
     pub update_all: bool,
     pub no_prefix: bool,
+    pub features: CrateFeatures,
+    pub list_features: bool,
+    pub pick: bool,
+    pub perl_version: Option<String>,
+    pub ruby_version: Option<String>,
+    pub skeleton: Option<String>,
+    pub variables: Vec<String>,
+    pub pkg_info_file: Option<String>,
+    pub retries: Option<u32>,
+    pub timeout: Option<u64>,
 }
 
 fn main() {
@@ -42,6 +79,13 @@ fn main() {
 
     set_up_logging(bin_options.debug, bin_options.verbose);
 
+    // libtmplgen also looks for data tables and skeletons in XBPS_DISTDIR
+    if let Some(xdist_dir) = &bin_options.xdist_dir {
+        std::env::set_var("XBPS_DISTDIR", xdist_dir);
+    }
+
+    set_network_policy(bin_network_policy(&bin_options));
+
     // This isn't so very pretty, especially since main() can return Result since Rust 2018,
     // but we need this for pretty error messages via `env_logger`.
     actual_work(&bin_options)
@@ -52,33 +96,197 @@ fn main() {
         .unwrap();
 }
 
+/// The network policy libtmplgen uses, with the flags passed on the command line overriding the
+/// environment variables
+fn bin_network_policy(opts: &BinOptions) -> NetworkPolicy {
+    let mut policy = network_policy();
+
+    if let Some(retries) = opts.retries {
+        policy.retries = retries;
+    }
+    if let Some(timeout) = opts.timeout {
+        policy.timeout = Some(std::time::Duration::from_secs(timeout))
+            .filter(|x| *x > std::time::Duration::from_secs(0));
+    }
+
+    policy
+}
+
 fn actual_work(opts: &BinOptions) -> Result<(), Error> {
-    if opts.update_ver && opts.update_all {
-        warn!("Specified both -u and -U! Will ignore -u");
+    match &opts.subcommand {
+        Subcommand::New => {
+            if let Some(pkg_info_file) = &opts.pkg_info_file {
+                new_templates_from_file(opts, pkg_info_file)?;
+            }
+
+            for pkg_name in &opts.pkg_names {
+                new_template(opts, pkg_name)?;
+            }
+        }
+        Subcommand::Update => {
+            for pkg_name in &opts.pkg_names {
+                update_template(opts, pkg_name)?;
+            }
+        }
+        Subcommand::Check => check_templates(opts)?,
+        Subcommand::Info => print_info(opts)?,
+        Subcommand::Search => search(opts)?,
+        Subcommand::Deps => print_deps(opts)?,
+        Subcommand::Completions(shell) => print!("{}", completions(shell)),
+        Subcommand::Man => print!("{}", include_str!(concat!(env!("OUT_DIR"), "/tmplgen.1"))),
+        Subcommand::PrintData => print!("{}", data_tables()?),
     }
 
-    let mut tmpl_builder = TmplBuilder::new(&opts.pkg_name);
+    Ok(())
+}
+
+/// Sets up a TmplBuilder for `pkg_name` with the options the user passed
+fn tmpl_builder(opts: &BinOptions, pkg_name: &str) -> Result<TmplBuilder, Error> {
+    let mut tmpl_builder = TmplBuilder::new(pkg_name);
 
-    if opts.tmpl_type.is_some() {
-        tmpl_builder.set_type(opts.tmpl_type.unwrap());
+    if let Some(tmpl_type) = opts.tmpl_type {
+        tmpl_builder.set_type(tmpl_type);
+    } else if opts.interactive {
+        tmpl_builder.set_type(interactive::pick_provider(pkg_name)?);
     } else {
         tmpl_builder.get_type()?;
     }
 
+    configure_builder(opts, &mut tmpl_builder)?;
+
+    Ok(tmpl_builder)
+}
+
+/// Applies the options the user passed to `tmpl_builder`, whose PkgType has to be set already
+fn configure_builder(opts: &BinOptions, tmpl_builder: &mut TmplBuilder) -> Result<(), Error> {
+    if let Some(perl_version) = &opts.perl_version {
+        if tmpl_builder.pkg_type.unwrap() == PkgType::PerlDist {
+            tmpl_builder.set_perl_version(perl_version);
+        } else {
+            warn!("Only perldists depend on perl! Will ignore --perl-version");
+        }
+    }
+
+    if let Some(ruby_version) = &opts.ruby_version {
+        if tmpl_builder.pkg_type.unwrap() == PkgType::Gem {
+            tmpl_builder.set_ruby_version(ruby_version);
+        } else {
+            warn!("Only gems depend on Ruby! Will ignore --ruby-version");
+        }
+    }
+
     if tmpl_builder.is_built_in()? {
         return Err(Error::BuiltIn(tmpl_builder.pkg_name.clone()));
     }
 
+    if opts.features != CrateFeatures::default() {
+        if tmpl_builder.pkg_type.unwrap() == PkgType::Crate {
+            tmpl_builder.set_features(opts.features.clone());
+        } else {
+            warn!("Only crates have features! Will ignore --features and --no-default-features");
+        }
+    }
+
+    if let Some(skeleton_path) = &opts.skeleton {
+        let mut skeleton = String::new();
+        File::open(skeleton_path)?.read_to_string(&mut skeleton)?;
+        tmpl_builder.set_skeleton(&skeleton);
+    }
+
+    for variable in &opts.variables {
+        let mut split = variable.splitn(2, '=');
+
+        match (split.next(), split.next()) {
+            (Some(name), Some(value)) if !name.is_empty() => {
+                tmpl_builder.set_variable(name, value);
+            }
+            _ => {
+                return Err(Error::WrongUsage {
+                    method: "--var".to_string(),
+                    err: format!("'{}' doesn't look like NAME=VALUE!", variable),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the template of `pkg_name` and the templates of the perldists/gems it depends on
+fn new_template(opts: &BinOptions, pkg_name: &str) -> Result<(), Error> {
+    let mut tmpl_builder = tmpl_builder(opts, pkg_name)?;
+
+    tmpl_builder.get_info()?;
+
+    write_new_template(opts, tmpl_builder)
+}
+
+/// Writes the templates of the PkgInfos in the JSON file `path` (`-` for stdin), e.g. the edited
+/// output of `tmplgen info --json`, and the templates of the perldists/gems they depend on
+fn new_templates_from_file(opts: &BinOptions, path: &str) -> Result<(), Error> {
+    let tmpl_type = match opts.tmpl_type {
+        Some(tmpl_type) => tmpl_type,
+        None => {
+            return Err(Error::WrongUsage {
+                method: "--pkg-info".to_string(),
+                err: "The PkgInfo doesn't say which provider it's from, pass it via --tmpltype!"
+                    .to_string(),
+            })
+        }
+    };
+
+    for pkg_info in read_pkg_infos(path)? {
+        let mut tmpl_builder = TmplBuilder::from_pkg_info(pkg_info);
+        tmpl_builder.set_type(tmpl_type);
+
+        configure_builder(opts, &mut tmpl_builder)?;
+
+        write_new_template(opts, tmpl_builder)?;
+    }
+
+    Ok(())
+}
+
+/// Reads either a single PkgInfo or a list of them, like `tmplgen info --json` prints them
+fn read_pkg_infos(path: &str) -> Result<Vec<PkgInfo>, Error> {
+    let mut json = String::new();
+
+    if path == "-" {
+        std::io::stdin().read_to_string(&mut json)?;
+    } else {
+        File::open(path)?.read_to_string(&mut json)?;
+    }
+
+    let invalid_json = |e: serde_json::Error| Error::Json(format!("{}: {}", path, e));
+
+    match serde_json::from_str::<serde_json::Value>(&json).map_err(invalid_json)? {
+        serde_json::Value::Array(pkg_infos) => pkg_infos
+            .into_iter()
+            .map(|x| serde_json::from_value(x).map_err(invalid_json))
+            .collect(),
+        pkg_info => Ok(vec![serde_json::from_value(pkg_info).map_err(invalid_json)?]),
+    }
+}
+
+/// Writes the template of the package whose PkgInfo `tmpl_builder` holds already and the
+/// templates of the perldists/gems it depends on
+fn write_new_template(opts: &BinOptions, mut tmpl_builder: TmplBuilder) -> Result<(), Error> {
     if opts.no_prefix {
-        let mut pkg_info = tmpl_builder.get_info()?.pkg_info.clone().unwrap();
+        let mut pkg_info = tmpl_builder.pkg_info.clone().unwrap();
         pkg_info.pkg_name = pkg_info
             .pkg_name
             .replace("perl-", "")
             .replace("ruby-", "")
             .replace("rust-", "");
-        tmpl_builder.set_info(pkg_info.to_owned());
-    } else {
-        tmpl_builder.get_info()?;
+        tmpl_builder.set_info(pkg_info);
+    }
+
+    if opts.interactive {
+        let mut pkg_info = tmpl_builder.pkg_info.clone().unwrap();
+        interactive::edit_short_desc(&mut pkg_info)?;
+        tmpl_builder.set_info(pkg_info);
+
+        interactive::confirm_licenses(&mut tmpl_builder)?;
     }
 
     let xdist_template_path = format!(
@@ -87,38 +295,14 @@ fn actual_work(opts: &BinOptions) -> Result<(), Error> {
         tmpl_builder.pkg_info.as_ref().unwrap().pkg_name
     );
 
-    let template = if opts.update_ver || opts.update_all {
-        if Path::new(&xdist_template_path).exists() {
-            let mut template_file = File::open(&xdist_template_path)?;
-            let mut template_string = String::new();
-            template_file.read_to_string(&mut template_string)?;
-
-            tmpl_builder.update(
-                &Template {
-                    inner: template_string,
-                    name: opts.pkg_name.clone(),
-                },
-                opts.update_all,
-            )
-        } else {
-            return Err(Error::TmplUpdater(format!(
-                "Can't update non-existing template {}",
-                &tmpl_builder.pkg_info.unwrap().pkg_name
-            )));
-        }
-    } else if Path::new(&xdist_template_path).exists() && !opts.force_overwrite {
+    if Path::new(&xdist_template_path).exists() && !opts.force_overwrite {
         return Err(Error::TmplWriter(format!(
             "Won't overwrite existing template '{}' without `--force`!",
             &xdist_template_path,
         )));
-    } else {
-        tmpl_builder.generate(!opts.no_prefix)
-    };
-
-    create_dir_all(&xdist_template_path.replace("/template", ""))?;
+    }
 
-    let mut file = File::create(&xdist_template_path)?;
-    file.write_all(template?.inner.as_bytes())?;
+    write_template(&tmpl_builder.generate(!opts.no_prefix)?)?;
 
     // We don't want to generate recursive deps for crates, as they don't have any!
     if tmpl_builder.pkg_type.unwrap() == PkgType::Crate {
@@ -135,17 +319,21 @@ fn actual_work(opts: &BinOptions) -> Result<(), Error> {
         let dep_template_vec = tmpl_builder.gen_deps(Some(&format!("{}/srcpkgs", xdist_dir()?)));
 
         if dep_template_vec.is_ok() {
-            for x in dep_template_vec.unwrap() {
-                let xdist_template_path = format!("{}/srcpkgs/{}/template", xdist_dir()?, x.name,);
+            let mut dep_templates = dep_template_vec.unwrap();
 
-                create_dir_all(&xdist_template_path.replace("/template", ""))?;
+            if opts.interactive {
+                dep_templates = interactive::pick_deps(
+                    &tmpl_builder.pkg_info.as_ref().unwrap().pkg_name,
+                    dep_templates,
+                )?;
+            }
 
-                let mut file = File::create(&xdist_template_path)?;
-                file.write_all(x.inner.as_bytes())?;
+            for x in dep_templates {
+                write_template(&x)?;
             }
         } else {
             return Err(Error::RecDeps {
-                pkg_name: opts.pkg_name.clone(),
+                pkg_name: tmpl_builder.pkg_name.clone(),
                 err: dep_template_vec.err().unwrap().to_string(),
             });
         }
@@ -154,18 +342,405 @@ fn actual_work(opts: &BinOptions) -> Result<(), Error> {
     Ok(())
 }
 
+/// Updates the template `tmpl_name` in srcpkgs, if a new version is available
+fn update_template(opts: &BinOptions, tmpl_name: &str) -> Result<(), Error> {
+    let old_template = match read_template(tmpl_name)? {
+        Some(old_template) => old_template,
+        None => {
+            return Err(Error::TmplUpdater(format!(
+                "Can't update non-existing template {}",
+                tmpl_name
+            )));
+        }
+    };
+
+    let mut tmpl_builder = TmplBuilder::from_template(&old_template)?;
+
+    if let Some(tmpl_type) = opts.tmpl_type {
+        tmpl_builder.set_type(tmpl_type);
+    }
+
+    configure_builder(opts, &mut tmpl_builder)?;
+
+    let mut template = tmpl_builder
+        .get_info()?
+        .update(&old_template, opts.update_all)?;
+
+    // The template might not be named like we'd name it, e.g. because it isn't prefixed
+    template.name = old_template.name.clone();
+
+    write_template(&template)
+}
+
+/// Reports the templates for which a new version is available
+fn check_templates(opts: &BinOptions) -> Result<(), Error> {
+    let templates = if opts.pkg_names.is_empty() {
+        let mut templates = Vec::new();
+
+        for entry in std::fs::read_dir(format!("{}/srcpkgs", xdist_dir()?))? {
+            let entry = entry?;
+
+            // Subpackages are symlinks to the directory of their main package
+            if entry.file_type()?.is_symlink() {
+                continue;
+            }
+
+            if let Some(template) = read_template(&entry.file_name().to_string_lossy())? {
+                // Only check templates of crates, gems and perldists
+                if TmplBuilder::from_template(&template).is_ok() {
+                    templates.push(template);
+                }
+            }
+        }
+
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+        templates
+    } else {
+        opts.pkg_names
+            .iter()
+            .map(|x| match read_template(x)? {
+                Some(template) => Ok(template),
+                None => Err(Error::TmplUpdater(format!(
+                    "Can't check non-existing template {}",
+                    x
+                ))),
+            })
+            .collect::<Result<Vec<_>, Error>>()?
+    };
+
+    let outdated = templates
+        .par_iter()
+        .filter_map(|template| {
+            match TmplBuilder::from_template(template).and_then(|x| x.check_update(template)) {
+                Ok(Some(latest_version)) => Some((
+                    template.name.clone(),
+                    template.get_field("version").unwrap_or_default(),
+                    latest_version,
+                )),
+                Ok(None) => {
+                    info!("{} is up to date", template.name);
+                    None
+                }
+                Err(ref e) if e.is_not_found() => {
+                    warn!(
+                        "{} doesn't exist on its registry anymore: {}",
+                        template.name, e
+                    );
+                    None
+                }
+                Err(e) => {
+                    warn!("Couldn't check {} for updates: {}", template.name, e);
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    match opts.format {
+        OutputFormat::Text => {
+            for (name, version, latest_version) in outdated {
+                println!("{} {} -> {}", name, version, latest_version);
+            }
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            json!(outdated
+                .iter()
+                .map(|(name, version, latest_version)| json!({
+                    "name": name,
+                    "version": version,
+                    "latest_version": latest_version,
+                }))
+                .collect::<Vec<_>>())
+        ),
+    }
+
+    Ok(())
+}
+
+/// Prints the PkgInfo (or the features with `--list-features`) of the packages
+fn print_info(opts: &BinOptions) -> Result<(), Error> {
+    let mut infos = Vec::new();
+
+    for (i, pkg_name) in opts.pkg_names.iter().enumerate() {
+        let mut tmpl_builder = tmpl_builder(opts, pkg_name)?;
+
+        if opts.list_features {
+            let features = tmpl_builder.get_features()?;
+
+            match opts.format {
+                OutputFormat::Text => {
+                    for (feature, enables) in features {
+                        println!("{} = [{}]", feature, enables.join(", "));
+                    }
+                }
+                OutputFormat::Json => infos.push(json!(features)),
+            }
+
+            continue;
+        }
+
+        let pkg_info = tmpl_builder.get_info()?.pkg_info.as_ref().unwrap();
+
+        match opts.format {
+            OutputFormat::Text => {
+                if i > 0 {
+                    println!();
+                }
+
+                print!("{}", pkg_info_text(pkg_info));
+            }
+            OutputFormat::Json => infos.push(json!(pkg_info)),
+        }
+    }
+
+    if opts.format == OutputFormat::Json {
+        println!("{}", json!(infos));
+    }
+
+    Ok(())
+}
+
+fn pkg_info_text(pkg_info: &PkgInfo) -> String {
+    let mut fields = vec![
+        ("pkgname", pkg_info.pkg_name.clone()),
+        ("version", pkg_info.version.clone()),
+        (
+            "short_desc",
+            pkg_info.description.clone().unwrap_or_default(),
+        ),
+        ("homepage", pkg_info.homepage.clone()),
+        (
+            "license",
+            pkg_info.license.clone().unwrap_or_default().join(", "),
+        ),
+        (
+            "distfiles",
+            pkg_info.download_url.clone().unwrap_or_default(),
+        ),
+        ("checksum", pkg_info.sha.clone()),
+    ];
+
+    if let Some(dependencies) = &pkg_info.dependencies {
+        fields.push((
+            "hostmakedepends",
+            dependencies.host.clone().unwrap_or_default().join(" "),
+        ));
+        fields.push((
+            "makedepends",
+            dependencies.make.clone().unwrap_or_default().join(" "),
+        ));
+        fields.push((
+            "depends",
+            dependencies.run.clone().unwrap_or_default().join(" "),
+        ));
+    }
+
+    fields.push((
+        "subpackages",
+        pkg_info
+            .subpackages
+            .iter()
+            .map(|x| format!("{}-{}", pkg_info.pkg_name, x.suffix))
+            .collect::<Vec<_>>()
+            .join(" "),
+    ));
+
+    fields
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(field, value)| format!("{:<16} {}\n", format!("{}:", field), value))
+        .collect()
+}
+
+/// The name of a PkgType as it's passed to `-t`
+fn provider_name(pkg_type: PkgType) -> &'static str {
+    match pkg_type {
+        PkgType::Crate => "crate",
+        PkgType::Gem => "gem",
+        PkgType::PerlDist => "perldist",
+    }
+}
+
+/// Searches the registries for packages matching the query and lets the user pick one to write
+/// a template for if `--pick` was passed
+fn search(opts: &BinOptions) -> Result<(), Error> {
+    let query = &opts.pkg_names[0];
+
+    let mut tmpl_builder = TmplBuilder::new(query);
+
+    if let Some(tmpl_type) = opts.tmpl_type {
+        tmpl_builder.set_type(tmpl_type);
+    }
+
+    let srcpkgs = xdist_dir().ok().map(|x| format!("{}/srcpkgs", x));
+    let results = tmpl_builder.search(srcpkgs.as_ref().map(|x| x.as_str()))?;
+
+    if results.is_empty() {
+        return Err(Error::NoSuchPkg(query.clone()));
+    }
+
+    match opts.format {
+        OutputFormat::Text => {
+            println!(
+                "{:<4}{:<30} {:<16} {:<10} {:<9} {}",
+                "#", "NAME", "VERSION", "PROVIDER", "TEMPLATE", "DESCRIPTION"
+            );
+
+            for (i, result) in results.iter().enumerate() {
+                println!(
+                    "{:<4}{:<30} {:<16} {:<10} {:<9} {}",
+                    i + 1,
+                    result.name,
+                    result.version,
+                    provider_name(result.pkg_type),
+                    if result.template_exists { "yes" } else { "no" },
+                    result.description.as_ref().map_or("", |x| x.as_str())
+                );
+            }
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            json!(results
+                .iter()
+                .map(|result| json!({
+                    "name": result.name,
+                    "version": result.version,
+                    "description": result.description,
+                    "provider": provider_name(result.pkg_type),
+                    "template_exists": result.template_exists,
+                }))
+                .collect::<Vec<_>>())
+        ),
+    }
+
+    if opts.pick {
+        if let Some(result) = interactive::pick_result(&results)? {
+            let mut new_opts = opts.clone();
+            new_opts.subcommand = Subcommand::New;
+            new_opts.tmpl_type = Some(result.pkg_type);
+
+            new_template(&new_opts, &result.name)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the dependency trees of the packages
+fn print_deps(opts: &BinOptions) -> Result<(), Error> {
+    let mut trees = Vec::new();
+
+    for pkg_name in &opts.pkg_names {
+        let mut tmpl_builder = tmpl_builder(opts, pkg_name)?;
+        tmpl_builder.get_info()?;
+
+        let name = tmpl_builder.pkg_info.as_ref().unwrap().pkg_name.clone();
+        let deps = tmpl_builder.get_dep_tree()?;
+
+        match opts.format {
+            OutputFormat::Text => {
+                println!("{}", name);
+                print_dep_tree(&deps, 1);
+            }
+            OutputFormat::Json => trees.push(json!({
+                "name": name,
+                "deps": deps.iter().map(dep_tree_json).collect::<Vec<_>>(),
+            })),
+        }
+    }
+
+    if opts.format == OutputFormat::Json {
+        println!("{}", json!(trees));
+    }
+
+    Ok(())
+}
+
+fn print_dep_tree(deps: &[DepTree], depth: usize) {
+    for dep in deps {
+        println!("{}{} ({})", "  ".repeat(depth), dep.name, dep.field);
+        print_dep_tree(&dep.deps, depth + 1);
+    }
+}
+
+fn dep_tree_json(dep: &DepTree) -> serde_json::Value {
+    json!({
+        "name": dep.name,
+        "field": dep.field,
+        "deps": dep.deps.iter().map(dep_tree_json).collect::<Vec<_>>(),
+    })
+}
+
+/// Reads the template `tmpl_name` from srcpkgs, `None` if it doesn't exist
+fn read_template(tmpl_name: &str) -> Result<Option<Template>, Error> {
+    let xdist_template_path = format!("{}/srcpkgs/{}/template", xdist_dir()?, tmpl_name);
+
+    if !Path::new(&xdist_template_path).exists() {
+        return Ok(None);
+    }
+
+    let mut template_string = String::new();
+    File::open(&xdist_template_path)?.read_to_string(&mut template_string)?;
+
+    Ok(Some(Template {
+        inner: template_string,
+        name: tmpl_name.to_string(),
+        subpackages: Vec::new(),
+    }))
+}
+
+/// Writes `template` to srcpkgs and links its subpackages
+fn write_template(template: &Template) -> Result<(), Error> {
+    let xdist_template_path = format!("{}/srcpkgs/{}/template", xdist_dir()?, template.name);
+
+    create_dir_all(&xdist_template_path.replace("/template", ""))?;
+
+    let mut file = File::create(&xdist_template_path)?;
+    file.write_all(template.inner.as_bytes())?;
+
+    link_subpackages(template)
+}
+
+/// Creates the `srcpkgs/{subpackage} -> {pkgname}` symlinks xbps-src needs to find the
+/// subpackages of `template`
+fn link_subpackages(template: &Template) -> Result<(), Error> {
+    for subpackage in &template.subpackages {
+        let link_path = format!("{}/srcpkgs/{}", xdist_dir()?, subpackage);
+
+        match std::fs::read_link(&link_path) {
+            Ok(target) if target == Path::new(&template.name) => continue,
+            Ok(_) => warn!(
+                "{} already exists and doesn't point to {}, won't touch it!",
+                link_path, template.name
+            ),
+            Err(_) if Path::new(&link_path).exists() => warn!(
+                "{} already exists and isn't a symlink, won't touch it!",
+                link_path
+            ),
+            Err(_) => std::os::unix::fs::symlink(&template.name, &link_path)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the completions for `shell`, which build.rs generated from the CLI definition
+fn completions(shell: &str) -> &'static str {
+    match shell {
+        "bash" => include_str!(concat!(env!("OUT_DIR"), "/tmplgen.bash")),
+        "fish" => include_str!(concat!(env!("OUT_DIR"), "/tmplgen.fish")),
+        _ => include_str!(concat!(env!("OUT_DIR"), "/_tmplgen")),
+    }
+}
+
 fn set_up_logging(is_debug: bool, is_verbose: bool) {
     let mut builder = Builder::new();
 
     if is_debug {
         builder
             .filter_module("libtmplgen", log::LevelFilter::Debug)
-            .filter_module("tmplgen", log::LevelFilter::Debug)
-            // Also include what GET requests the below modules do, which
-            // get the data we need for PkgInfo
-            .filter_module("crates_io_api", log::LevelFilter::Trace)
-            .filter_module("rubygems_api", log::LevelFilter::Debug)
-            .filter_module("metacpan_api", log::LevelFilter::Debug);
+            .filter_module("tmplgen", log::LevelFilter::Debug);
     } else if is_verbose {
         builder
             .filter_module("libtmplgen", log::LevelFilter::Info)
@@ -189,47 +764,147 @@ fn help_string() -> BinOptions {
         YamlLoader::load_from_str(include_str!(concat!(env!("OUT_DIR"), "/cli_gen.yml"))).unwrap();
     let matches = App::from_yaml(&help_yaml[0]).get_matches();
 
-    let tmpl_type = if matches.value_of("tmpltype").unwrap_or_default() == "crate" {
-        Some(PkgType::Crate)
-    } else if matches.value_of("tmpltype").unwrap_or_default() == "gem" {
-        Some(PkgType::Gem)
-    } else if matches.value_of("tmpltype").unwrap_or_default() == "perldist" {
-        Some(PkgType::PerlDist)
+    let (subcommand, sub_matches) = match matches.subcommand() {
+        ("new", Some(sub_matches)) => (Subcommand::New, sub_matches),
+        ("update", Some(sub_matches)) => (Subcommand::Update, sub_matches),
+        ("check", Some(sub_matches)) => (Subcommand::Check, sub_matches),
+        ("info", Some(sub_matches)) => (Subcommand::Info, sub_matches),
+        ("search", Some(sub_matches)) => (Subcommand::Search, sub_matches),
+        ("deps", Some(sub_matches)) => (Subcommand::Deps, sub_matches),
+        ("completions", Some(sub_matches)) => (
+            Subcommand::Completions(
+                sub_matches
+                    .value_of("SHELL")
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            sub_matches,
+        ),
+        ("man", Some(sub_matches)) => (Subcommand::Man, sub_matches),
+        _ if matches.is_present("print_data") => (Subcommand::PrintData, &matches),
+        _ => {
+            eprintln!("{}\n\nFor more information try --help", matches.usage());
+            std::process::exit(1);
+        }
+    };
+
+    // Global options can be passed before or after the subcommand
+    let is_present = |name: &str| matches.is_present(name) || sub_matches.is_present(name);
+    let value_of = |name: &str| {
+        sub_matches
+            .value_of(name)
+            .or_else(|| matches.value_of(name))
+            .map(|x| x.to_string())
+    };
+
+    let pkg_names = sub_matches
+        .values_of("PKGNAME")
+        .or_else(|| sub_matches.values_of("QUERY"))
+        .map(|x| x.map(|x| x.to_string()).collect())
+        .unwrap_or_default();
+
+    let tmpl_type = match value_of("tmpltype").unwrap_or_default().as_str() {
+        "crate" => Some(PkgType::Crate),
+        "gem" => Some(PkgType::Gem),
+        "perldist" => Some(PkgType::PerlDist),
+        _ => None,
+    };
+
+    let xdist_dir = value_of("distdir");
+
+    let format = if matches.value_of("format") == Some("json")
+        || sub_matches.value_of("format") == Some("json")
+        || sub_matches.is_present("json")
+    {
+        OutputFormat::Json
     } else {
-        None
+        OutputFormat::Text
     };
 
-    let pkg_name = String::from(matches.value_of("PKGNAME").unwrap());
+    let force_overwrite = sub_matches.is_present("force");
 
-    let force_overwrite = matches.is_present("force");
+    let interactive = sub_matches.is_present("interactive");
 
-    let verbose = matches.is_present("verbose");
+    let verbose = is_present("verbose");
 
-    let debug = matches.is_present("debug");
+    let debug = is_present("debug");
 
-    let update_ver = matches.is_present("update");
+    let update_all = sub_matches.is_present("all");
 
-    let update_all = matches.is_present("update_all");
+    let no_prefix = sub_matches.is_present("no_prefix");
 
-    let no_prefix = matches.is_present("no_prefix");
+    let features = CrateFeatures {
+        features: sub_matches
+            .values_of("features")
+            .map(|x| x.map(|x| x.to_string()).collect())
+            .unwrap_or_default(),
+        no_default_features: sub_matches.is_present("no_default_features"),
+    };
+
+    let list_features = sub_matches.is_present("list_features");
+
+    let pick = sub_matches.is_present("pick");
+
+    let perl_version = sub_matches.value_of("perl_version").map(|x| x.to_string());
+
+    let ruby_version = sub_matches.value_of("ruby_version").map(|x| x.to_string());
+
+    let skeleton = sub_matches.value_of("skeleton").map(|x| x.to_string());
+
+    let variables = sub_matches
+        .values_of("var")
+        .map(|x| x.map(|x| x.to_string()).collect())
+        .unwrap_or_default();
+
+    let pkg_info_file = sub_matches.value_of("pkg_info").map(|x| x.to_string());
+
+    let retries = parse_number("retries", value_of("retries"));
+
+    let timeout = parse_number("timeout", value_of("timeout"));
 
     BinOptions {
-        pkg_name,
+        subcommand,
+        pkg_names,
         tmpl_type,
+        xdist_dir,
+        format,
         force_overwrite,
+        interactive,
         verbose,
         debug,
-        update_ver,
         update_all,
         no_prefix,
+        features,
+        list_features,
+        pick,
+        perl_version,
+        ruby_version,
+        skeleton,
+        variables,
+        pkg_info_file,
+        retries,
+        timeout,
     }
 }
 
+/// Parses the value of the argument `name`, exiting with clap's error message if it isn't a number
+fn parse_number<T: std::str::FromStr>(name: &str, value: Option<String>) -> Option<T> {
+    value.map(|x| {
+        x.parse().unwrap_or_else(|_| {
+            clap::Error::value_validation_auto(format!(
+                "The argument '{}' isn't a valid number for --{}",
+                x, name
+            ))
+            .exit()
+        })
+    })
+}
+
 fn xdist_dir() -> Result<String, Error> {
     let xdist_env = std::env::var_os("XBPS_DISTDIR");
 
     if xdist_env.is_none() {
-        return Err(libtmplgen::Error::Xdist("Couldn't get XBPS_DISTDIR variable, please set it to where you want to write the template to!".to_string()));
+        return Err(libtmplgen::Error::Xdist("Couldn't get XBPS_DISTDIR variable, please set it (or pass --distdir) to where you want to write the template to!".to_string()));
     }
 
     let unclean_dir = std::str::from_utf8(xdist_env.unwrap().as_bytes())?.to_string();
@@ -0,0 +1,235 @@
+//This file is part of tmplgen
+//
+//tmplgen is free software: you can redistribute it and/or modify
+//it under the terms of the GNU General Public License as published by
+//the Free Software Foundation, either version 3 of the License, or
+//(at your option) any later version.
+//
+//tmplgen is distributed in the hope that it will be useful,
+//but WITHOUT ANY WARRANTY; without even the implied warranty of
+//MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//GNU General Public License for more details.
+//
+//You should have received a copy of the GNU General Public License
+//along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::provider_name;
+use libtmplgen::*;
+use std::io::prelude::*;
+
+/// Asks the user `question` on stderr and returns their answer without surrounding whitespace
+///
+/// # Errors
+///
+/// * Errors out if stdin is closed before the user answered
+fn ask(question: &str) -> Result<String, Error> {
+    eprint!("{} ", question);
+    std::io::stderr().flush()?;
+
+    let mut answer = String::new();
+
+    if std::io::stdin().read_line(&mut answer)? == 0 {
+        return Err(Error::WrongUsage {
+            method: "--interactive".to_string(),
+            err: "stdin was closed while waiting for an answer!".to_string(),
+        });
+    }
+
+    Ok(answer.trim().to_string())
+}
+
+/// Parses `answer` as a number between 1 and `max` and returns it as index
+pub(crate) fn parse_choice(answer: &str, max: usize) -> Option<usize> {
+    match answer.parse::<usize>() {
+        Ok(i) if i >= 1 && i <= max => Some(i - 1),
+        _ => None,
+    }
+}
+
+/// Parses `answer` as selection of the items 1 to `max` (e.g. `1,3` or `2 4`, `all` or `none`)
+/// and returns the indices of the selected items. An empty answer selects all items.
+pub(crate) fn parse_selection(answer: &str, max: usize) -> Option<Vec<usize>> {
+    match answer {
+        "" | "all" => Some((0..max).collect()),
+        "none" => Some(Vec::new()),
+        _ => {
+            let mut selection = answer
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|x| !x.is_empty())
+                .map(|x| parse_choice(x, max))
+                .collect::<Option<Vec<usize>>>()?;
+
+            selection.sort();
+            selection.dedup();
+
+            Some(selection)
+        }
+    }
+}
+
+/// Asks the user to pick a number between 1 and `max` until they give a valid one. Returns `None`
+/// if `allow_empty` is set and the user didn't pick any.
+fn ask_choice(question: &str, max: usize, allow_empty: bool) -> Result<Option<usize>, Error> {
+    loop {
+        let answer = ask(question)?;
+
+        if answer.is_empty() && allow_empty {
+            return Ok(None);
+        }
+
+        match parse_choice(&answer, max) {
+            Some(i) => return Ok(Some(i)),
+            None => eprintln!("Please answer with a number between 1 and {}!", max),
+        }
+    }
+}
+
+/// Asks the user on which provider `pkg_name` is the package they want a template for
+///
+/// # Errors
+///
+/// * Errors out if `pkg_name` can't be found on any provider
+/// * Errors out if a provider can't be reached
+pub(crate) fn pick_provider(pkg_name: &str) -> Result<PkgType, Error> {
+    let matches = TmplBuilder::new(pkg_name).find_providers()?;
+
+    match matches.len() {
+        0 => return Err(Error::NoSuchPkg(pkg_name.to_string())),
+        1 => return Ok(matches[0].pkg_type),
+        _ => {}
+    }
+
+    eprintln!("Found {} on several providers:", pkg_name);
+
+    for (i, provider_match) in matches.iter().enumerate() {
+        eprintln!(
+            "  {} {} {}",
+            i + 1,
+            provider_name(provider_match.pkg_type),
+            provider_match.version
+        );
+    }
+
+    let i = ask_choice(
+        &format!("Which one to use? [1-{}]:", matches.len()),
+        matches.len(),
+        false,
+    )?
+    .unwrap();
+
+    Ok(matches[i].pkg_type)
+}
+
+/// Asks the user which of the search results to write a template for. Returns `None` if the
+/// user didn't pick any.
+pub(crate) fn pick_result(results: &[SearchResult]) -> Result<Option<&SearchResult>, Error> {
+    Ok(ask_choice(
+        &format!(
+            "Write a template for which package? [1-{}, empty to abort]:",
+            results.len()
+        ),
+        results.len(),
+        true,
+    )?
+    .map(|i| &results[i]))
+}
+
+/// Lets the user shorten the description of the package if it's too long for `short_desc`
+pub(crate) fn edit_short_desc(pkg_info: &mut PkgInfo) -> Result<(), Error> {
+    let description = match &pkg_info.description {
+        Some(description) if description.len() >= 80 => description.clone(),
+        _ => return Ok(()),
+    };
+
+    eprintln!(
+        "The short_desc of {} is longer than 80 characters:\n  {}",
+        pkg_info.pkg_name, description
+    );
+
+    loop {
+        let answer = ask("New short_desc [empty to keep it]:")?;
+
+        if answer.is_empty() {
+            return Ok(());
+        } else if answer.len() < 80 {
+            pkg_info.description = Some(answer);
+            return Ok(());
+        }
+
+        eprintln!("That's still {} characters long!", answer.len());
+    }
+}
+
+/// Shows the user the licenses which will be written to the template and lets them correct them
+///
+/// # Errors
+///
+/// * Errors out if pkg_type and pkg_info of `tmpl_builder` aren't set
+pub(crate) fn confirm_licenses(tmpl_builder: &mut TmplBuilder) -> Result<(), Error> {
+    let licenses = tmpl_builder.get_licenses()?;
+    let mut pkg_info = tmpl_builder.pkg_info.clone().unwrap();
+
+    let question = if licenses.is_empty() {
+        eprintln!("Couldn't determine the license of {}.", pkg_info.pkg_name);
+        "License [e.g. 'MIT, Apache-2.0', empty to add it to the template yourself]:"
+    } else {
+        eprintln!(
+            "The license of {} will be written as: {}",
+            pkg_info.pkg_name,
+            licenses.join(", ")
+        );
+        "Correct license [e.g. 'MIT, Apache-2.0', empty to keep it]:"
+    };
+
+    let answer = ask(question)?;
+
+    if !answer.is_empty() {
+        pkg_info.license = Some(
+            answer
+                .split(',')
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .collect(),
+        );
+        tmpl_builder.set_info(pkg_info);
+    }
+
+    Ok(())
+}
+
+/// Asks the user which of the templates `dep_templates` of the dependencies of `pkg_name` to
+/// write and returns them
+pub(crate) fn pick_deps(
+    pkg_name: &str,
+    dep_templates: Vec<Template>,
+) -> Result<Vec<Template>, Error> {
+    if dep_templates.is_empty() {
+        return Ok(dep_templates);
+    }
+
+    eprintln!("{} depends on packages without a template:", pkg_name);
+
+    for (i, template) in dep_templates.iter().enumerate() {
+        eprintln!("  {} {}", i + 1, template.name);
+    }
+
+    let selection = loop {
+        let answer =
+            ask("Write templates for which of them? [all, none or e.g. 1,3; empty for all]:")?;
+
+        match parse_selection(&answer, dep_templates.len()) {
+            Some(selection) => break selection,
+            None => eprintln!(
+                "Please answer with all, none or numbers between 1 and {}!",
+                dep_templates.len()
+            ),
+        }
+    };
+
+    Ok(dep_templates
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| selection.contains(i))
+        .map(|(_, template)| template)
+        .collect())
+}
@@ -15,18 +15,41 @@
 
 use crate::*;
 use assert_cmd::prelude::*;
-use std::env::set_var;
+use std::env::{set_var, var};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use tempfile::tempdir;
 
+/// The responses of the registries recorded for the tests
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lib/tests/fixtures")
+}
+
+trait ForwardHttpMode {
+    fn forward_http_mode(&mut self) -> &mut Self;
+}
+
+impl ForwardHttpMode for Command {
+    /// Passes `TMPLGEN_HTTP_MODE` on to tmplgen despite `env_clear`, so the tests replay or record
+    /// the responses of the registries if asked to
+    fn forward_http_mode(&mut self) -> &mut Self {
+        if let Ok(mode) = var("TMPLGEN_HTTP_MODE") {
+            self.env("TMPLGEN_HTTP_MODE", mode);
+        }
+
+        self.env("TMPLGEN_HTTP_FIXTURES", fixtures_dir())
+    }
+}
+
 #[test]
 fn test_bin_gen() {
     let dir = tempdir().unwrap();
 
     Command::cargo_bin(env!("CARGO_PKG_NAME"))
         .unwrap()
-        .args(&["tmplgen"])
+        .args(&["new", "tmplgen"])
         .env_clear()
+        .forward_http_mode()
         .env("XBPS_DISTDIR", dir.path().join("tmplgen-tests/"))
         .env("GIT_AUTHOR_NAME", "tmplgentests")
         .env("GIT_AUTHOR_EMAIL", "tmplgen@tests.de")
@@ -42,8 +65,9 @@ fn test_bin_update() {
 
     Command::cargo_bin(env!("CARGO_PKG_NAME"))
         .unwrap()
-        .args(&["tmplgen"])
+        .args(&["new", "tmplgen"])
         .env_clear()
+        .forward_http_mode()
         .env("XBPS_DISTDIR", dir.path().join("tmplgen-tests/"))
         .env("GIT_AUTHOR_NAME", "tmplgentests")
         .env("GIT_AUTHOR_EMAIL", "tmplgen@tests.de")
@@ -52,8 +76,9 @@ fn test_bin_update() {
 
     Command::cargo_bin(env!("CARGO_PKG_NAME"))
         .unwrap()
-        .args(&["-U", "tmplgen"])
+        .args(&["update", "--all", "tmplgen"])
         .env_clear()
+        .forward_http_mode()
         .env("XBPS_DISTDIR", dir.path().join("tmplgen-tests/"))
         .env("GIT_AUTHOR_NAME", "tmplgentests")
         .env("GIT_AUTHOR_EMAIL", "tmplgen@tests.de")
@@ -68,8 +93,9 @@ fn test_bin_update() {
 fn test_bad_env() {
     Command::cargo_bin("tmplgen")
         .unwrap()
-        .args(&["tmplgen"])
+        .args(&["new", "tmplgen"])
         .env_clear()
+        .forward_http_mode()
         .assert()
         .success();
 }
@@ -94,14 +120,27 @@ fn test_main_worker() {
     set_var("GIT_AUTHOR_EMAIL", "tmplgen@tests.de");
 
     let mut opts = BinOptions {
-        pkg_name: "tmplgen".to_string(),
+        subcommand: Subcommand::New,
+        pkg_names: vec!["tmplgen".to_string()],
         tmpl_type: Some(PkgType::Crate),
+        xdist_dir: None,
+        format: OutputFormat::Text,
         force_overwrite: false,
+        interactive: false,
         verbose: false,
         debug: false,
         update_all: false,
-        update_ver: false,
         no_prefix: false,
+        features: CrateFeatures::default(),
+        list_features: false,
+        pick: false,
+        perl_version: None,
+        ruby_version: None,
+        skeleton: None,
+        variables: Vec::new(),
+        pkg_info_file: None,
+        retries: None,
+        timeout: None,
     };
 
     actual_work(&opts).unwrap();
@@ -111,13 +150,288 @@ fn test_main_worker() {
     opts.no_prefix = true;
     actual_work(&opts).unwrap();
 
-    //opts.update_ver = true;
-    //actual_work(&opts).unwrap();
-    //opts.update_ver = false;
+    opts.subcommand = Subcommand::Update;
+    actual_work(&opts).unwrap();
 
-    //actual_work(&opts).unwrap();
-    //opts.update_all = true;
-    //actual_work(&opts).unwrap();
+    opts.update_all = true;
+    actual_work(&opts).unwrap();
+
+    opts.subcommand = Subcommand::Check;
+    actual_work(&opts).unwrap();
+
+    opts.pkg_names = vec!["rust-nonexistent".to_string()];
+    assert!(actual_work(&opts).is_err());
 
     dir.close().unwrap()
 }
+
+#[test]
+fn test_bin_features() {
+    let dir = tempdir().unwrap();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(&["-t", "crate", "info", "--list-features", "git2"])
+        .env_clear()
+        .forward_http_mode()
+        .env("XBPS_DISTDIR", dir.path().join("tmplgen-tests/"))
+        .assert()
+        .success();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(&[
+            "new",
+            "--no-default-features",
+            "--features",
+            "https,ssh",
+            "git2",
+        ])
+        .env_clear()
+        .forward_http_mode()
+        .env("XBPS_DISTDIR", dir.path().join("tmplgen-tests/"))
+        .env("GIT_AUTHOR_NAME", "tmplgentests")
+        .env("GIT_AUTHOR_EMAIL", "tmplgen@tests.de")
+        .assert()
+        .success();
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_bin_print_data() {
+    let dir = tempdir().unwrap();
+
+    std::fs::create_dir_all(dir.path().join("config/tmplgen")).unwrap();
+    std::fs::write(
+        dir.path().join("config/tmplgen/data.toml"),
+        "[[native_deps.rust]]\nname = \"foo-sys\"\ndep = \"foo-devel\"\n",
+    )
+    .unwrap();
+
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .arg("--print-data")
+        .env_clear()
+        .forward_http_mode()
+        .env("XDG_CONFIG_HOME", dir.path().join("config"))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("foo-devel"));
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_bin_completions() {
+    for (shell, pkgnames) in &[
+        ("bash", "_tmplgen_pkgnames"),
+        ("fish", "__tmplgen_srcpkgs"),
+        ("zsh", "_tmplgen_pkgnames"),
+    ] {
+        let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .unwrap()
+            .args(&["completions", shell])
+            .env_clear()
+            .forward_http_mode()
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains(pkgnames));
+    }
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(&["completions", "tcsh"])
+        .env_clear()
+        .forward_http_mode()
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_bin_man() {
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .arg("man")
+        .env_clear()
+        .forward_http_mode()
+        .output()
+        .unwrap();
+
+    let man_page = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(man_page.contains(".SH OPTIONS"));
+    assert!(man_page.contains("\\fB\\-\\-perl\\-version\\fR <VERSION>"));
+    assert!(man_page.contains(".SH TEMPLATE SKELETONS"));
+}
+
+#[test]
+fn test_bin_info() {
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(&["info", "-t", "crate", "--json", "tmplgen"])
+        .env_clear()
+        .forward_http_mode()
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(info[0]["pkg_name"], "tmplgen");
+    assert_eq!(info[0]["homepage"], "https://github.com/Cogitri/tmplgen");
+}
+
+#[test]
+fn test_bin_pkg_info() {
+    let dir = tempdir().unwrap();
+    let pkg_info_path = dir.path().join("pkg_info.json");
+
+    std::fs::write(
+        &pkg_info_path,
+        r#"[{
+            "pkg_name": "rust-tmplgen",
+            "version": "1.3.1",
+            "description": "Generates Void Linux templates",
+            "homepage": "https://github.com/Cogitri/tmplgen",
+            "license": ["GPL-3.0-or-later"],
+            "sha": "dummy_sha",
+            "download_url": "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate"
+        }]"#,
+    )
+    .unwrap();
+
+    // The PkgInfo doesn't say which provider it's from
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(&["new", "--pkg-info", pkg_info_path.to_str().unwrap()])
+        .env_clear()
+        .forward_http_mode()
+        .env("XBPS_DISTDIR", dir.path())
+        .assert()
+        .failure();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(&[
+            "new",
+            "-t",
+            "crate",
+            "--pkg-info",
+            pkg_info_path.to_str().unwrap(),
+        ])
+        .env_clear()
+        .forward_http_mode()
+        .env("XBPS_DISTDIR", dir.path())
+        .env("GIT_AUTHOR_NAME", "tmplgentests")
+        .env("GIT_AUTHOR_EMAIL", "tmplgen@tests.de")
+        .assert()
+        .success();
+
+    let template =
+        std::fs::read_to_string(dir.path().join("srcpkgs/rust-tmplgen/template")).unwrap();
+    assert!(template.contains("version=1.3.1\n"));
+    assert!(template.contains("short_desc=\"Generates Void Linux templates\"\n"));
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_bin_search() {
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(&["search", "tmplgen"])
+        .env_clear()
+        .forward_http_mode()
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("crate"));
+
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(&["search", "-t", "gem", "--format", "json", "diff-lcs"])
+        .env_clear()
+        .forward_http_mode()
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+
+    let results: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(results[0]["name"], "diff-lcs");
+    assert_eq!(results[0]["provider"], "gem");
+    assert_eq!(results[0]["template_exists"], false);
+}
+
+#[test]
+fn test_bin_deps() {
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(&["deps", "-t", "gem", "mocha"])
+        .env_clear()
+        .forward_http_mode()
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("ruby-mocha\n"));
+}
+
+#[test]
+fn test_bin_interactive() {
+    let dir = tempdir().unwrap();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(&["new", "-i", "-t", "crate", "tmplgen"])
+        .env_clear()
+        .forward_http_mode()
+        .env("XBPS_DISTDIR", dir.path())
+        .env("GIT_AUTHOR_NAME", "tmplgentests")
+        .env("GIT_AUTHOR_EMAIL", "tmplgen@tests.de")
+        .with_stdin()
+        .buffer("MIT\n")
+        .assert()
+        .success();
+
+    let template = std::fs::read_to_string(dir.path().join("srcpkgs/tmplgen/template")).unwrap();
+    assert!(template.contains("license=\"MIT\""));
+
+    // Nobody answers the prompt
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(&["new", "-i", "-f", "-t", "crate", "tmplgen"])
+        .env_clear()
+        .forward_http_mode()
+        .env("XBPS_DISTDIR", dir.path())
+        .env("GIT_AUTHOR_NAME", "tmplgentests")
+        .env("GIT_AUTHOR_EMAIL", "tmplgen@tests.de")
+        .with_stdin()
+        .buffer("")
+        .assert()
+        .failure();
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_parse_selection() {
+    assert_eq!(interactive::parse_choice("2", 3), Some(1));
+    assert_eq!(interactive::parse_choice("0", 3), None);
+    assert_eq!(interactive::parse_choice("4", 3), None);
+    assert_eq!(interactive::parse_choice("foo", 3), None);
+
+    assert_eq!(interactive::parse_selection("", 3), Some(vec![0, 1, 2]));
+    assert_eq!(interactive::parse_selection("all", 3), Some(vec![0, 1, 2]));
+    assert_eq!(interactive::parse_selection("none", 3), Some(Vec::new()));
+    assert_eq!(interactive::parse_selection("3, 1", 3), Some(vec![0, 2]));
+    assert_eq!(interactive::parse_selection("1 1 2", 3), Some(vec![0, 1]));
+    assert_eq!(interactive::parse_selection("1,4", 3), None);
+}
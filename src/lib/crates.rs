@@ -13,112 +13,945 @@
 //You should have received a copy of the GNU General Public License
 //along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::errors::Error;
+use crate::errors::{Error, ProviderError};
 use crate::helpers::*;
+use crate::http::*;
+use crate::registry::Registries;
+use crate::spdx::*;
 use crate::types::*;
-use log::debug;
-use retry::retry_exponentially;
+use flate2::read::GzDecoder;
+use futures::future::{self, Either};
+use futures::Future;
+use log::{debug, warn};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
 
-/// Query the crates.io API.
+/// Query the crates.io API. The native dependencies are determined for the crate built with
+/// `features`.
 ///
 /// # Errors
 ///
 /// * Errors out if crates.io can't be reached
 /// * Errors out if the crate can't be found on crates.io
-/// * Errors if the native deps can't be determined (via `check_native_deps`)
-// TODO: Switch to AsyncClient
-pub(super) fn crate_info(crate_name: &str) -> Result<PkgInfo, Error> {
-    let client = crates_io_api::SyncClient::with_user_agent(&format!(
-        "tmplgen/{} (github.com/Cogitri/tmplgen)",
-        env!("CARGO_PKG_VERSION")
-    ));
-
-    let query_result = match retry_exponentially(
-        3,
-        10.0,
-        &mut || client.full_crate(crate_name, false),
-        |result| result.is_ok(),
-    ) {
-        Ok(response) => response?,
-        Err(error) => return Err(Error::Crate(error.to_string())),
+/// * Errors if the native deps can't be determined (via `native_deps`)
+pub(super) fn crate_info(crate_name: &str, features: &CrateFeatures) -> Result<PkgInfo, Error> {
+    let crate_response = get_crate(crate_name)?;
+
+    debug!("crates.io query result: {:?}", crate_response);
+
+    // inspect_distfile can't replace ${version} itself, so we have to do it here
+    let distfile_info = inspect_distfile(
+        &crate_download_url(crate_name)
+            .replace("${version}", &crate_response.crate_data.max_version),
+    )?;
+
+    let crate_deps = native_deps(
+        crate_name,
+        &crate_response,
+        Some(features),
+        Some(&distfile_info),
+    )?;
+
+    let crate_meta = crate_meta(crate_response);
+
+    Ok(crate_pkg_info(
+        crate_name,
+        crate_meta,
+        crate_deps,
+        distfile_info,
+    ))
+}
+
+/// Gets what crates.io knows about the crate
+///
+/// # Errors
+///
+/// * Errors out if crates.io can't be reached
+/// * Errors out if the crate can't be found on crates.io
+fn get_crate(crate_name: &str) -> Result<CrateResponse, Error> {
+    get_json(&crate_api_url(crate_name), crate_not_found(crate_name))
+}
+
+/// The error for a crate crates.io doesn't know
+fn crate_not_found(crate_name: &str) -> Error {
+    ProviderError::not_found("The crate doesn't exist on crates.io")
+        .with_pkg(PkgType::Crate, crate_name)
+        .into()
+}
+
+/// The error for a version of a crate whose dependencies crates.io doesn't know
+fn crate_deps_not_found(crate_name: &str) -> Error {
+    ProviderError::not_found("Couldn't find the dependencies of the crate")
+        .with_pkg(PkgType::Crate, crate_name)
+        .into()
+}
+
+/// The URL of the crate in the API of the configured crates.io registry
+fn crate_api_url(crate_name: &str) -> String {
+    Registries::get().api_url(PkgType::Crate, &format!("crates/{}", crate_name))
+}
+
+/// The URL of the dependencies of version `version` of the crate in the API of the configured
+/// crates.io registry
+fn crate_deps_url(crate_name: &str, version: &str) -> String {
+    format!("{}/{}/dependencies", crate_api_url(crate_name), version)
+}
+
+/// Picks what we need to know about the crate from the answer of crates.io
+fn crate_meta(crate_response: CrateResponse) -> CrateMeta {
+    let max_version = crate_response.crate_data.max_version;
+
+    CrateMeta {
+        license: crate_response
+            .versions
+            .into_iter()
+            .find(|x| x.num == max_version)
+            .and_then(|x| x.license),
+        max_version,
+        description: crate_response.crate_data.description,
+        homepage: crate_response.crate_data.homepage,
+    }
+}
+
+/// Gets the latest version of the crate from the answer of crates.io, i.e. its `max_version`
+/// whose distfile we package. crates.io doesn't count yanked versions as `max_version`.
+///
+/// # Errors
+///
+/// * Errors out if `max_version` isn't one of the versions of the crate or was yanked
+pub(super) fn latest_version<'a>(
+    crate_name: &str,
+    crate_response: &'a CrateResponse,
+) -> Result<&'a CrateVersion, Error> {
+    let max_version = &crate_response.crate_data.max_version;
+
+    crate_response
+        .versions
+        .iter()
+        .find(|x| !x.yanked && &x.num == max_version)
+        .ok_or_else(|| {
+            ProviderError::not_found(format!(
+                "The crate doesn't have a version {} that isn't yanked",
+                max_version
+            ))
+            .with_pkg(PkgType::Crate, crate_name)
+            .into()
+        })
+}
+
+/// Gets the latest version of the crate that isn't yanked and matches the version requirement
+/// `req` (e.g. `^0.9`) from the answer of crates.io. Falls back to the latest version if `req`
+/// can't be parsed or no version matches it.
+///
+/// # Errors
+///
+/// * Errors out if the crate doesn't have any versions
+pub(super) fn matching_version<'a>(
+    crate_name: &str,
+    crate_response: &'a CrateResponse,
+    req: &str,
+) -> Result<&'a CrateVersion, Error> {
+    let matching = semver::VersionReq::parse(req).ok().and_then(|req| {
+        crate_response
+            .versions
+            .iter()
+            .filter(|x| !x.yanked)
+            .filter_map(|x| {
+                semver::Version::parse(&x.num)
+                    .ok()
+                    .map(|version| (version, x))
+            })
+            .filter(|(version, _)| req.matches(version))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, x)| x)
+    });
+
+    match matching {
+        Some(version) => Ok(version),
+        None => {
+            debug!(
+                "No version of crate {} matches {}, using the latest one",
+                crate_name, req
+            );
+            latest_version(crate_name, crate_response)
+        }
+    }
+}
+
+/// Determines the native dependencies of the latest version of the crate built with `features`,
+/// see [check_native_deps](crate::helpers::check_native_deps)
+///
+/// # Errors
+///
+/// * Errors out if crates.io can't be reached
+/// * Errors out if the crate can't be found on crates.io
+/// * Errors out if one of `features` isn't a feature of the crate
+pub(super) fn get_crate_native_deps(
+    crate_name: &str,
+    features: Option<&CrateFeatures>,
+) -> Result<Option<Dependencies>, Error> {
+    native_deps(crate_name, &get_crate(crate_name)?, features, None)
+}
+
+/// Determines the native dependencies of the latest version of the crate built with `features`.
+/// `crate_response` is what crates.io told us about the crate. If we've inspected its distfile
+/// already, its build script is read from `distfile_info` instead of downloading it again.
+///
+/// # Errors
+///
+/// * Errors out if crates.io can't be reached
+/// * Errors out if one of `features` isn't a feature of the crate
+/// * Errors out if one of the crates whose build script we have to look at can't be downloaded
+fn native_deps(
+    crate_name: &str,
+    crate_response: &CrateResponse,
+    features: Option<&CrateFeatures>,
+    distfile_info: Option<&DistfileInfo>,
+) -> Result<Option<Dependencies>, Error> {
+    let version = latest_version(crate_name, crate_response)?;
+    let dependencies = get_crate_deps(crate_name, &version.num)?;
+
+    let native_deps = crate_native_deps(crate_name, &dependencies, &version.features, features)?;
+
+    let mut build_script_infos = Vec::new();
+
+    if native_deps.own_build_script {
+        let build_script_info = match distfile_info {
+            Some(distfile_info) => distfile_build_script_info(crate_name, distfile_info),
+            None => analyze_build_script(crate_name, &version.num)?,
+        };
+
+        build_script_infos.push((crate_name.to_string(), build_script_info));
+    }
+
+    for dep in &native_deps.unknown_crates {
+        let dep_response = get_crate(&dep.crate_id)?;
+        let dep_version = matching_version(&dep.crate_id, &dep_response, &dep.req)?;
+
+        build_script_infos.push((
+            dep.crate_id.clone(),
+            analyze_build_script(&dep.crate_id, &dep_version.num)?,
+        ));
+    }
+
+    Ok(finish_crate_native_deps(
+        crate_name,
+        native_deps,
+        build_script_infos,
+    ))
+}
+
+/// Like [crate_info](crate::crates::crate_info), but queries crates.io with `client` without
+/// blocking
+pub(super) fn crate_info_async(
+    client: &reqwest::r#async::Client,
+    crate_name: &str,
+    features: &CrateFeatures,
+) -> BoxFuture<PkgInfo> {
+    let client = client.clone();
+    let crate_name = crate_name.to_string();
+    let features = features.clone();
+
+    Box::new(
+        get_crate_async(&client, &crate_name).and_then(move |crate_response| {
+            debug!("crates.io query result: {:?}", crate_response);
+
+            let distfile_url = Registries::get().download_url(
+                &crate_download_url(&crate_name)
+                    .replace("${version}", &crate_response.crate_data.max_version),
+            );
+
+            let distfile_info =
+                get_async(&client, PkgType::Crate, &distfile_url).and_then(|response| {
+                    inspect_distfile_source(Cursor::new(response.error_for_status()?.body))
+                });
+
+            let native_deps = native_deps_async(&client, &crate_name, &crate_response, features);
+            let crate_meta = crate_meta(crate_response);
+
+            native_deps
+                .join(distfile_info)
+                .and_then(move |(native_deps, distfile_info)| {
+                    let mut build_script_infos: Vec<BoxFuture<(String, BuildScriptInfo)>> =
+                        Vec::new();
+
+                    // We've downloaded the crate already, no need to do that again for its
+                    // build script
+                    if native_deps.own_build_script {
+                        build_script_infos.push(Box::new(future::ok((
+                            crate_name.clone(),
+                            distfile_build_script_info(&crate_name, &distfile_info),
+                        ))));
+                    }
+
+                    build_script_infos.extend(
+                        native_deps
+                            .unknown_crates
+                            .iter()
+                            .map(|dep| analyze_build_script_async(&client, dep)),
+                    );
+
+                    future::join_all(build_script_infos).map(move |build_script_infos| {
+                        let crate_deps =
+                            finish_crate_native_deps(&crate_name, native_deps, build_script_infos);
+
+                        crate_pkg_info(&crate_name, crate_meta, crate_deps, distfile_info)
+                    })
+                })
+        }),
+    )
+}
+
+/// Gets what crates.io knows about the crate without blocking
+fn get_crate_async(
+    client: &reqwest::r#async::Client,
+    crate_name: &str,
+) -> BoxFuture<CrateResponse> {
+    get_json_async(
+        client,
+        PkgType::Crate,
+        &crate_api_url(crate_name),
+        crate_not_found(crate_name),
+    )
+}
+
+/// Determines which native dependencies of the latest version of the crate built with `features`
+/// are in the data tables without blocking, see
+/// [crate_native_deps](crate::helpers::crate_native_deps). `crate_response` is what crates.io
+/// told us about the crate.
+fn native_deps_async(
+    client: &reqwest::r#async::Client,
+    crate_name: &str,
+    crate_response: &CrateResponse,
+    features: CrateFeatures,
+) -> BoxFuture<CrateNativeDeps> {
+    let crate_name = crate_name.to_string();
+    let latest_version = match latest_version(&crate_name, crate_response) {
+        Ok(latest_version) => latest_version,
+        Err(e) => return Box::new(future::err(e)),
     };
+    let crate_features = latest_version.features.clone();
+
+    Box::new(
+        get_json_async::<CrateDependenciesResponse>(
+            client,
+            PkgType::Crate,
+            &crate_deps_url(&crate_name, &latest_version.num),
+            crate_deps_not_found(&crate_name),
+        )
+        .and_then(move |response| {
+            crate_native_deps(
+                &crate_name,
+                &response.dependencies,
+                &crate_features,
+                Some(&features),
+            )
+        }),
+    )
+}
+
+/// Like [analyze_build_script](crate::crates::analyze_build_script) for the latest version of the
+/// dependency `dep` that matches its version requirement, but without blocking
+fn analyze_build_script_async(
+    client: &reqwest::r#async::Client,
+    dep: &CrateDependency,
+) -> BoxFuture<(String, BuildScriptInfo)> {
+    let client = client.clone();
+    let dep = dep.clone();
 
-    let crate_deps = check_native_deps(crate_name, PkgType::Crate)?;
+    Box::new(
+        get_crate_async(&client, &dep.crate_id).and_then(move |crate_response| {
+            let url = match matching_version(&dep.crate_id, &crate_response, &dep.req) {
+                Ok(version) => Registries::get().download_url(
+                    &crate_download_url(&dep.crate_id).replace("${version}", &version.num),
+                ),
+                Err(e) => return Either::A(future::err(e)),
+            };
 
-    debug!("crates.io query result: {:?}", query_result);
+            Either::B(
+                get_async(&client, PkgType::Crate, &url).and_then(move |response| {
+                    let build_script_info = read_build_script_info(
+                        &dep.crate_id,
+                        Cursor::new(response.error_for_status()?.body),
+                    )?;
 
-    let download_url = format!(
+                    Ok((dep.crate_id, build_script_info))
+                }),
+            )
+        }),
+    )
+}
+
+/// The URL of the distfile of the crate, with `${version}` in place of its version
+pub(super) fn crate_download_url(crate_name: &str) -> String {
+    format!(
         "https://static.crates.io/crates/{name}/{name}-${{version}}.crate",
         name = &crate_name,
-    );
+    )
+}
+
+/// Puts together the PkgInfo of the crate from what crates.io told us about it, its native
+/// dependencies and what we found in its distfile
+pub(super) fn crate_pkg_info(
+    crate_name: &str,
+    crate_meta: CrateMeta,
+    mut crate_deps: Option<Dependencies>,
+    distfile_info: DistfileInfo,
+) -> PkgInfo {
+    let download_url = crate_download_url(crate_name);
 
-    let license_query = query_result.license.unwrap_or_default();
+    let license_query = crate_meta.license.unwrap_or_default();
 
+    // Unknown identifiers are warned about once we write the template, so we don't have to
+    // do that here
     let license = if license_query.is_empty() {
         None
-    } else if license_query.contains("OR") {
-        Some(
-            license_query
-                .replace("OR", "")
-                .split_whitespace()
-                .map(|x| x.to_string())
-                .collect::<Vec<_>>(),
-        )
     } else {
         Some(
-            license_query
-                .replace("/", " ")
-                .split_whitespace()
-                .map(|x| x.to_string())
-                .collect::<Vec<_>>(),
+            parse_license_expr(&license_query)
+                .map(|x| x.to_void_licenses())
+                .unwrap_or_else(|_| vec![license_query]),
         )
     };
 
+    let root_manifest = distfile_info
+        .manifests
+        .get("")
+        .map(|x| x.as_str())
+        .unwrap_or_default();
+
+    let is_c_lib = is_cargo_c_lib(root_manifest);
+
+    let mut targets = crate_targets(root_manifest, &distfile_info.files, "");
+    let mut make_install_args = None;
+
+    // The binary of a workspace might be in one of its members, which we have to tell
+    // `cargo install` about
+    if targets.bins.is_empty() {
+        let members = workspace_members(root_manifest, &distfile_info.manifests);
+
+        let bin_members = members
+            .iter()
+            .map(|x| {
+                (
+                    x,
+                    crate_targets(&distfile_info.manifests[*x], &distfile_info.files, x),
+                )
+            })
+            .filter(|(_, x)| !x.bins.is_empty())
+            .collect::<Vec<_>>();
+
+        match bin_members.as_slice() {
+            [(member, member_targets)] => {
+                debug!(
+                    "Binaries {:?} of crate {} are in workspace member {}",
+                    member_targets.bins, crate_name, member
+                );
+
+                make_install_args = Some(format!("--path {}", member));
+                targets.bins = member_targets.bins.clone();
+            }
+            [] => {}
+            _ => warn!(
+                "Multiple members of the workspace of crate {} provide binaries, please set make_install_args=\"--path MEMBER\" in the template yourself!",
+                crate_name
+            ),
+        }
+    }
+
+    let is_binary = !targets.bins.is_empty();
+
+    if !is_binary && !is_c_lib {
+        warn!(
+            "Crate {} only provides a library! Library crates are rarely packaged in Void, you probably want to package the crates using it instead.",
+            crate_name
+        );
+    }
+
+    let mut subpackages = Vec::new();
+
+    // C libraries written in Rust are built and installed with cargo-c and ship headers, so they
+    // need a -devel subpackage
+    if is_c_lib {
+        debug!("Crate {} is a C library built with cargo-c", crate_name);
+
+        let dependencies = crate_deps.get_or_insert_with(Dependencies::default);
+        dependencies
+            .host
+            .get_or_insert_with(Vec::new)
+            .push("cargo-c".to_string());
+
+        subpackages.push(SubPackage {
+            suffix: "devel".to_string(),
+            short_desc: "development files".to_string(),
+            depends: vec!["${sourcepkg}>=${version}_${revision}".to_string()],
+            noarch: false,
+            files: vec![
+                "usr/include".to_string(),
+                "usr/lib/pkgconfig".to_string(),
+                "usr/lib/*.a".to_string(),
+                "usr/lib/*.so".to_string(),
+            ],
+        });
+    }
+
     let pkg_info = PkgInfo {
-        pkg_name: format!("rust-{}", &crate_name),
-        // gen_checksum can't replace ${version} itself, so we have to do it here
-        sha: gen_checksum(&download_url.replace("${version}", &query_result.max_version))?,
-        version: query_result.max_version,
-        description: query_result.description,
-        homepage: query_result
+        // Packages of binaries are named after them, not after the language they're written in
+        pkg_name: if is_binary {
+            crate_name.to_string()
+        } else {
+            format!("rust-{}", &crate_name)
+        },
+        sha: distfile_info.sha,
+        version: crate_meta.max_version,
+        description: crate_meta.description,
+        homepage: crate_meta
             .homepage
             .unwrap_or_else(|| format!("https://crates.io/crates/{}", &crate_name)),
         license,
         dependencies: crate_deps,
         download_url: Some(download_url),
+        native: true,
+        license_files: distfile_info.license_files,
+        subpackages,
+        make_install_args,
+        wrksrc: None,
     };
 
     debug!("All pkg related info: {:?}", pkg_info);
 
-    Ok(pkg_info)
+    pkg_info
+}
+
+/// Gets the dependencies of version `version` of the crate
+///
+/// # Errors
+///
+/// * Errors out if crates.io can't be reached
+/// * Errors out if the crate or its version can't be found on crates.io
+pub(super) fn get_crate_deps(
+    crate_name: &str,
+    version: &str,
+) -> Result<Vec<CrateDependency>, Error> {
+    Ok(get_json::<CrateDependenciesResponse>(
+        &crate_deps_url(crate_name, version),
+        crate_deps_not_found(&crate_name),
+    )?
+    .dependencies)
+}
+
+/// Gets the latest version of the crate
+///
+/// # Errors
+///
+/// * Errors out if crates.io can't be reached
+/// * Errors out if the crate can't be found on crates.io
+pub(super) fn get_crate_version(crate_name: &str) -> Result<String, Error> {
+    let crate_response = get_crate(crate_name)?;
+
+    Ok(latest_version(crate_name, &crate_response)?.num.clone())
+}
+
+/// Searches crates.io for crates matching `query`, returning at most `limit` of them
+///
+/// # Errors
+///
+/// * Errors out if crates.io can't be reached
+pub(super) fn search_crates(query: &str, limit: usize) -> Result<Vec<SearchResult>, Error> {
+    let per_page = limit.to_string();
+    let url = reqwest::Url::parse_with_params(
+        &Registries::get().api_url(PkgType::Crate, "crates"),
+        &[("q", query), ("per_page", per_page.as_str())],
+    )?;
+
+    let response: CrateSearchResponse = get_ok(url.as_str())?.json()?;
+
+    Ok(response
+        .crates
+        .into_iter()
+        .map(|x| SearchResult {
+            name: x.name,
+            version: x.max_version,
+            description: x.description.map(|x| x.trim().to_string()),
+            pkg_type: PkgType::Crate,
+            template_exists: false,
+        })
+        .collect())
+}
+
+/// Downloads version `version` of the crate and looks for hints of native dependencies in its
+/// `Cargo.toml` and build script.
+///
+/// # Errors
+///
+/// * Errors out if the crate can't be downloaded
+/// * Errors out if the downloaded crate isn't a valid tarball
+pub(super) fn analyze_build_script(
+    crate_name: &str,
+    version: &str,
+) -> Result<BuildScriptInfo, Error> {
+    let url = crate_download_url(crate_name).replace("${version}", version);
+
+    read_build_script_info(crate_name, download(&Registries::get().download_url(&url))?)
+}
+
+/// Looks for hints of native dependencies in the `Cargo.toml` and build script of the crate
+/// read from `source`, see [analyze_build_script](crate::crates::analyze_build_script)
+///
+/// # Errors
+///
+/// * Errors out if `source` isn't a valid crate
+pub(super) fn read_build_script_info<R: Read>(
+    crate_name: &str,
+    source: R,
+) -> Result<BuildScriptInfo, Error> {
+    let mut crate_archive = tar::Archive::new(GzDecoder::new(source));
+    let invalid_crate = |e: std::io::Error| {
+        ProviderError::parse("The crate isn't a valid tarball")
+            .with_pkg(PkgType::Crate, crate_name)
+            .with_source(e)
+    };
+
+    let mut cargo_toml = String::new();
+    let mut sources = HashMap::new();
+
+    for entry in crate_archive.entries().map_err(invalid_crate)? {
+        let mut entry = entry.map_err(invalid_crate)?;
+
+        // Strip the leading `{name}-{version}/` directory
+        let path = entry
+            .path()
+            .map_err(invalid_crate)?
+            .components()
+            .skip(1)
+            .collect::<PathBuf>()
+            .to_string_lossy()
+            .to_string();
+
+        if path == "Cargo.toml" {
+            entry
+                .read_to_string(&mut cargo_toml)
+                .map_err(invalid_crate)?;
+        } else if is_build_script_candidate(&path) {
+            // We don't know where the build script is until we've read Cargo.toml, so we
+            // keep everything that might be it.
+            let mut source = String::new();
+            if entry.read_to_string(&mut source).is_ok() {
+                sources.insert(path, source);
+            }
+        }
+    }
+
+    let build_script = sources
+        .get(&build_script_path(&cargo_toml))
+        .map(|x| x.as_str())
+        .unwrap_or_default();
+
+    let build_script_info = parse_build_script(&cargo_toml, build_script);
+
+    debug!(
+        "Build script info of crate {}: {:?}",
+        crate_name, build_script_info
+    );
+
+    Ok(build_script_info)
+}
+
+/// Looks for hints of native dependencies in the `Cargo.toml` and build script of the crate whose
+/// distfile we've inspected already, see [analyze_build_script](crate::crates::analyze_build_script)
+pub(super) fn distfile_build_script_info(
+    crate_name: &str,
+    distfile_info: &DistfileInfo,
+) -> BuildScriptInfo {
+    let cargo_toml = distfile_info
+        .manifests
+        .get("")
+        .map(|x| x.as_str())
+        .unwrap_or_default();
+
+    let build_script = distfile_info
+        .build_scripts
+        .get(&build_script_path(cargo_toml))
+        .map(|x| x.as_str())
+        .unwrap_or_default();
+
+    let build_script_info = parse_build_script(cargo_toml, build_script);
+
+    debug!(
+        "Build script info of crate {}: {:?}",
+        crate_name, build_script_info
+    );
+
+    build_script_info
+}
+
+/// Checks if the file at `path` (relative to the top level of a crate) might be its build script,
+/// i.e. it's `src/build.rs` or a `.rs` file outside of the directories of the crate's targets
+pub(super) fn is_build_script_candidate(path: &str) -> bool {
+    path.ends_with(".rs")
+        && (path == "src/build.rs"
+            || !["src/", "tests/", "examples/", "benches/"]
+                .iter()
+                .any(|x| path.starts_with(x)))
 }
 
-// Below you can see how this function could look if we were to figure out recursive deps
-// of crates. The problem is that crates.io doesn't tell us recursive deps, so we'd have
-// to iterate over some 50-200 packages (and there aren't many crates with less than 100
-// deps, especially for binaries), which would mean 100-400 API calls (since crates_io_api
-// queries the package once and then the latest version), which is UBER SLOW!
-//pub(super) fn get_crate_deps(crate_name: &str, done_deps: Option<Vec<crates_io_api::Dependency>>) -> Result<Vec<crates_io_api::Dependency>, Error> {
-pub(super) fn get_crate_deps(crate_name: &str) -> Result<Vec<crates_io_api::Dependency>, Error> {
-    let client = crates_io_api::SyncClient::new();
+/// Determines the path of the crate's build script from its `Cargo.toml`
+fn build_script_path(cargo_toml: &str) -> String {
+    toml::from_str::<toml::Value>(cargo_toml)
+        .ok()
+        .and_then(|x| {
+            x.get("package")
+                .and_then(|x| x.get("build"))
+                .and_then(|x| x.as_str())
+                .map(|x| x.to_string())
+        })
+        .unwrap_or_else(|| "build.rs".to_string())
+}
+
+/// Looks for hints of native dependencies in a crate's `Cargo.toml` and build script, e.g.
+/// `links = "z"` or `pkg_config::probe_library("zlib")`
+pub(super) fn parse_build_script(cargo_toml: &str, build_script: &str) -> BuildScriptInfo {
+    let manifest = toml::from_str::<toml::Value>(cargo_toml).ok();
+
+    let links = manifest
+        .as_ref()
+        .and_then(|x| x.get("package"))
+        .and_then(|x| x.get("links"))
+        .and_then(|x| x.as_str())
+        .map(|x| x.to_string());
 
-    let query_result = client.get_crate(crate_name)?;
+    let build_deps = manifest
+        .as_ref()
+        .and_then(|x| x.get("build-dependencies"))
+        .and_then(|x| x.as_table());
 
-    let latest_version = &query_result.versions[0].num;
+    let has_build_dep = |name: &str| build_deps.map_or(false, |x| x.contains_key(name));
 
-    //let mut deps = done_deps.clone().unwrap_or(client.crate_dependencies(crate_name, &latest_version)?);
+    let mut pkg_config = Vec::new();
+
+    for pattern in &["probe_library(", ".probe("] {
+        for (index, _) in build_script.match_indices(pattern) {
+            let args = build_script[index + pattern.len()..].trim_start();
+
+            // We can only figure out the library if it's passed as a string literal
+            if args.starts_with('"') {
+                if let Some(library) = args[1..].split('"').next() {
+                    if !library.is_empty() && !pkg_config.contains(&library.to_string()) {
+                        pkg_config.push(library.to_string());
+                    }
+                }
+            }
+        }
+    }
 
-    Ok(client.crate_dependencies(crate_name, &latest_version)?)
+    BuildScriptInfo {
+        links,
+        pkg_config,
+        uses_cc: has_build_dep("cc")
+            || has_build_dep("gcc")
+            || build_script.contains("cc::Build")
+            || build_script.contains("gcc::Build"),
+        uses_cmake: has_build_dep("cmake") || build_script.contains("cmake::"),
+        uses_bindgen: has_build_dep("bindgen") || build_script.contains("bindgen::"),
+    }
 }
 
-/*
-    for x in deps.clone() {
-        if done_deps.as_ref().unwrap_or(&Vec::new()).contains(&x) {
+/// Checks if the crate's `Cargo.toml` describes a C library built with
+/// [cargo-c](https://github.com/lu-zero/cargo-c), i.e. its `crate-type` contains `cdylib` or
+/// `staticlib` and it has a `[package.metadata.capi]` section
+pub(super) fn is_cargo_c_lib(cargo_toml: &str) -> bool {
+    let manifest = match toml::from_str::<toml::Value>(cargo_toml) {
+        Ok(manifest) => manifest,
+        Err(_) => return false,
+    };
+
+    let is_c_lib = manifest
+        .get("lib")
+        .and_then(|x| x.get("crate-type"))
+        .and_then(|x| x.as_array())
+        .map_or(false, |x| {
+            x.iter()
+                .any(|x| x.as_str() == Some("cdylib") || x.as_str() == Some("staticlib"))
+        });
+
+    let uses_cargo_c = manifest
+        .get("package")
+        .and_then(|x| x.get("metadata"))
+        .and_then(|x| x.get("capi"))
+        .is_some();
+
+    is_c_lib && uses_cargo_c
+}
+
+/// Determines the targets of the crate in the directory `crate_dir` (relative to the top level,
+/// empty for the top level itself) from its `Cargo.toml` and `files`. Besides the explicitly
+/// declared targets, Cargo automatically discovers `src/lib.rs`, `src/main.rs`, `src/bin/*.rs`
+/// and `src/bin/*/main.rs`.
+pub(super) fn crate_targets(cargo_toml: &str, files: &[String], crate_dir: &str) -> CrateTargets {
+    let manifest = toml::from_str::<toml::Value>(cargo_toml).ok();
+    let package = manifest.as_ref().and_then(|x| x.get("package"));
+
+    let package_name = package
+        .and_then(|x| x.get("name"))
+        .and_then(|x| x.as_str())
+        .unwrap_or_default();
+
+    let autobins = package
+        .and_then(|x| x.get("autobins"))
+        .and_then(|x| x.as_bool())
+        .unwrap_or(true);
+
+    let prefix = if crate_dir.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", crate_dir)
+    };
+
+    let crate_files = files
+        .iter()
+        .filter_map(|x| {
+            if x.starts_with(&prefix) {
+                Some(&x[prefix.len()..])
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut bins = manifest
+        .as_ref()
+        .and_then(|x| x.get("bin"))
+        .and_then(|x| x.as_array())
+        .map(|x| {
+            x.iter()
+                .filter_map(|x| x.get("name").and_then(|x| x.as_str()))
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    if autobins {
+        for file in &crate_files {
+            let bin_name = if *file == "src/main.rs" {
+                Some(package_name)
+            } else if file.starts_with("src/bin/") {
+                let bin_path = &file["src/bin/".len()..];
+
+                match bin_path.split('/').collect::<Vec<_>>().as_slice() {
+                    [file_name] if file_name.ends_with(".rs") => {
+                        Some(file_name.trim_end_matches(".rs"))
+                    }
+                    [dir_name, "main.rs"] => Some(*dir_name),
+                    _ => None,
+                }
+            } else {
+                None
+            };
+
+            if let Some(bin_name) = bin_name {
+                if !bin_name.is_empty() && !bins.iter().any(|x| x == bin_name) {
+                    bins.push(bin_name.to_string());
+                }
+            }
+        }
+    }
+
+    CrateTargets {
+        bins,
+        lib: manifest.as_ref().and_then(|x| x.get("lib")).is_some()
+            || crate_files.contains(&"src/lib.rs"),
+    }
+}
+
+/// Returns the directories of the members of the workspace defined in the top-level `Cargo.toml`
+/// which are in `manifests` (see [inspect_archive](crate::helpers::inspect_archive)). Members may
+/// be globs like `crates/*`.
+pub(super) fn workspace_members<'a>(
+    cargo_toml: &str,
+    manifests: &'a BTreeMap<String, String>,
+) -> Vec<&'a String> {
+    let patterns = toml::from_str::<toml::Value>(cargo_toml)
+        .ok()
+        .and_then(|x| {
+            x.get("workspace")
+                .and_then(|x| x.get("members"))
+                .and_then(|x| x.as_array())
+                .map(|x| {
+                    x.iter()
+                        .filter_map(|x| x.as_str())
+                        .map(|x| x.trim_end_matches('/').to_string())
+                        .collect::<Vec<_>>()
+                })
+        })
+        .unwrap_or_default();
+
+    let matches = |pattern: &str, dir: &str| {
+        let pattern = pattern.split('/').collect::<Vec<_>>();
+        let dir = dir.split('/').collect::<Vec<_>>();
+
+        pattern.len() == dir.len()
+            && pattern
+                .iter()
+                .zip(dir.iter())
+                .all(|(pattern, dir)| *pattern == "*" || pattern == dir)
+    };
+
+    manifests
+        .keys()
+        .filter(|x| !x.is_empty() && patterns.iter().any(|pattern| matches(pattern, x)))
+        .collect()
+}
+
+/// Gets the features of the latest version of the crate
+///
+/// # Errors
+///
+/// * Errors out if crates.io can't be reached
+/// * Errors out if the crate can't be found on crates.io
+pub(super) fn get_crate_features(crate_name: &str) -> Result<HashMap<String, Vec<String>>, Error> {
+    let crate_response = get_crate(crate_name)?;
+
+    Ok(latest_version(crate_name, &crate_response)?
+        .features
+        .clone())
+}
+
+/// Determines which features (and as such optional dependencies) of a crate with the features
+/// `crate_features` are enabled if it's built with `features`
+pub(super) fn enabled_features(
+    crate_features: &HashMap<String, Vec<String>>,
+    features: &CrateFeatures,
+) -> HashSet<String> {
+    let mut pending = features.features.clone();
+
+    if !features.no_default_features && crate_features.contains_key("default") {
+        pending.push("default".to_string());
+    }
+
+    let mut enabled = HashSet::new();
+
+    while let Some(feature) = pending.pop() {
+        // `dep?/feature` only enables `feature` of `dep` if `dep` is enabled by something else
+        if feature.contains("?/") {
             continue;
         }
-        deps.append(&mut get_crate_deps(&x.crate_id, Some(deps.clone()))?);
+
+        // `dep/feature` enables the optional dependency `dep` (and its feature `feature`, which
+        // doesn't matter to us). `dep:dep` is the explicit syntax for enabling `dep`.
+        let feature = feature
+            .trim_start_matches("dep:")
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        if enabled.insert(feature.clone()) {
+            if let Some(sub_features) = crate_features.get(&feature) {
+                pending.extend(sub_features.iter().cloned());
+            }
+        }
     }
 
-    Ok(deps)
+    enabled
 }
-*/
@@ -0,0 +1,602 @@
+//This file is part of tmplgen
+//
+//tmplgen is free software: you can redistribute it and/or modify
+//it under the terms of the GNU General Public License as published by
+//the Free Software Foundation, either version 3 of the License, or
+//(at your option) any later version.
+//
+//tmplgen is distributed in the hope that it will be useful,
+//but WITHOUT ANY WARRANTY; without even the implied warranty of
+//MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//GNU General Public License for more details.
+//
+//You should have received a copy of the GNU General Public License
+//along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::errors::Error;
+use crate::gems::{compare_gem_versions, gem_requirement_satisfied};
+use crate::helpers::dedup_vec;
+use crate::perldist::numify_perl_version;
+use crate::types::*;
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env::var_os;
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    static ref KNOWN_DATA: KnownData =
+        KnownData::new(load_data()).expect("The built-in data tables are invalid!");
+}
+
+/// Returns the directories of the user-supplied configuration (data tables and template
+/// skeletons), in order of increasing precedence:
+///
+/// * `$XDG_CONFIG_HOME/tmplgen` (or `~/.config/tmplgen`)
+/// * `$XBPS_DISTDIR/.tmplgen`, for configuration specific to one void-packages checkout
+pub(super) fn config_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    let config_dir = var_os("XDG_CONFIG_HOME")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| var_os("HOME").map(|x| Path::new(&x).join(".config")));
+
+    if let Some(config_dir) = config_dir {
+        dirs.push(config_dir.join("tmplgen"));
+    }
+
+    if let Some(xdist_dir) = var_os("XBPS_DISTDIR") {
+        let xdist_dir = xdist_dir.to_string_lossy().to_string();
+
+        let xdist_dir = match var_os("HOME") {
+            Some(home_dir) => xdist_dir.replace("~", &home_dir.to_string_lossy()),
+            None => xdist_dir,
+        };
+
+        dirs.push(Path::new(&xdist_dir).join(".tmplgen"));
+    }
+
+    dirs
+}
+
+/// Returns the paths of the user-supplied data tables, `data.toml` in each of the
+/// [config_dirs](crate::data::config_dirs), in order of increasing precedence
+pub(super) fn data_paths() -> Vec<PathBuf> {
+    config_dirs()
+        .into_iter()
+        .map(|x| x.join("data.toml"))
+        .collect()
+}
+
+/// Reads the data tables at `path`, returns None if the file doesn't exist
+///
+/// # Errors
+///
+/// * Errors out if the file can't be read
+/// * Errors out if the file isn't valid TOML or doesn't match the layout of `data.toml`
+fn read_data_file(path: &Path) -> Result<Option<TomlData>, Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    debug!("Reading data tables from {}", path.display());
+
+    let data_string = std::fs::read_to_string(path)?;
+
+    let data: TomlData = toml::from_str(&data_string)
+        .map_err(|e| Error::Data(format!("{}: {}", path.display(), e)))?;
+
+    validate_data(&data).map_err(|e| Error::Data(format!("{}: {}", path.display(), e)))?;
+
+    Ok(Some(data))
+}
+
+/// Checks that the data tables don't contain empty names and that license corrections are
+/// either unambiguous or ambiguous, but not both.
+///
+/// # Errors
+///
+/// * Errors out with a description of the first invalid entry
+fn validate_data(data: &TomlData) -> Result<(), Error> {
+    let native_deps = data
+        .native_deps
+        .rust
+        .iter()
+        .chain(data.native_deps.perl.iter())
+        .chain(data.native_deps.ruby.iter())
+        .chain(data.pkg_config.iter());
+
+    for native_dep in native_deps {
+        if native_dep.name.is_empty() || native_dep.dep.is_empty() {
+            return Err(Error::Data(format!(
+                "Native dependency '{}' = '{}' mustn't be empty!",
+                native_dep.name, native_dep.dep
+            )));
+        }
+    }
+
+    let licenses = data
+        .licenses
+        .common
+        .iter()
+        .chain(data.licenses.perl.iter())
+        .chain(data.licenses.ruby.iter());
+
+    for license in licenses {
+        if license.is.is_empty() {
+            return Err(Error::Data("License names mustn't be empty!".to_string()));
+        }
+
+        match &license.should {
+            Some(should) if !license.candidates.is_empty() => {
+                return Err(Error::Data(format!(
+                    "License '{}' can't be corrected to {} and have candidates at the same time!",
+                    license.is, should
+                )));
+            }
+            Some(should) if should.split(", ").any(|x| x.is_empty()) => {
+                return Err(Error::Data(format!(
+                    "License '{}' is corrected to an empty license!",
+                    license.is
+                )));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Merges the entries of `overlay` into `table`. Entries of `overlay` replace all entries of
+/// `table` with the same key and take precedence over the remaining ones.
+fn merge_table<T, F>(table: &mut Vec<T>, overlay: Vec<T>, key: F)
+where
+    F: Fn(&T) -> &str,
+{
+    table.retain(|x| !overlay.iter().any(|y| key(y) == key(x)));
+
+    let mut merged = overlay;
+    merged.append(table);
+
+    *table = merged;
+}
+
+/// Merges the data tables `overlay` over `data`. Built-in packages are added to the existing ones,
+/// license corrections, native dependencies and pkg-config mappings replace the existing ones for
+/// the same name.
+pub(super) fn merge_data(data: &mut TomlData, overlay: TomlData) {
+    data.builtin.perl.extend(overlay.builtin.perl);
+    dedup_vec(&mut data.builtin.perl);
+    data.builtin.ruby.extend(overlay.builtin.ruby);
+    dedup_vec(&mut data.builtin.ruby);
+    data.builtin.perl_dists.extend(overlay.builtin.perl_dists);
+
+    merge_table(&mut data.licenses.common, overlay.licenses.common, |x| {
+        &x.is
+    });
+    merge_table(&mut data.licenses.perl, overlay.licenses.perl, |x| &x.is);
+    merge_table(&mut data.licenses.ruby, overlay.licenses.ruby, |x| &x.is);
+
+    merge_table(&mut data.native_deps.rust, overlay.native_deps.rust, |x| {
+        &x.name
+    });
+    merge_table(&mut data.native_deps.perl, overlay.native_deps.perl, |x| {
+        &x.name
+    });
+    merge_table(&mut data.native_deps.ruby, overlay.native_deps.ruby, |x| {
+        &x.name
+    });
+
+    merge_table(&mut data.pkg_config, overlay.pkg_config, |x| &x.name);
+}
+
+/// Returns the built-in data tables merged with the user-supplied ones (see `data_paths`)
+///
+/// # Errors
+///
+/// * Errors out if one of the user-supplied files can't be read or is invalid
+pub(super) fn try_load_data() -> Result<TomlData, Error> {
+    let mut data: TomlData = toml::from_str(include_str!("data.toml")).unwrap();
+
+    for path in data_paths() {
+        if let Some(overlay) = read_data_file(&path)? {
+            merge_data(&mut data, overlay);
+        }
+    }
+
+    Ok(data)
+}
+
+/// Like `try_load_data`, but warns about and ignores invalid user-supplied files
+pub(super) fn load_data() -> TomlData {
+    let mut data: TomlData = toml::from_str(include_str!("data.toml")).unwrap();
+
+    for path in data_paths() {
+        match read_data_file(&path) {
+            Ok(Some(overlay)) => merge_data(&mut data, overlay),
+            Ok(None) => {}
+            Err(e) => warn!("Ignoring invalid data tables: {}", e),
+        }
+    }
+
+    data
+}
+
+/// Makes free-form license names comparable by lowercasing them and collapsing whitespace
+fn license_key(license: &str) -> String {
+    license
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Indexes a native dependency table by the name of the package needing the native dependencies
+fn index_native_deps(native_deps: Vec<NativeDeps>) -> HashMap<String, Vec<String>> {
+    let mut index: HashMap<String, Vec<String>> = HashMap::new();
+
+    for native_dep in native_deps {
+        index
+            .entry(native_dep.name)
+            .or_default()
+            .push(native_dep.dep);
+    }
+
+    index
+}
+
+/// Indexes a license table by `license_key`. Earlier entries (e.g. user-supplied ones) take
+/// precedence over later ones with the same key.
+fn index_licenses(licenses: Vec<CorrectedLicenses>) -> HashMap<String, CorrectedLicenses> {
+    let mut index = HashMap::new();
+
+    for license in licenses {
+        index.entry(license_key(&license.is)).or_insert(license);
+    }
+
+    index
+}
+
+/// Resolves `version` to the exactly matching key of `versions` or, if `version` is a release
+/// series like `5.36`, to the latest version of that series according to `compare`
+fn resolve_version<'a, V, F>(
+    versions: &'a BTreeMap<String, V>,
+    version: &str,
+    compare: F,
+) -> Option<&'a str>
+where
+    F: Fn(&str, &str) -> Ordering,
+{
+    let series_prefix = format!("{}.", version);
+
+    versions
+        .keys()
+        .filter(|x| x.as_str() == version || x.starts_with(&series_prefix))
+        .max_by(|a, b| compare(a, b))
+        .map(|x| x.as_str())
+}
+
+/// The data tables tmplgen knows about (packages built into Perl/Ruby, license corrections and
+/// native dependencies), indexed for fast lookups. They're parsed and validated once, on first
+/// use, and include the user-supplied tables (see [data_tables](crate::data::data_tables)).
+///
+/// # Example
+///
+/// ```
+/// use libtmplgen::*;
+///
+/// let known_data = KnownData::get();
+///
+/// assert!(known_data
+///     .is_built_in("Carp", PkgType::PerlDist, None, &RuntimeVersions::default())
+///     .unwrap());
+/// assert_eq!(
+///     known_data.native_deps("openssl-sys", PkgType::Crate),
+///     &["libressl-devel".to_string()]
+/// );
+/// ```
+#[derive(Debug)]
+pub struct KnownData {
+    builtin_perl: HashSet<String>,
+    builtin_ruby: HashSet<String>,
+    perl_dists: BTreeMap<String, String>,
+    licenses_common: HashMap<String, CorrectedLicenses>,
+    licenses_perl: HashMap<String, CorrectedLicenses>,
+    licenses_ruby: HashMap<String, CorrectedLicenses>,
+    native_deps_rust: HashMap<String, Vec<String>>,
+    native_deps_perl: HashMap<String, Vec<String>>,
+    native_deps_ruby: HashMap<String, Vec<String>>,
+    native_dep_names: HashSet<String>,
+    pkg_config: HashMap<String, String>,
+    perl_core: PerlCoreList,
+    ruby_gems: RubyGemList,
+}
+
+impl KnownData {
+    /// Validates and indexes the data tables `data`
+    ///
+    /// # Errors
+    ///
+    /// * Errors out if `data` contains invalid entries
+    pub(super) fn new(data: TomlData) -> Result<Self, Error> {
+        validate_data(&data)?;
+
+        let native_dep_names = data
+            .native_deps
+            .rust
+            .iter()
+            .chain(data.native_deps.perl.iter())
+            .chain(data.native_deps.ruby.iter())
+            .map(|x| x.dep.clone())
+            .collect();
+
+        let mut pkg_config = HashMap::new();
+        for native_dep in data.pkg_config {
+            pkg_config.entry(native_dep.name).or_insert(native_dep.dep);
+        }
+
+        Ok(Self {
+            builtin_perl: data.builtin.perl.into_iter().collect(),
+            builtin_ruby: data.builtin.ruby.into_iter().collect(),
+            perl_dists: data.builtin.perl_dists,
+            licenses_common: index_licenses(data.licenses.common),
+            licenses_perl: index_licenses(data.licenses.perl),
+            licenses_ruby: index_licenses(data.licenses.ruby),
+            native_deps_rust: index_native_deps(data.native_deps.rust),
+            native_deps_perl: index_native_deps(data.native_deps.perl),
+            native_deps_ruby: index_native_deps(data.native_deps.ruby),
+            native_dep_names,
+            pkg_config,
+            perl_core: toml::from_str(include_str!("perl_corelist.toml"))
+                .map_err(|e| Error::Data(e.to_string()))?,
+            ruby_gems: toml::from_str(include_str!("ruby_gems.toml"))
+                .map_err(|e| Error::Data(e.to_string()))?,
+        })
+    }
+
+    /// Returns the shared `KnownData`, parsing the data tables on the first call. Invalid
+    /// user-supplied tables are ignored with a warning.
+    pub fn get() -> &'static Self {
+        &KNOWN_DATA
+    }
+
+    /// Checks if the package `pkg_name` is built into the Perl/Ruby version of
+    /// `runtime_versions`. If `required_version` is set, the built-in version has to satisfy
+    /// it, so dual-lived modules and default/bundled gems that are required in a newer version
+    /// than the one shipped with Perl/Ruby aren't considered built in. For perldists
+    /// `required_version` is the minimum version, for gems it's a RubyGems requirement
+    /// (e.g. `~> 1.2` or `>= 1.0, < 2`). Crates can't be built in.
+    ///
+    /// # Errors
+    ///
+    /// * Errors out if tmplgen doesn't know about the Perl/Ruby version of `runtime_versions`
+    pub fn is_built_in(
+        &self,
+        pkg_name: &str,
+        pkg_type: PkgType,
+        required_version: Option<&str>,
+        runtime_versions: &RuntimeVersions,
+    ) -> Result<bool, Error> {
+        match pkg_type {
+            PkgType::PerlDist => {
+                let dist_name = pkg_name.replace("::", "-");
+
+                if self.builtin_perl.contains(&dist_name) {
+                    return Ok(true);
+                }
+
+                // Dual-lived perldists whose name doesn't match a module are checked by the
+                // module that carries their version
+                let module = match self.perl_dists.get(&dist_name) {
+                    Some(module) => module.clone(),
+                    None => pkg_name.replace("-", "::"),
+                };
+
+                let core_version = self.perl_core_version(
+                    &module,
+                    runtime_versions.perl.as_ref().map(|x| x.as_str()),
+                )?;
+
+                Ok(match (core_version, required_version) {
+                    (Some(core_version), Some(required_version)) => {
+                        numify_perl_version(core_version) >= numify_perl_version(required_version)
+                    }
+                    (Some(_), None) => true,
+                    (None, _) => false,
+                })
+            }
+            PkgType::Gem => {
+                if self.builtin_ruby.contains(pkg_name) {
+                    return Ok(true);
+                }
+
+                let shipped_version = self.ruby_gem_version(
+                    pkg_name,
+                    runtime_versions.ruby.as_ref().map(|x| x.as_str()),
+                )?;
+
+                Ok(match (shipped_version, required_version) {
+                    (Some(shipped_version), Some(required_version)) => {
+                        gem_requirement_satisfied(shipped_version, required_version)
+                    }
+                    (Some(_), None) => true,
+                    (None, _) => false,
+                })
+            }
+            PkgType::Crate => Ok(false),
+        }
+    }
+
+    /// Returns the perl versions tmplgen knows the core modules of
+    pub fn perl_versions(&self) -> Vec<&str> {
+        self.perl_core.versions.keys().map(|x| x.as_str()).collect()
+    }
+
+    /// Resolves `perl_version` (e.g. `5.36` or `5.36.0`) to one of the known perl versions.
+    /// `None` resolves to the latest known version.
+    ///
+    /// # Errors
+    ///
+    /// * Errors out if tmplgen doesn't know about `perl_version`
+    fn resolve_perl_version(&self, perl_version: Option<&str>) -> Result<&str, Error> {
+        match perl_version {
+            Some(perl_version) => resolve_version(
+                &self.perl_core.versions,
+                perl_version.trim_start_matches('v'),
+                |a, b| {
+                    numify_perl_version(a)
+                        .partial_cmp(&numify_perl_version(b))
+                        .unwrap_or(Ordering::Equal)
+                },
+            )
+            .ok_or_else(|| Error::WrongUsage {
+                method: "set_perl_version".to_string(),
+                err: format!(
+                    "Don't know which modules perl {} ships! Known versions are: {}",
+                    perl_version,
+                    self.perl_versions().join(", ")
+                ),
+            }),
+            None => Ok(self.perl_core.default.as_str()),
+        }
+    }
+
+    /// Returns the version of `module` shipped with `perl_version` (e.g. `5.36`, `None` for
+    /// the latest known version) or None if it isn't a core module of that version. The version
+    /// is empty if the module doesn't have one.
+    ///
+    /// # Errors
+    ///
+    /// * Errors out if tmplgen doesn't know about `perl_version`
+    pub fn perl_core_version(
+        &self,
+        module: &str,
+        perl_version: Option<&str>,
+    ) -> Result<Option<&str>, Error> {
+        let perl_version = self.resolve_perl_version(perl_version)?;
+
+        Ok(self.perl_core.versions[perl_version]
+            .get(module)
+            .map(|x| x.as_str()))
+    }
+
+    /// Returns the Ruby versions tmplgen knows the default and bundled gems of
+    pub fn ruby_versions(&self) -> Vec<&str> {
+        self.ruby_gems.versions.keys().map(|x| x.as_str()).collect()
+    }
+
+    /// Resolves `ruby_version` (e.g. `3.3` or `3.3.0`) to one of the known Ruby versions.
+    /// `None` resolves to the latest known version.
+    ///
+    /// # Errors
+    ///
+    /// * Errors out if tmplgen doesn't know about `ruby_version`
+    fn resolve_ruby_version(&self, ruby_version: Option<&str>) -> Result<&str, Error> {
+        match ruby_version {
+            Some(ruby_version) => {
+                resolve_version(&self.ruby_gems.versions, ruby_version, compare_gem_versions)
+                    .ok_or_else(|| Error::WrongUsage {
+                        method: "set_ruby_version".to_string(),
+                        err: format!(
+                            "Don't know which gems Ruby {} ships! Known versions are: {}",
+                            ruby_version,
+                            self.ruby_versions().join(", ")
+                        ),
+                    })
+            }
+            None => Ok(self.ruby_gems.default.as_str()),
+        }
+    }
+
+    /// Returns the version of the gem `gem_name` shipped with `ruby_version` (e.g. `3.3`, `None`
+    /// for the latest known version), either as default or as bundled gem, or None if Ruby
+    /// doesn't ship it.
+    ///
+    /// # Errors
+    ///
+    /// * Errors out if tmplgen doesn't know about `ruby_version`
+    pub fn ruby_gem_version(
+        &self,
+        gem_name: &str,
+        ruby_version: Option<&str>,
+    ) -> Result<Option<&str>, Error> {
+        let gems = &self.ruby_gems.versions[self.resolve_ruby_version(ruby_version)?];
+
+        Ok(gems
+            .default
+            .get(gem_name)
+            .or_else(|| gems.bundled.get(gem_name))
+            .map(|x| x.as_str()))
+    }
+
+    /// Returns the correction of the license name `license` of a package of type `pkg_type`,
+    /// if there is one. Matching is case-insensitive and ignores redundant whitespace.
+    pub fn license_correction(
+        &self,
+        license: &str,
+        pkg_type: PkgType,
+    ) -> Option<&CorrectedLicenses> {
+        let license_key = license_key(license);
+
+        let vocabulary = match pkg_type {
+            PkgType::PerlDist => Some(&self.licenses_perl),
+            PkgType::Gem => Some(&self.licenses_ruby),
+            PkgType::Crate => None,
+        };
+
+        vocabulary
+            .and_then(|x| x.get(&license_key))
+            .or_else(|| self.licenses_common.get(&license_key))
+    }
+
+    /// Returns the native dependencies the package `pkg_name` of type `pkg_type` needs, e.g.
+    /// `libressl-devel` for the crate `openssl-sys`
+    pub fn native_deps(&self, pkg_name: &str, pkg_type: PkgType) -> &[String] {
+        let native_deps = match pkg_type {
+            PkgType::Crate => &self.native_deps_rust,
+            PkgType::PerlDist => &self.native_deps_perl,
+            PkgType::Gem => &self.native_deps_ruby,
+        };
+
+        native_deps
+            .get(pkg_name)
+            .map(|x| x.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Checks if `dep` is one of the known native dependencies (e.g. `libxml2-devel`)
+    pub fn is_native_dep(&self, dep: &str) -> bool {
+        self.native_dep_names.contains(dep)
+    }
+
+    /// Maps the pkg-config name (or `links` value) of a native library to the package
+    /// providing it, e.g. `libxml-2.0` to `libxml2-devel`
+    pub fn map_pkg_config(&self, library: &str) -> Option<&str> {
+        self.pkg_config
+            .get(library)
+            .or_else(|| self.pkg_config.get(&format!("lib{}", library)))
+            .map(|x| x.as_str())
+    }
+}
+
+/// Returns the effective data tables (the built-in ones merged with
+/// `$XDG_CONFIG_HOME/tmplgen/data.toml` and `$XBPS_DISTDIR/.tmplgen/data.toml`) as TOML.
+///
+/// # Errors
+///
+/// * Errors out if one of the user-supplied files can't be read or is invalid
+///
+/// # Example
+///
+/// ```
+/// use libtmplgen::*;
+///
+/// let data_tables = data_tables().unwrap();
+///
+/// assert!(data_tables.contains("[builtin]"));
+/// ```
+pub fn data_tables() -> Result<String, Error> {
+    toml::to_string(&try_load_data()?).map_err(|e| Error::Data(e.to_string()))
+}
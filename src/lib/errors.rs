@@ -1,16 +1,15 @@
+use crate::types::PkgType;
 use failure::Fail;
+use std::fmt;
+use std::sync::Arc;
 
 /// The Error enum containing all Errors that may occur when running tmplgen
-#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Fail)]
+#[derive(Clone, Debug, Eq, PartialEq, Fail)]
 pub enum Error {
     #[fail(display = "Failed to read/write the template! Error: {}", _0)]
     File(String),
-    #[fail(display = "Failed to query the crate! Error: {}", _0)]
-    Crate(String),
-    #[fail(display = "Failed to query the gem! Error: {}", _0)]
-    Gem(String),
-    #[fail(display = "Failed to query the perldist! Error: {}", _0)]
-    PerlDist(String),
+    #[fail(display = "Crate {} doesn't have a feature {}!", crate_name, feature)]
+    UnknownFeature { crate_name: String, feature: String },
     #[fail(display = "Failed to convert UTF-8 to a string! Error: {}", _0)]
     UTF8(String),
     #[fail(display = "Failed to write the template! Error: {}", _0)]
@@ -27,7 +26,7 @@ pub enum Error {
     #[fail(display = "Failed to determine XBPS_DISTDIR: {}", _0)]
     Xdist(String),
     #[fail(
-        display = "Found a package matching the specified package {}! Please explicitly choose one via the `-t` parameter!",
+        display = "Found a package matching the specified package {}! Please explicitly choose one via the `-t` parameter or `tmplgen search --pick`!",
         _0
     )]
     AmbPkg(String),
@@ -36,11 +35,6 @@ pub enum Error {
         _0
     )]
     NoSuchPkg(String),
-    #[fail(
-        display = "Failed to write checksum to the newly written template! Error: {}",
-        _0
-    )]
-    Sha(String),
     #[fail(display = "Didn't provide enough info for action {}", _0)]
     TooLittleInfo(String),
     #[fail(
@@ -51,24 +45,230 @@ pub enum Error {
     #[fail(display = "Can't run method {}! {}", method, err)]
     WrongUsage { method: String, err: String },
     #[fail(display = "{}", _0)]
-    Reqwest(String),
+    Provider(#[cause] ProviderError),
+    #[fail(display = "Failed to parse the license! Error: {}", _0)]
+    License(String),
+    #[fail(display = "Failed to load the data tables! Error: {}", _0)]
+    Data(String),
+    #[fail(display = "Failed to load the registry configuration! Error: {}", _0)]
+    Registry(String),
+    #[fail(display = "Failed to render the template skeleton! Error: {}", _0)]
+    Skeleton(String),
+    #[fail(display = "Failed to read/write JSON! Error: {}", _0)]
+    Json(String),
 }
 
-impl From<crates_io_api::Error> for Error {
-    fn from(e: crates_io_api::Error) -> Self {
-        Error::Crate(e.to_string())
+impl Error {
+    /// The kind of the error if it occurred while talking to a provider, see
+    /// [ProviderError](crate::errors::ProviderError)
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match self {
+            Error::Provider(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+
+    /// Whether the package (or a version of it) doesn't exist. It's usually not worth retrying
+    /// these.
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::NoSuchPkg(_) => true,
+            _ => self.kind() == Some(ErrorKind::NotFound),
+        }
+    }
+
+    /// Whether the registry couldn't be reached or answered with an error, so trying again later
+    /// on might work
+    pub fn is_network(&self) -> bool {
+        self.kind() == Some(ErrorKind::Network)
     }
+
+    /// Adds the package the error occurred for, unless it already names one
+    pub fn for_pkg(self, pkg_type: PkgType, pkg_name: &str) -> Self {
+        match self {
+            Error::Provider(e) => Error::Provider(e.with_pkg(pkg_type, pkg_name)),
+            e => e,
+        }
+    }
+
+    /// Adds the package `other` names, unless the error already names one
+    pub(super) fn for_pkg_of(self, other: &Error) -> Self {
+        match other {
+            Error::Provider(other) => match (other.pkg_type(), other.pkg_name()) {
+                (Some(pkg_type), Some(pkg_name)) => self.for_pkg(pkg_type, pkg_name),
+                _ => self,
+            },
+            _ => self,
+        }
+    }
+
+    /// Adds the URL the error occurred for, unless it already names one
+    pub fn for_url(self, url: &str) -> Self {
+        match self {
+            Error::Provider(e) => Error::Provider(e.with_url(url)),
+            e => e,
+        }
+    }
+}
+
+/// What went wrong in a [ProviderError](crate::errors::ProviderError)
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ErrorKind {
+    /// The registry doesn't know the package (or the version of it) we asked about
+    NotFound,
+    /// The registry couldn't be reached or answered with an error
+    Network,
+    /// The answer of the registry or the downloaded package couldn't be understood
+    Parse,
+    /// The checksum of a distfile couldn't be determined
+    Checksum,
 }
 
-impl From<rubygems_api::Error> for Error {
-    fn from(e: rubygems_api::Error) -> Self {
-        Error::Gem(e.to_string())
+/// An error that occurred while querying a provider about a package or downloading a distfile.
+/// Besides its [ErrorKind](crate::errors::ErrorKind) it carries the package, provider and URL it
+/// occurred for (as far as they're known) and the underlying error, which is available via
+/// [Fail::cause](failure::Fail::cause).
+#[derive(Clone, Debug)]
+pub struct ProviderError {
+    kind: ErrorKind,
+    message: String,
+    pkg_type: Option<PkgType>,
+    pkg_name: Option<String>,
+    url: Option<String>,
+    source: Option<Arc<dyn Fail>>,
+}
+
+impl ProviderError {
+    pub fn new<S: Into<String>>(kind: ErrorKind, message: S) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            pkg_type: None,
+            pkg_name: None,
+            url: None,
+            source: None,
+        }
+    }
+
+    pub fn not_found<S: Into<String>>(message: S) -> Self {
+        Self::new(ErrorKind::NotFound, message)
+    }
+
+    pub fn network<S: Into<String>>(message: S) -> Self {
+        Self::new(ErrorKind::Network, message)
+    }
+
+    pub fn parse<S: Into<String>>(message: S) -> Self {
+        Self::new(ErrorKind::Parse, message)
+    }
+
+    pub fn checksum<S: Into<String>>(message: S) -> Self {
+        Self::new(ErrorKind::Checksum, message)
+    }
+
+    /// Sets the package the error occurred for, unless it's already set
+    pub fn with_pkg(mut self, pkg_type: PkgType, pkg_name: &str) -> Self {
+        if self.pkg_name.is_none() {
+            self.pkg_type = Some(pkg_type);
+            self.pkg_name = Some(pkg_name.to_string());
+        }
+        self
+    }
+
+    /// Sets the provider the error occurred on, unless it's already set
+    pub fn with_pkg_type(mut self, pkg_type: PkgType) -> Self {
+        self.pkg_type = self.pkg_type.or(Some(pkg_type));
+        self
+    }
+
+    /// Sets the URL the error occurred for, unless it's already set
+    pub fn with_url(mut self, url: &str) -> Self {
+        if self.url.is_none() {
+            self.url = Some(url.to_string());
+        }
+        self
+    }
+
+    /// Sets the underlying error
+    pub fn with_source<F: Fail>(mut self, source: F) -> Self {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The provider the error occurred on
+    pub fn pkg_type(&self) -> Option<PkgType> {
+        self.pkg_type
+    }
+
+    /// The package the error occurred for
+    pub fn pkg_name(&self) -> Option<&str> {
+        self.pkg_name.as_ref().map(|x| x.as_str())
+    }
+
+    /// The URL the error occurred for
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_ref().map(|x| x.as_str())
+    }
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let provider = self.pkg_type.map(|pkg_type| match pkg_type {
+            PkgType::Crate => "crate",
+            PkgType::Gem => "gem",
+            PkgType::PerlDist => "perldist",
+        });
+
+        match (provider, &self.pkg_name) {
+            (Some(provider), Some(pkg_name)) => {
+                write!(f, "Failed to query the {} {}! ", provider, pkg_name)?
+            }
+            (Some(provider), None) => write!(f, "Failed to query the {}! ", provider)?,
+            _ => {}
+        }
+
+        write!(f, "Error: {}", self.message)?;
+
+        if let Some(source) = &self.source {
+            write!(f, ": {}", source)?;
+        }
+
+        if let Some(url) = &self.url {
+            write!(f, " (URL: {})", url)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Fail for ProviderError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.source.as_ref().map(|x| &**x)
     }
 }
 
-impl From<metacpan_api::Error> for Error {
-    fn from(e: metacpan_api::Error) -> Self {
-        Error::PerlDist(e.to_string())
+/// Errors are equal if everything but their source is, sources are compared by their message
+impl PartialEq for ProviderError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && self.message == other.message
+            && self.pkg_type == other.pkg_type
+            && self.pkg_name == other.pkg_name
+            && self.url == other.url
+            && self.source.as_ref().map(|x| x.to_string())
+                == other.source.as_ref().map(|x| x.to_string())
+    }
+}
+
+impl Eq for ProviderError {}
+
+impl From<ProviderError> for Error {
+    fn from(e: ProviderError) -> Self {
+        Error::Provider(e)
     }
 }
 
@@ -86,13 +286,22 @@ impl From<std::io::Error> for Error {
 
 impl From<reqwest::Error> for Error {
     fn from(e: reqwest::Error) -> Self {
-        Error::Reqwest(e.to_string())
+        let error = if e.is_serialization() {
+            ProviderError::parse("Failed to parse the answer")
+        } else {
+            ProviderError::network("The request failed")
+        };
+
+        match e.url().map(|x| x.to_string()) {
+            Some(url) => error.with_url(&url).with_source(e).into(),
+            None => error.with_source(e).into(),
+        }
     }
 }
 
 impl From<reqwest::UrlError> for Error {
     fn from(e: reqwest::UrlError) -> Self {
-        Error::Reqwest(e.to_string())
+        ProviderError::parse("Invalid URL").with_source(e).into()
     }
 }
 
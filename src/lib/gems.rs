@@ -13,41 +13,341 @@
 //You should have received a copy of the GNU General Public License
 //along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::errors::Error;
+use crate::data::KnownData;
+use crate::errors::{Error, ProviderError};
+use crate::helpers::*;
+use crate::http::*;
+use crate::registry::Registries;
 use crate::types::*;
-use log::debug;
+use flate2::read::GzDecoder;
+use futures::future::{self, Either, Loop};
+use futures::Future;
+use log::{debug, warn};
 use rayon::prelude::*;
-use retry::retry_exponentially;
+use std::cmp::Ordering;
+use std::io::{Cursor, Read};
 
-/// Query the rubygems.org API.
+/// Query the rubygems.org API. Dependencies on gems shipped with the Ruby version of
+/// `runtime_versions` are dropped if the shipped version satisfies the requirement.
 ///
 /// # Errors
 ///
 /// * Errors out if rubygems.org can't be reached
 /// * Errors out if the gem can't be found on rubygems.org
-pub(super) fn gem_info(gem_name: &str) -> Result<PkgInfo, Error> {
-    let client = rubygems_api::SyncClient::new();
-
-    let query_result =
-        match retry_exponentially(3, 10.0, &mut || client.gem_info(gem_name), |result| {
-            result.is_ok()
-        }) {
-            Ok(response) => response?,
-            Err(error) => return Err(Error::Gem(error.to_string())),
+/// * Errors out if tmplgen doesn't know about the Ruby version of `runtime_versions`
+pub(super) fn gem_info(
+    gem_name: &str,
+    runtime_versions: &RuntimeVersions,
+) -> Result<PkgInfo, Error> {
+    let gem_meta = gem_meta(get_json(&gem_api_url(gem_name), gem_not_found(&gem_name))?);
+
+    let version_info = gem_version_info(gem_name, &gem_meta.version)?;
+    let gem_contents = inspect_gem(gem_name, &gem_meta.version)?;
+    let native = is_native_gem(gem_name, &version_info, &gem_contents);
+
+    let source = find_gem_source(
+        gem_name,
+        &gem_meta.version,
+        gem_sources_to_inspect(gem_name, &version_info, native),
+    );
+
+    gem_pkg_info(
+        gem_name,
+        gem_meta,
+        runtime_versions,
+        native,
+        gem_contents,
+        source,
+    )
+}
+
+/// Like [gem_info](crate::gems::gem_info), but queries rubygems.org with `client` without
+/// blocking
+pub(super) fn gem_info_async(
+    client: &reqwest::r#async::Client,
+    gem_name: &str,
+    runtime_versions: &RuntimeVersions,
+) -> BoxFuture<PkgInfo> {
+    let client = client.clone();
+    let gem_name = gem_name.to_string();
+    let runtime_versions = runtime_versions.clone();
+
+    Box::new(
+        get_json_async::<GemResponse>(
+            &client,
+            PkgType::Gem,
+            &gem_api_url(&gem_name),
+            gem_not_found(&gem_name),
+        )
+        .and_then(move |query_result| {
+            let gem_meta = gem_meta(query_result);
+
+            let version_info = get_json_async::<GemVersionInfo>(
+                &client,
+                PkgType::Gem,
+                &gem_version_url(&gem_name, &gem_meta.version),
+                gem_version_not_found(&gem_name, &gem_meta.version),
+            );
+
+            let gem_contents = {
+                let gem_name = gem_name.clone();
+                let version = gem_meta.version.clone();
+
+                get_async(
+                    &client,
+                    PkgType::Gem,
+                    &gem_download_url(&gem_name, &version),
+                )
+                .and_then(move |response| {
+                    read_gem_contents(
+                        &gem_name,
+                        &version,
+                        Cursor::new(response.error_for_status()?.body),
+                    )
+                })
+            };
+
+            version_info
+                .join(gem_contents)
+                .and_then(move |(version_info, gem_contents)| {
+                    let native = is_native_gem(&gem_name, &version_info, &gem_contents);
+
+                    let source = find_gem_source_async(
+                        &client,
+                        &gem_name,
+                        &gem_meta.version,
+                        gem_sources_to_inspect(&gem_name, &version_info, native),
+                    );
+
+                    source.and_then(move |source| {
+                        gem_pkg_info(
+                            &gem_name,
+                            gem_meta,
+                            &runtime_versions,
+                            native,
+                            gem_contents,
+                            source,
+                        )
+                    })
+                })
+        }),
+    )
+}
+
+/// Picks what we need to know about the gem from the answer of rubygems.org
+fn gem_meta(gem_response: GemResponse) -> GemMeta {
+    GemMeta {
+        version: gem_response.version,
+        info: gem_response.info,
+        homepage_uri: gem_response.homepage_uri,
+        licenses: gem_response.licenses,
+        sha: gem_response.sha,
+        runtime_deps: gem_response.dependencies.runtime,
+    }
+}
+
+/// The error for a gem rubygems.org doesn't know
+fn gem_not_found(gem_name: &str) -> Error {
+    ProviderError::not_found("The gem doesn't exist on rubygems.org")
+        .with_pkg(PkgType::Gem, gem_name)
+        .into()
+}
+
+/// The error for a version of a gem rubygems.org doesn't know
+fn gem_version_not_found(gem_name: &str, version: &str) -> Error {
+    ProviderError::not_found(format!(
+        "Version {} of the gem doesn't exist on rubygems.org",
+        version
+    ))
+    .with_pkg(PkgType::Gem, gem_name)
+    .into()
+}
+
+/// The URL of the gem in the API of the configured rubygems.org registry
+fn gem_api_url(gem_name: &str) -> String {
+    Registries::get().api_url(PkgType::Gem, &format!("v1/gems/{}.json", gem_name))
+}
+
+/// The URL of version `version` of the gem in the API of the configured rubygems.org registry
+fn gem_version_url(gem_name: &str, version: &str) -> String {
+    Registries::get().api_url(
+        PkgType::Gem,
+        &format!("v2/rubygems/{}/versions/{}.json", gem_name, version),
+    )
+}
+
+/// The URL version `version` of the gem is downloaded from
+fn gem_download_url(gem_name: &str, version: &str) -> String {
+    format!(
+        "{}/{}-{}.gem",
+        Registries::get().registry(PkgType::Gem).download,
+        gem_name,
+        version
+    )
+}
+
+/// Checks if the gem has to be compiled, i.e. if it has native extensions or is prebuilt for a
+/// specific platform
+pub(super) fn is_native_gem(
+    gem_name: &str,
+    version_info: &GemVersionInfo,
+    gem_contents: &GemContents,
+) -> bool {
+    let prebuilt = !version_info.platform.is_empty() && version_info.platform != "ruby";
+
+    if prebuilt {
+        warn!(
+            "Gem {} is prebuilt for platform {}, please make sure to build it from source instead!",
+            gem_name, version_info.platform
+        );
+    }
+
+    prebuilt || !gem_contents.extensions.is_empty()
+}
+
+/// Gems with extensions are best built from their source via the gemspec build_style, but we
+/// can only do that if we know where the sources are. Returns where the sources might be
+/// downloaded from (see [gem_sources](crate::gems::gem_sources)) if the gem is `native`.
+pub(super) fn gem_sources_to_inspect(
+    gem_name: &str,
+    version_info: &GemVersionInfo,
+    native: bool,
+) -> Vec<GemSource> {
+    if !native {
+        return Vec::new();
+    }
+
+    let sources = gem_sources(version_info);
+
+    if sources.is_empty() {
+        warn!(
+            "Couldn't determine the source repository of gem {}, will build it from the gem instead!",
+            gem_name
+        );
+    }
+
+    sources
+}
+
+/// Downloads and inspects the first of `sources` that exists for version `version` of the gem.
+/// Their URLs are only guessed, so if none of them can be downloaded we build the gem from the
+/// gem instead.
+pub(super) fn find_gem_source(
+    gem_name: &str,
+    version: &str,
+    sources: Vec<GemSource>,
+) -> Option<(GemSource, DistfileInfo)> {
+    if sources.is_empty() {
+        return None;
+    }
+
+    for source in sources {
+        let url = Registries::get().download_url(&source.url.replace("${version}", version));
+
+        match get_ok(&url).and_then(|x| inspect_distfile_source(Cursor::new(x.body))) {
+            Ok(distfile_info) => return Some((source, distfile_info)),
+            Err(e) => debug!(
+                "Couldn't get the sources of gem {} from {}: {}",
+                gem_name, url, e
+            ),
+        }
+    }
+
+    warn!(
+        "Couldn't download the sources of gem {}, will build it from the gem instead!",
+        gem_name
+    );
+
+    None
+}
+
+/// Like [find_gem_source](crate::gems::find_gem_source), but downloads the sources with
+/// `client` without blocking
+pub(super) fn find_gem_source_async(
+    client: &reqwest::r#async::Client,
+    gem_name: &str,
+    version: &str,
+    sources: Vec<GemSource>,
+) -> BoxFuture<Option<(GemSource, DistfileInfo)>> {
+    if sources.is_empty() {
+        return Box::new(future::ok(None));
+    }
+
+    let client = client.clone();
+    let gem_name = gem_name.to_string();
+    let version = version.to_string();
+
+    Box::new(future::loop_fn(sources.into_iter(), move |mut sources| {
+        let source = match sources.next() {
+            Some(source) => source,
+            None => {
+                warn!(
+                    "Couldn't download the sources of gem {}, will build it from the gem instead!",
+                    gem_name
+                );
+                return Either::A(future::ok(Loop::Break(None)));
+            }
         };
 
-    let dep_vec_run = query_result
-        .dependencies
-        .runtime
-        .unwrap_or_default()
+        let url = Registries::get().download_url(&source.url.replace("${version}", &version));
+        let gem_name = gem_name.clone();
+
+        Either::B(
+            get_async(&client, PkgType::Gem, &url)
+                .and_then(|response| {
+                    inspect_distfile_source(Cursor::new(response.error_for_status()?.body))
+                })
+                .then(move |result| -> Result<_, Error> {
+                    match result {
+                        Ok(distfile_info) => Ok(Loop::Break(Some((source, distfile_info)))),
+                        Err(e) => {
+                            debug!(
+                                "Couldn't get the sources of gem {} from {}: {}",
+                                gem_name, url, e
+                            );
+                            Ok(Loop::Continue(sources))
+                        }
+                    }
+                }),
+        )
+    }))
+}
+
+/// Puts together the PkgInfo of the gem from what rubygems.org told us about it, what we found
+/// in the gem and the sources of the gem (see
+/// [gem_sources_to_inspect](crate::gems::gem_sources_to_inspect)), if we could download them.
+///
+/// # Errors
+///
+/// * Errors out if tmplgen doesn't know about the Ruby version of `runtime_versions`
+pub(super) fn gem_pkg_info(
+    gem_name: &str,
+    gem_meta: GemMeta,
+    runtime_versions: &RuntimeVersions,
+    native: bool,
+    gem_contents: GemContents,
+    source: Option<(GemSource, DistfileInfo)>,
+) -> Result<PkgInfo, Error> {
+    let dep_vec_run = gem_meta
+        .runtime_deps
         .par_iter()
-        .filter(|&x| {
-            !TmplBuilder::new(&x.name)
-                .set_type(PkgType::Gem)
-                .is_built_in()
-                .unwrap_or(false)
+        .map(|x| {
+            let built_in = KnownData::get().is_built_in(
+                &x.name,
+                PkgType::Gem,
+                Some(&x.requirements),
+                runtime_versions,
+            )?;
+
+            Ok(if built_in {
+                None
+            } else {
+                Some(parse_gem_version_req(&x))
+            })
         })
-        .map(|x| parse_gem_version_req(&x))
+        .collect::<Result<Vec<_>, Error>>()?
+        .into_iter()
+        .flatten()
         .collect::<Vec<_>>();
 
     debug!("Gem run dependencies: {:?}", &dep_vec_run);
@@ -58,21 +358,62 @@ pub(super) fn gem_info(gem_name: &str) -> Result<PkgInfo, Error> {
         Some(dep_vec_run)
     };
 
+    let mut make_vec = Vec::new();
+    let mut download_url = None;
+    let mut wrksrc = None;
+    let mut sha = gem_meta.sha;
+    let mut license_files = Some(gem_contents.license_files);
+
+    if native {
+        debug!(
+            "Gem {} has native extensions: {:?}",
+            gem_name, &gem_contents.extensions
+        );
+
+        make_vec.push("ruby-devel".to_string());
+
+        if let Some(native_deps) = check_native_deps(gem_name, PkgType::Gem, None)? {
+            make_vec.append(&mut native_deps.make.unwrap_or_default());
+        }
+
+        if let Some((source, distfile_info)) = source {
+            download_url = Some(source.url);
+            wrksrc = Some(source.wrksrc);
+            sha = distfile_info.sha;
+            license_files = distfile_info.license_files;
+        }
+    }
+
     let pkg_info = PkgInfo {
         pkg_name: format!("ruby-{}", gem_name.to_string()),
-        version: query_result.version,
-        description: query_result.info,
-        homepage: query_result
+        version: gem_meta.version,
+        description: gem_meta.info,
+        homepage: gem_meta
             .homepage_uri
             .unwrap_or_else(|| format!("https://rubygems.org/gems/{}", gem_name)),
-        license: query_result.licenses,
+        license: gem_meta.licenses,
         dependencies: Some(Dependencies {
             host: None,
-            make: None,
+            make: if make_vec.is_empty() {
+                None
+            } else {
+                Some(make_vec)
+            },
             run: gem_run_deps,
         }),
-        sha: query_result.sha,
-        download_url: None,
+        sha,
+        download_url,
+        native,
+        license_files,
+        // Both the gem and gemspec build_styles install the gem's docs into its gem directory
+        subpackages: doc_subpackage(
+            gem_contents.doc_size,
+            &["usr/lib/ruby/gems/*/gems/${pkgname#ruby-}-${version}/doc"],
+        )
+        .into_iter()
+        .collect(),
+        make_install_args: None,
+        wrksrc,
     };
 
     debug!("All pkg related info: {:?}", pkg_info);
@@ -80,6 +421,187 @@ pub(super) fn gem_info(gem_name: &str) -> Result<PkgInfo, Error> {
     Ok(pkg_info)
 }
 
+/// Query the rubygems.org API for info about version `version` of the gem that the endpoint for
+/// the gem itself doesn't tell us about (e.g. what platform it's built for)
+///
+/// # Errors
+///
+/// * Errors out if rubygems.org can't be reached
+/// * Errors out if the version of the gem can't be found on rubygems.org
+fn gem_version_info(gem_name: &str, version: &str) -> Result<GemVersionInfo, Error> {
+    get_json(
+        &gem_version_url(gem_name, version),
+        gem_version_not_found(gem_name, version),
+    )
+}
+
+/// Searches rubygems.org for gems matching `query`, returning at most `limit` of them
+///
+/// # Errors
+///
+/// * Errors out if rubygems.org can't be reached
+pub(super) fn search_gems(query: &str, limit: usize) -> Result<Vec<SearchResult>, Error> {
+    let url = reqwest::Url::parse_with_params(
+        &Registries::get().api_url(PkgType::Gem, "v1/search.json"),
+        &[("query", query)],
+    )?;
+
+    let response: Vec<GemSearchEntry> = get_ok(url.as_str())?.json()?;
+
+    Ok(response
+        .into_iter()
+        .take(limit)
+        .map(|x| SearchResult {
+            name: x.name,
+            version: x.version,
+            description: x.info.map(|x| x.trim().to_string()),
+            pkg_type: PkgType::Gem,
+            template_exists: false,
+        })
+        .collect())
+}
+
+/// Downloads version `version` of the gem and returns the `extensions` listed in its gemspec
+/// (e.g. `ext/nokogiri/extconf.rb`), the license files it contains and the size of its docs
+///
+/// # Errors
+///
+/// * Errors out if the gem can't be downloaded from rubygems.org
+/// * Errors out if the gem doesn't contain valid metadata
+pub(super) fn inspect_gem(gem_name: &str, version: &str) -> Result<GemContents, Error> {
+    read_gem_contents(
+        gem_name,
+        version,
+        download(&gem_download_url(gem_name, version))?,
+    )
+}
+
+/// Reads the gem from `source`, see [inspect_gem](crate::gems::inspect_gem)
+///
+/// # Errors
+///
+/// * Errors out if the gem doesn't contain valid metadata
+pub(super) fn read_gem_contents<R: Read>(
+    gem_name: &str,
+    version: &str,
+    source: R,
+) -> Result<GemContents, Error> {
+    // A .gem is a plain tar archive which contains the gzipped YAML gemspec as `metadata.gz`
+    // and the gem's files as `data.tar.gz`
+    let mut gem_archive = tar::Archive::new(source);
+
+    let mut metadata = None;
+    let mut contents = ArchiveContents::default();
+    let invalid_gem = || {
+        ProviderError::parse(format!("Version {} of the gem isn't a valid .gem", version))
+            .with_pkg(PkgType::Gem, gem_name)
+    };
+
+    for entry in gem_archive
+        .entries()
+        .map_err(|e| invalid_gem().with_source(e))?
+    {
+        let entry = entry.map_err(|e| invalid_gem().with_source(e))?;
+
+        let path = entry
+            .path()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if path == "metadata.gz" {
+            let mut metadata_string = String::new();
+            GzDecoder::new(entry)
+                .read_to_string(&mut metadata_string)
+                .map_err(|e| invalid_gem().with_source(e))?;
+
+            metadata = Some(metadata_string);
+        } else if path == "data.tar.gz" {
+            // The files of data.tar.gz don't have a leading directory
+            contents = inspect_archive(&mut tar::Archive::new(GzDecoder::new(entry)), 0)
+                .map_err(|e| invalid_gem().with_source(e))?;
+        }
+    }
+
+    match metadata {
+        Some(metadata) => Ok(GemContents {
+            extensions: parse_gem_extensions(&metadata),
+            license_files: contents.license_files,
+            doc_size: contents.doc_size,
+        }),
+        None => Err(ProviderError::parse(format!(
+            "Version {} of the gem doesn't contain any metadata",
+            version
+        ))
+        .with_pkg(PkgType::Gem, gem_name)
+        .into()),
+    }
+}
+
+/// Parses the `extensions` out of a gem's YAML gemspec. We only need this one list, so we
+/// just look for it instead of parsing the entire (ruby object tagged) YAML document.
+pub(super) fn parse_gem_extensions(metadata: &str) -> Vec<String> {
+    let mut lines = metadata
+        .lines()
+        .skip_while(|x| !x.starts_with("extensions:"));
+
+    match lines.next() {
+        Some(x) if x.trim_start_matches("extensions:").trim().is_empty() => lines
+            .take_while(|x| x.starts_with("- "))
+            .map(|x| x.trim_start_matches("- ").trim().to_string())
+            .collect(),
+        // Either there's no `extensions` field or it's an inline list, which YAML only uses
+        // for empty lists here (`extensions: []`)
+        _ => Vec::new(),
+    }
+}
+
+/// Determines where the source tarball of the gem might be downloaded from. We only know that
+/// for gems whose `source_code_uri` is a GitHub repository (and not e.g. a branch or a
+/// subdirectory of one). Their tags may or may not be prefixed with `v`, so both are tried.
+pub(super) fn gem_sources(version_info: &GemVersionInfo) -> Vec<GemSource> {
+    let (owner, repo) = match version_info
+        .source_code_uri
+        .as_ref()
+        .and_then(|x| github_repo(x))
+    {
+        Some(repo) => repo,
+        None => return Vec::new(),
+    };
+
+    ["v", ""]
+        .iter()
+        .map(|tag_prefix| GemSource {
+            url: format!(
+                "https://github.com/{}/{}/archive/{}${{version}}.tar.gz",
+                owner, repo, tag_prefix
+            ),
+            // GitHub names the directory in the tarball after the repository and the tag,
+            // dropping the `v`
+            wrksrc: format!("{}-${{version}}", repo),
+        })
+        .collect()
+}
+
+/// Splits a plain `https://github.com/<owner>/<repo>` URL into the owner and the repository.
+/// Returns None for any other URL, e.g. one pointing at a branch of the repository.
+pub(super) fn github_repo(url: &str) -> Option<(&str, &str)> {
+    let github = "https://github.com/";
+
+    if !url.starts_with(github) || url.contains(|c| c == '?' || c == '#') {
+        return None;
+    }
+
+    let path = url[github.len()..].trim_end_matches('/');
+    let mut components = path.trim_end_matches(".git").split('/');
+
+    match (components.next(), components.next(), components.next()) {
+        (Some(owner), Some(repo), None) if !owner.is_empty() && !repo.is_empty() => {
+            Some((owner, repo))
+        }
+        _ => None,
+    }
+}
+
 /* Can't be used right now, we'll just replace it with >=
 // Convert the ~> comparator to something useful for us.
 // The ~> comparator is meant to allow only version updates up to the first version specifier
@@ -122,8 +644,90 @@ pub fn tilde_parse(version: String) -> Option<Vec<String>> {
 }
 */
 
+/// Compares the gem versions `a` and `b` like RubyGems does: segments are compared numerically,
+/// prerelease segments (containing letters, e.g. `1.0.0.rc1`) sort before numeric ones and
+/// missing segments count as `0`.
+pub(super) fn compare_gem_versions(a: &str, b: &str) -> Ordering {
+    let segments = |version: &str| {
+        version
+            .trim()
+            .split('.')
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let a = segments(a);
+    let b = segments(b);
+
+    for i in 0..a.len().max(b.len()) {
+        let a = a.get(i).map(|x| x.as_str()).unwrap_or("0");
+        let b = b.get(i).map(|x| x.as_str()).unwrap_or("0");
+
+        let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            (Ok(_), Err(_)) => Ordering::Greater,
+            (Err(_), Ok(_)) => Ordering::Less,
+            (Err(_), Err(_)) => a.cmp(b),
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// Checks if the gem version `version` satisfies the RubyGems requirement `requirements`, e.g.
+/// `>= 1.0, < 2` or `~> 2.0.3`. A version without an operator means `=`.
+pub(super) fn gem_requirement_satisfied(version: &str, requirements: &str) -> bool {
+    requirements.split(',').all(|requirement| {
+        let requirement = requirement.trim();
+        let operator_len = requirement
+            .find(|c: char| c.is_ascii_alphanumeric())
+            .unwrap_or_else(|| requirement.len());
+        let (operator, required) = requirement.split_at(operator_len);
+        let required = required.trim();
+        let ordering = compare_gem_versions(version, required);
+
+        match operator.trim() {
+            "" | "=" => ordering == Ordering::Equal,
+            "!=" => ordering != Ordering::Equal,
+            ">" => ordering == Ordering::Greater,
+            "<" => ordering == Ordering::Less,
+            ">=" => ordering != Ordering::Less,
+            "<=" => ordering != Ordering::Greater,
+            "~>" => {
+                // ~> 2.0.3 means >= 2.0.3 and < 2.1, ~> 2.1 means >= 2.1 and < 3
+                let mut upper = required
+                    .split('.')
+                    .take_while(|x| x.parse::<u64>().is_ok())
+                    .map(|x| x.parse::<u64>().unwrap_or(0))
+                    .collect::<Vec<_>>();
+
+                if upper.len() > 1 {
+                    upper.pop();
+                }
+                if let Some(last) = upper.last_mut() {
+                    *last += 1;
+                }
+
+                let upper = upper
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(".");
+
+                ordering != Ordering::Less
+                    && compare_gem_versions(version, &upper) == Ordering::Less
+            }
+            _ => false,
+        }
+    })
+}
+
 /// Determines the run dependencies of a gem. Deals with version requirements.
-pub(super) fn parse_gem_version_req(rubygem_dep: &rubygems_api::GemRunDeps) -> String {
+pub(super) fn parse_gem_version_req(rubygem_dep: &GemResponseDep) -> String {
     let cmpr = rubygem_dep
         .requirements
         .split_whitespace()
@@ -14,69 +14,151 @@
 //along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::crates::*;
-use crate::errors::Error;
+use crate::data::KnownData;
+use crate::errors::{Error, ProviderError};
 use crate::gems::*;
+use crate::http::{self, get};
 use crate::perldist::*;
+use crate::registry::Registries;
+use crate::spdx::*;
 use crate::types::*;
+use flate2::read::GzDecoder;
+use futures::Future;
 use git2::Config as GitConfig;
 use indicatif::{ProgressBar, ProgressStyle};
 use log::{debug, info, warn};
 use rayon::prelude::*;
-use retry::retry_exponentially;
 use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 use std::env::var_os;
+use std::io::Read;
+use std::path::PathBuf;
+
+/// Packages with more documentation than this (in bytes) get a -doc subpackage
+const DOC_SUBPKG_SIZE: u64 = 512 * 1024;
+
+/// The User-Agent tmplgen identifies itself with, crates.io refuses requests without one
+pub(super) fn user_agent() -> String {
+    format!(
+        "tmplgen/{} (github.com/Cogitri/tmplgen)",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// The name of the registry of `pkg_type`, e.g. `crates.io`
+pub(super) fn registry_name(pkg_type: PkgType) -> &'static str {
+    match pkg_type {
+        PkgType::Crate => "crates.io",
+        PkgType::Gem => "rubygems.org",
+        PkgType::PerlDist => "metacpan.org",
+    }
+}
 
-/// Figure out whether we're dealing with a crate or a gem if the user hasn't specified that.
+/// Figure out whether we're dealing with a crate, a gem or a perldist if the user hasn't
+/// specified that.
 ///
 /// # Errors
 ///
 /// * Errors out of a package with the name the user gave us can be found multiple platforms
 /// * Errors out if the package can't be found on any platform
+/// * Errors out if a registry can't be reached, see [find_providers](crate::helpers::find_providers)
 pub(super) fn figure_out_provider(pkg_name: &str) -> Result<PkgType, Error> {
-    //TODO: Actually check that the error is "Not Found"!
-    let crate_status = crates_io_api::SyncClient::new()
-        .get_crate(&pkg_name)
-        .is_ok();
-
-    let gem_status = rubygems_api::SyncClient::new().gem_info(&pkg_name).is_ok();
-
-    let perldist_status = metacpan_api::SyncClient::new().perl_info(&pkg_name).is_ok();
-
-    if (crate_status && gem_status)
-        || (crate_status && perldist_status)
-        || (gem_status && perldist_status)
-    {
-        let mut found_platforms = Vec::new();
-        if crate_status {
-            found_platforms.push("crates.io");
+    let matches = find_providers(pkg_name)?;
+
+    match matches.len() {
+        0 => Err(Error::NoSuchPkg(pkg_name.to_string())),
+        1 => {
+            debug!(
+                "Determined the target package {} to be a {:?}",
+                &pkg_name, matches[0].pkg_type
+            );
+            Ok(matches[0].pkg_type)
         }
-        if gem_status {
-            found_platforms.push("metacpan.org");
-        }
-        if perldist_status {
-            found_platforms.push("rubygems.org")
+        _ => Err(Error::AmbPkg(format!(
+            "{} on the platforms {}",
+            pkg_name,
+            matches
+                .iter()
+                .map(|x| format!("\"{}\"", registry_name(x.pkg_type)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+/// Queries the registries of all PkgTypes concurrently and returns those which know a package
+/// called `pkg_name`
+///
+/// # Errors
+///
+/// * Errors out if any of the registries can't be reached, since we can't tell if the package
+///   exists there then
+pub(super) fn find_providers(pkg_name: &str) -> Result<Vec<ProviderMatch>, Error> {
+    let responses = PkgType::all()
+        .par_iter()
+        .map(|pkg_type| (*pkg_type, probe_registry(pkg_name, *pkg_type)))
+        .collect::<Vec<_>>();
+
+    let mut matches = Vec::new();
+
+    for (pkg_type, response) in responses {
+        match response? {
+            Some(version) => matches.push(ProviderMatch { pkg_type, version }),
+            None => debug!("{} doesn't know {}", registry_name(pkg_type), pkg_name),
         }
+    }
 
-        Err(Error::AmbPkg(
-            (&format!("{} on the platforms {:?}", pkg_name, found_platforms)
-                .replace("[", "")
-                .replace("]", ""))
-                .to_string(),
+    Ok(matches)
+}
+
+/// Asks the registry of `pkg_type` for the latest version of `pkg_name`. Returns `None` if the
+/// registry doesn't know the package.
+///
+/// # Errors
+///
+/// * Errors out if the registry can't be reached or answers with an error other than "Not Found"
+/// * Errors out if the answer of the registry doesn't contain the version of the package
+fn probe_registry(pkg_name: &str, pkg_type: PkgType) -> Result<Option<String>, Error> {
+    let registries = Registries::get();
+
+    let (url, version_pointer) = match pkg_type {
+        PkgType::Crate => (
+            registries.api_url(pkg_type, &format!("crates/{}", pkg_name)),
+            "/crate/max_version",
+        ),
+        PkgType::Gem => (
+            registries.api_url(pkg_type, &format!("v1/gems/{}.json", pkg_name)),
+            "/version",
+        ),
+        PkgType::PerlDist => (
+            registries.api_url(pkg_type, &format!("release/{}", pkg_name)),
+            "/version",
+        ),
+    };
+
+    // Transport errors and errors of the server are retried, but not a 404 which is an answer
+    let response = get(&url).map_err(|e| e.for_pkg(pkg_type, pkg_name))?;
+
+    if response.status == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    let answer: serde_json::Value = response
+        .error_for_status()
+        .and_then(|x| x.json())
+        .map_err(|e| e.for_pkg(pkg_type, pkg_name))?;
+
+    match answer.pointer(version_pointer) {
+        Some(serde_json::Value::String(version)) => Ok(Some(version.clone())),
+        // metacpan sometimes returns versions as numbers
+        Some(serde_json::Value::Number(version)) => Ok(Some(version.to_string())),
+        _ => Err(ProviderError::parse(format!(
+            "{} didn't tell us the version",
+            registry_name(pkg_type)
         ))
-    } else if crate_status {
-        debug!("Determined the target package {} to be a crate", &pkg_name);
-        Ok(PkgType::Crate)
-    } else if gem_status {
-        debug!(
-            "Determined the target package {} to be a ruby gem",
-            &pkg_name
-        );
-        Ok(PkgType::Gem)
-    } else if perldist_status {
-        debug!("Determined the target package to be a perldist");
-        Ok(PkgType::PerlDist)
-    } else {
-        Err(Error::NoSuchPkg(pkg_name.to_string()))
+        .with_pkg(pkg_type, pkg_name)
+        .with_url(&url)
+        .into()),
     }
 }
 
@@ -113,7 +195,7 @@ pub(super) fn gen_dep_string(dep_vec: &[String], pkg_type: PkgType) -> String {
         }
 
         if pkg_type == PkgType::PerlDist {
-            if x == "perl" {
+            if x == "perl" || is_native_dep(x) {
                 dep_string.push_str(x)
             } else {
                 dep_string.push_str(&format!("perl-{}", &x.replace("::", "-")));
@@ -126,39 +208,170 @@ pub(super) fn gen_dep_string(dep_vec: &[String], pkg_type: PkgType) -> String {
     dep_string
 }
 
-/// Converts some non-SPDX conform names to SPDX-conform ones (e.g. GPL-2.0+ to GPL-2.0-or-later)
-pub(super) fn correct_license(license: &str) -> String {
-    let data: TomlData = toml::from_str(include_str!("data.toml")).unwrap();
+/// Checks if `dep` is one of the native dependencies listed in `data.toml` (e.g. `libxml2-devel`),
+/// which mustn't be prefixed like language-specific dependencies
+pub(super) fn is_native_dep(dep: &str) -> bool {
+    KnownData::get().is_native_dep(dep)
+}
 
-    let corrected_vals = CorrectedVals {
-        licenses: data.licenses,
-    };
+/// Maps the pkg-config name (or `links` value) of a native library to the package providing it,
+/// e.g. `libxml-2.0` to `libxml2-devel`
+pub(super) fn map_pkg_config(library: &str) -> Option<String> {
+    KnownData::get()
+        .map_pkg_config(library)
+        .map(|x| x.to_string())
+}
 
-    let correct_license = corrected_vals
-        .licenses
-        .par_iter()
-        .filter(|x| license == x.is)
-        .map(|x| x.should.to_string())
-        .collect::<String>();
+/// Removes duplicate entries from `vec` while keeping the order of the remaining ones
+pub(super) fn dedup_vec(vec: &mut Vec<String>) {
+    let mut seen = std::collections::HashSet::new();
+    vec.retain(|x| seen.insert(x.clone()));
+}
 
-    if correct_license.is_empty() {
-        license.to_string()
-    } else {
-        correct_license
+/// Converts the license names of the package `pkg_name` to SPDX-conform ones. The license
+/// vocabulary of `pkg_type` is looked up first (e.g. the CPAN::Meta key perl_5 becomes
+/// Artistic-1.0-Perl, GPL-1.0-or-later and the gemspec license "Apache 2.0" becomes Apache-2.0),
+/// everything else is parsed as SPDX license expression and converted to the format Void expects,
+/// warning about unknown identifiers.
+///
+/// Ambiguous names (e.g. bsd or open_source) aren't returned, instead the maintainer is asked
+/// to choose the correct license.
+pub(super) fn correct_license(pkg_name: &str, pkg_type: PkgType, license: &str) -> Vec<String> {
+    match KnownData::get().license_correction(license, pkg_type) {
+        Some(CorrectedLicenses {
+            should: Some(should),
+            ..
+        }) => should.split(", ").map(|x| x.to_string()).collect(),
+        Some(CorrectedLicenses { candidates, .. }) => {
+            if candidates.is_empty() {
+                warn!(
+                    "License '{}' of package {} doesn't name a specific license! Please add the correct license to the template yourself.",
+                    license, pkg_name
+                );
+            } else {
+                warn!(
+                    "License '{}' of package {} is ambiguous, it could be {}! Please add the correct license to the template yourself.",
+                    license,
+                    pkg_name,
+                    candidates.join(" or ")
+                );
+            }
+
+            Vec::new()
+        }
+        None => spdx_to_void(pkg_name, license),
     }
 }
 
-/// Convenience function to get `PkgInfo` for the package `pkg_name` of a certain `PkgType`
+/// Convenience function to get `PkgInfo` for the package `pkg_name` of a certain `PkgType`.
+/// `features` are only used for crates, `runtime_versions` only for perldists and gems.
 ///
 /// Errors if determining `PkgInfo` fails, see the doc for [crate_info](crate::crates::crate_info),
 /// [gem_info](crate::gems::gem_info) and [perldist_info](crate::perldist::perldist_info)
-pub(super) fn get_pkginfo(pkg_name: &str, pkg_type: PkgType) -> Result<PkgInfo, Error> {
+pub(super) fn get_pkginfo(
+    pkg_name: &str,
+    pkg_type: PkgType,
+    features: &CrateFeatures,
+    runtime_versions: &RuntimeVersions,
+) -> Result<PkgInfo, Error> {
     if pkg_type == PkgType::Crate {
-        crate_info(&pkg_name)
+        crate_info(&pkg_name, features)
     } else if pkg_type == PkgType::PerlDist {
-        perldist_info(&pkg_name)
+        perldist_info(&pkg_name, runtime_versions)
     } else {
-        gem_info(pkg_name)
+        gem_info(pkg_name, runtime_versions)
+    }
+    .map_err(|e| e.for_pkg(pkg_type, pkg_name))
+}
+
+/// Like [get_pkginfo](crate::helpers::get_pkginfo), but without blocking. All requests are
+/// sent with `client`.
+pub(super) fn get_pkginfo_async(
+    client: &reqwest::r#async::Client,
+    pkg_name: &str,
+    pkg_type: PkgType,
+    features: &CrateFeatures,
+    runtime_versions: &RuntimeVersions,
+) -> crate::http::BoxFuture<PkgInfo> {
+    let future = match pkg_type {
+        PkgType::Crate => crate_info_async(client, pkg_name, features),
+        PkgType::Gem => gem_info_async(client, pkg_name, runtime_versions),
+        PkgType::PerlDist => perldist_info_async(client, pkg_name, runtime_versions),
+    };
+    let pkg_name = pkg_name.to_string();
+
+    Box::new(future.map_err(move |e| e.for_pkg(pkg_type, &pkg_name)))
+}
+
+/// Gets the latest version of the package `pkg_name` from the registry of `pkg_type`, without
+/// gathering the rest of its `PkgInfo`
+///
+/// # Errors
+///
+/// * Errors out if the registry can't be reached
+/// * Errors out if the package can't be found in the registry
+pub(super) fn get_latest_version(pkg_name: &str, pkg_type: PkgType) -> Result<String, Error> {
+    match pkg_type {
+        PkgType::Crate => get_crate_version(pkg_name).map_err(|e| e.for_pkg(pkg_type, pkg_name)),
+        _ => probe_registry(pkg_name, pkg_type)?
+            .ok_or_else(|| Error::NoSuchPkg(pkg_name.to_string())),
+    }
+}
+
+/// Searches the registry of `pkg_type` for packages matching `query`, returning at most `limit`
+/// of them
+///
+/// # Errors
+///
+/// * Errors out if the registry can't be reached
+pub(super) fn search_registry(
+    query: &str,
+    pkg_type: PkgType,
+    limit: usize,
+) -> Result<Vec<SearchResult>, Error> {
+    match pkg_type {
+        PkgType::Crate => search_crates(query, limit),
+        PkgType::Gem => search_gems(query, limit),
+        PkgType::PerlDist => search_perldists(query, limit),
+    }
+}
+
+/// Gets the value of the field `field` (e.g. `version`) of the template `template`, without
+/// surrounding quotes
+pub(super) fn template_field(template: &str, field: &str) -> Option<String> {
+    template
+        .lines()
+        .find(|x| x.starts_with(&format!("{}=", field)))
+        .map(|x| {
+            x[field.len() + 1..]
+                .trim()
+                .trim_matches(|c| c == '"' || c == '\'')
+                .to_string()
+        })
+}
+
+/// Splits a dependency of a perldist or gem as it's listed in its `PkgInfo` (e.g. `Moo>=2.0` or
+/// `ruby-rake`) into the name of the dependency in its registry and the version it requires
+/// (in the format [is_built_in](crate::data::KnownData::is_built_in) expects). Returns `None` for
+/// dependencies that aren't in the registry, e.g. `perl` or native dependencies.
+pub(super) fn split_lang_dep(dep: &str, pkg_type: PkgType) -> Option<(String, Option<String>)> {
+    let mut split = dep.splitn(2, ">=");
+    let name = split.next().unwrap_or_default();
+    let required_version = split.next().map(|x| x.trim());
+
+    match pkg_type {
+        PkgType::PerlDist if name != "perl" && !is_native_dep(name) => {
+            Some((name.to_string(), required_version.map(|x| x.to_string())))
+        }
+        PkgType::Gem if name.starts_with("ruby-") => Some((
+            name.trim_start_matches("ruby-")
+                .split(|c| c == '<' || c == '>')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            required_version.map(|x| format!(">= {}", x)),
+        )),
+        _ => None,
     }
 }
 
@@ -191,42 +404,18 @@ pub(super) fn get_git_author() -> Result<String, Error> {
     Ok(maintainer)
 }
 
-/// Download the file specified via `dwnld_url` and return its checksum
+/// Starts downloading the file specified via `dwnld_url`, displaying a progressbar for
+/// big downloads
 ///
 /// # Errors
 ///
 /// * Errors out if the file can't be downloaded
-/// * Errors out if the sha256sum couldn't be determined
-pub(super) fn gen_checksum(dwnld_url: &str) -> Result<String, Error> {
-    let req_client = reqwest::Client::new();
-    let url = reqwest::Url::parse(dwnld_url)?;
-
-    let response_head = {
-        match retry_exponentially(
-            3,
-            10.0,
-            &mut || req_client.head(url.as_str()).send(),
-            |result| result.is_ok(),
-        ) {
-            Ok(resp) => resp,
-            Err(err) => {
-                return Err(Error::Sha(format!(
-                    "Couldn't download URL {}: {}",
-                    url,
-                    err.to_string()
-                )));
-            }
-        }
-    };
+fn download(dwnld_url: &str) -> Result<DownloadProgress<http::Download>, Error> {
+    let url = Registries::get().download_url(dwnld_url);
 
-    let total_size = response_head?
-        .headers()
-        .get(reqwest::header::CONTENT_LENGTH)
-        .and_then(|ct_len| ct_len.to_str().ok())
-        .and_then(|ct_len| ct_len.parse().ok())
-        .unwrap_or(0);
+    let source = http::download(&url)?;
 
-    debug!("GET: {}", dwnld_url);
+    let total_size = source.size().unwrap_or(0);
 
     info!("Downloading distfile to generate checksum...");
 
@@ -242,26 +431,28 @@ pub(super) fn gen_checksum(dwnld_url: &str) -> Result<String, Error> {
         .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
         .progress_chars("#>-"));
 
-    let resp_get = || reqwest::get(url.clone());
-
-    let query_result =
-        match retry_exponentially(3, 10.0, &mut || resp_get(), |result| result.is_ok()) {
-            Ok(response) => response?,
-            Err(error) => return Err(Error::Crate(error.to_string())),
-        };
-
-    let mut source = DownloadProgress {
+    Ok(DownloadProgress {
         progress_bar: pb,
-        inner: query_result,
-    };
+        inner: source,
+    })
+}
 
-    let mut hasher = Sha256::new();
+/// Download the file specified via `dwnld_url` and return its checksum
+///
+/// # Errors
+///
+/// * Errors out if the file can't be downloaded
+/// * Errors out if the sha256sum couldn't be determined
+pub(super) fn gen_checksum(dwnld_url: &str) -> Result<String, Error> {
+    let mut source = download(dwnld_url)?;
 
-    let hash_res = std::io::copy(&mut source, &mut hasher);
+    let mut hasher = Sha256::new();
 
-    if hash_res.is_err() {
-        return Err(Error::Sha(hash_res.unwrap_err().to_string()));
-    }
+    std::io::copy(&mut source, &mut hasher).map_err(|e| {
+        ProviderError::checksum("Failed to hash the distfile")
+            .with_url(dwnld_url)
+            .with_source(e)
+    })?;
 
     let hash = hasher.result();
 
@@ -270,61 +461,387 @@ pub(super) fn gen_checksum(dwnld_url: &str) -> Result<String, Error> {
     Ok(format!("{:x}", &hash))
 }
 
+/// Download the distfile specified via `dwnld_url`, return its checksum and what it contains (see
+/// [inspect_archive](crate::helpers::inspect_archive)). The distfile is inspected while it's being
+/// hashed, so it's only downloaded once. If the distfile isn't a gzipped tarball we can't look into
+/// it, so the license files are None.
+///
+/// # Errors
+///
+/// * Errors out if the file can't be downloaded
+/// * Errors out if the sha256sum couldn't be determined
+pub(super) fn inspect_distfile(dwnld_url: &str) -> Result<DistfileInfo, Error> {
+    inspect_distfile_source(download(dwnld_url)?).map_err(|e| e.for_url(dwnld_url))
+}
+
+/// Hashes and inspects the distfile read from `source`, see
+/// [inspect_distfile](crate::helpers::inspect_distfile)
+///
+/// # Errors
+///
+/// * Errors out if the sha256sum couldn't be determined
+pub(super) fn inspect_distfile_source<R: Read>(source: R) -> Result<DistfileInfo, Error> {
+    let mut source = HashingReader {
+        inner: source,
+        hasher: Sha256::new(),
+    };
+
+    let contents = {
+        let mut archive = tar::Archive::new(GzDecoder::new(&mut source));
+
+        // Distfiles usually contain a `{name}-{version}/` directory, license files are
+        // at its top level.
+        inspect_archive(&mut archive, 1).ok()
+    };
+
+    // The tarball might have some trailing bytes (e.g. the gzip trailer) which
+    // we still have to hash
+    std::io::copy(&mut source, &mut std::io::sink())
+        .map_err(|e| ProviderError::checksum("Failed to hash the distfile").with_source(e))?;
+
+    let hash = source.hasher.result();
+
+    debug!("Hash: {:x}", &hash);
+    debug!("Distfile contents: {:?}", &contents);
+
+    Ok(match contents {
+        Some(contents) => DistfileInfo {
+            sha: format!("{:x}", &hash),
+            license_files: Some(contents.license_files),
+            doc_size: contents.doc_size,
+            files: contents.files,
+            manifests: contents.manifests,
+            build_scripts: contents.build_scripts,
+        },
+        None => DistfileInfo {
+            sha: format!("{:x}", &hash),
+            license_files: None,
+            doc_size: 0,
+            files: Vec::new(),
+            manifests: BTreeMap::new(),
+            build_scripts: BTreeMap::new(),
+        },
+    })
+}
+
+/// Looks into the archive: returns the license files at its top level (e.g. `LICENSE-MIT` or
+/// `COPYING`), the size of its documentation, its files, its `Cargo.toml`s and what might be the
+/// build script of the top-level crate. `depth` is the number of leading directories of the
+/// archive's paths before its top level.
+///
+/// # Errors
+///
+/// * Errors out if `archive` isn't a valid tarball
+pub(super) fn inspect_archive<R: std::io::Read>(
+    archive: &mut tar::Archive<R>,
+    depth: usize,
+) -> Result<ArchiveContents, Error> {
+    let mut contents = ArchiveContents::default();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let components = path.components().count();
+
+        if is_doc_file(&path, depth) {
+            contents.doc_size += entry.header().size().unwrap_or(0);
+        }
+
+        if components <= depth || !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let relative_path = path
+            .components()
+            .skip(depth)
+            .collect::<PathBuf>()
+            .to_string_lossy()
+            .to_string();
+
+        if let Some(file_name) = path.file_name().and_then(|x| x.to_str()) {
+            if components == depth + 1 && is_license_file(file_name) {
+                contents.license_files.push(file_name.to_string());
+            } else if file_name == "Cargo.toml" {
+                let mut cargo_toml = String::new();
+                entry.read_to_string(&mut cargo_toml)?;
+
+                let manifest_dir = relative_path.trim_end_matches("Cargo.toml");
+                contents
+                    .manifests
+                    .insert(manifest_dir.trim_end_matches('/').to_string(), cargo_toml);
+            } else if is_build_script_candidate(&relative_path) {
+                // We don't know where the build script is until we've read Cargo.toml, so we
+                // keep everything that might be it.
+                let mut source = String::new();
+                if entry.read_to_string(&mut source).is_ok() {
+                    contents.build_scripts.insert(relative_path.clone(), source);
+                }
+            }
+        }
+
+        contents.files.push(relative_path);
+    }
+
+    contents.license_files.sort();
+    contents.files.sort();
+
+    Ok(contents)
+}
+
+/// Checks if `path` is documentation, i.e. a `.pod` file or a file in a top-level `doc/` or
+/// `docs/` directory. `depth` is the same as for [inspect_archive](crate::helpers::inspect_archive).
+fn is_doc_file(path: &std::path::Path, depth: usize) -> bool {
+    let is_pod = path.extension().map_or(false, |x| x == "pod");
+    let in_doc_dir = path
+        .components()
+        .nth(depth)
+        .map_or(false, |x| x.as_os_str() == "doc" || x.as_os_str() == "docs");
+
+    is_pod || (in_doc_dir && path.components().count() > depth + 1)
+}
+
+/// Returns a -doc subpackage which moves `files` if the documentation is `doc_size` (see
+/// [inspect_archive](crate::helpers::inspect_archive)) bytes large, which is too large to ship
+/// it in the main package
+pub(super) fn doc_subpackage(doc_size: u64, files: &[&str]) -> Option<SubPackage> {
+    if doc_size > DOC_SUBPKG_SIZE {
+        debug!(
+            "Documentation is {} bytes large, splitting it into a -doc subpackage",
+            doc_size
+        );
+
+        Some(SubPackage {
+            suffix: "doc".to_string(),
+            short_desc: "documentation".to_string(),
+            depends: Vec::new(),
+            noarch: true,
+            files: files.iter().map(|x| x.to_string()).collect(),
+        })
+    } else {
+        None
+    }
+}
+
+/// Renders the `{pkg_name}-{suffix}_package()` function of the subpackage for a template
+pub(super) fn gen_subpkg_string(pkg_name: &str, subpackage: &SubPackage) -> String {
+    let mut subpkg_string = format!("{}-{}_package() {{\n", pkg_name, subpackage.suffix);
+
+    if subpackage.noarch {
+        subpkg_string.push_str("\tarchs=noarch\n");
+    }
+
+    if !subpackage.depends.is_empty() {
+        subpkg_string.push_str(&format!("\tdepends=\"{}\"\n", subpackage.depends.join(" ")));
+    }
+
+    subpkg_string.push_str(&format!(
+        "\tshort_desc+=\" - {}\"\n\tpkg_install() {{\n",
+        subpackage.short_desc
+    ));
+
+    for file in &subpackage.files {
+        // Globs have to be quoted, so vmove expands them in DESTDIR
+        if file.contains('*') {
+            subpkg_string.push_str(&format!("\t\tvmove \"{}\"\n", file));
+        } else {
+            subpkg_string.push_str(&format!("\t\tvmove {}\n", file));
+        }
+    }
+
+    subpkg_string.push_str("\t}\n}");
+
+    subpkg_string
+}
+
+/// Checks if `file_name` is the name of a license file, e.g. `LICENSE`, `LICENSE-MIT`,
+/// `LICENCE.txt`, `MIT-LICENSE.md` or `COPYING`
+pub(super) fn is_license_file(file_name: &str) -> bool {
+    let file_name = file_name.to_uppercase();
+    let file_stem = file_name.split('.').next().unwrap_or_default();
+
+    ["LICENSE", "LICENCE", "COPYING", "COPYRIGHT", "UNLICENSE"]
+        .iter()
+        .any(|x| {
+            file_stem == *x
+                || file_stem.starts_with(&format!("{}-", x))
+                || file_stem.ends_with(&format!("-{}", x))
+        })
+}
+
 /// Check if a package needs native deps (e.g. crate openssl-sys needs libressl-devel)
 /// If the package has native deps, we return Some(Dependencies), otherwise we return
 /// None
 ///
-/// As of now only works for crates
+/// For crates only the optional dependencies enabled by `features` are considered. If
+/// `features` is None, the crate's default features are used.
 ///
 /// # Errors
 ///
 /// * Errors out if crates.io can't be queried
 /// * Errors out if the crate can't be found on crates.io
+/// * Errors out if one of `features` isn't a feature of the crate
 pub(super) fn check_native_deps(
     pkg_name: &str,
     pkg_type: PkgType,
+    features: Option<&CrateFeatures>,
 ) -> Result<Option<Dependencies>, Error> {
     if pkg_type == PkgType::Crate {
-        let dependencies = crate::crates::get_crate_deps(pkg_name)?;
+        crate::crates::get_crate_native_deps(pkg_name, features)
+    } else {
+        // Unlike crates, the perldists and gems we depend on are already built once they're
+        // installed, so only the package itself can need native libraries.
+        let make_dep_vec = KnownData::get().native_deps(pkg_name, pkg_type).to_vec();
 
-        debug!("Crate dependencies: {:?}", dependencies);
+        if make_dep_vec.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(Dependencies {
+                host: None,
+                make: Some(make_dep_vec),
+                run: None,
+            }))
+        }
+    }
+}
+
+/// Determines the native dependencies of the crate `pkg_name` with the dependencies
+/// `dependencies` and the features `crate_features` that are in the data tables. The crates
+/// whose build scripts we have to look at, because we don't know about them, are returned as
+/// `own_build_script` and `unknown_crates`.
+///
+/// # Errors
+///
+/// * Errors out if one of `features` isn't a feature of the crate
+pub(super) fn crate_native_deps(
+    pkg_name: &str,
+    dependencies: &[CrateDependency],
+    crate_features: &HashMap<String, Vec<String>>,
+    features: Option<&CrateFeatures>,
+) -> Result<CrateNativeDeps, Error> {
+    debug!("Crate dependencies: {:?}", dependencies);
+
+    let default_features = CrateFeatures::default();
+    let features = features.unwrap_or(&default_features);
+
+    for feature in &features.features {
+        if !crate_features.contains_key(feature)
+            && !dependencies
+                .iter()
+                .any(|x| x.optional && &x.crate_id == feature)
+        {
+            return Err(Error::UnknownFeature {
+                crate_name: pkg_name.to_string(),
+                feature: feature.clone(),
+            });
+        }
+    }
 
-        let data: TomlData = toml::from_str(include_str!("data.toml")).unwrap();
+    let enabled_features = crate::crates::enabled_features(crate_features, features);
 
-        let native_deps = NativeDepType {
-            rust: data.native_deps.rust,
-        };
+    debug!("Enabled crate features: {:?}", enabled_features);
 
-        let mut make_dep_vec = vec![];
+    let known_data = KnownData::get();
 
-        for native_dep in &native_deps.rust {
-            if pkg_name == native_dep.name {
-                make_dep_vec.push(native_dep.dep.clone());
-                break;
-            }
+    let mut native_deps = CrateNativeDeps::default();
+
+    let own_native_deps = known_data.native_deps(pkg_name, PkgType::Crate);
+
+    if own_native_deps.is_empty() {
+        native_deps.own_build_script = true;
+    } else {
+        native_deps.make.extend_from_slice(own_native_deps);
+    }
+
+    for dep in dependencies {
+        if dep.optional && !enabled_features.contains(&dep.crate_id) {
+            debug!(
+                "Skipping optional dependency {} which isn't enabled by any feature",
+                dep.crate_id
+            );
+            continue;
         }
 
-        for dep in dependencies {
-            for native_dep in &native_deps.rust {
-                if dep.crate_id == native_dep.name {
-                    make_dep_vec.push(native_dep.dep.clone());
-                }
+        let dep_native_deps = known_data.native_deps(&dep.crate_id, PkgType::Crate);
+
+        // By convention -sys crates are the ones linking to native libraries
+        if dep_native_deps.is_empty() && dep.crate_id.ends_with("-sys") {
+            native_deps.unknown_crates.push(dep.clone());
+        } else {
+            native_deps.make.extend_from_slice(dep_native_deps);
+        }
+    }
+
+    Ok(native_deps)
+}
+
+/// Adds what the build scripts of the crates we don't know about (`build_script_infos`, keyed by
+/// the name of the crate, see [crate_native_deps](crate::helpers::crate_native_deps)) tell us to
+/// the native dependencies of the crate `pkg_name`
+pub(super) fn finish_crate_native_deps(
+    pkg_name: &str,
+    native_deps: CrateNativeDeps,
+    build_script_infos: Vec<(String, BuildScriptInfo)>,
+) -> Option<Dependencies> {
+    let mut host_dep_vec = native_deps.host;
+    let mut make_dep_vec = native_deps.make;
+
+    for (unknown_crate, build_script_info) in build_script_infos {
+        let mut libraries = build_script_info.pkg_config.clone();
+        if let Some(links) = build_script_info.links {
+            libraries.push(links);
+        }
+
+        for library in &libraries {
+            match map_pkg_config(library) {
+                Some(dep) => make_dep_vec.push(dep),
+                None => warn!(
+                    "Couldn't map native library {} of crate {} to a package, please add it to the template yourself!",
+                    library, unknown_crate
+                ),
             }
         }
 
-        if make_dep_vec.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(Dependencies {
-                host: Some(vec!["pkg-config".to_string()]),
-                make: Some(make_dep_vec),
-                run: None,
-            }))
+        if build_script_info.uses_cmake {
+            host_dep_vec.push("cmake".to_string());
         }
+
+        if build_script_info.uses_bindgen {
+            make_dep_vec.push("clang".to_string());
+        }
+
+        if libraries.is_empty() && unknown_crate != pkg_name {
+            if build_script_info.uses_cc {
+                debug!(
+                    "Crate {} doesn't link to any libraries, it probably builds bundled C code",
+                    unknown_crate
+                );
+            } else {
+                warn!(
+                    "Couldn't determine the native dependencies of crate {}, please check them yourself!",
+                    unknown_crate
+                );
+            }
+        }
+    }
+
+    dedup_vec(&mut make_dep_vec);
+    dedup_vec(&mut host_dep_vec);
+
+    if !make_dep_vec.is_empty() {
+        host_dep_vec.insert(0, "pkg-config".to_string());
+    }
+
+    if make_dep_vec.is_empty() && host_dep_vec.is_empty() {
+        None
     } else {
-        Err(Error::WrongUsage {
-            method: "check_native_deps".to_string(),
-            err: "Right now check_native_deps only works for crates!".to_string(),
+        Some(Dependencies {
+            host: Some(host_dep_vec),
+            make: if make_dep_vec.is_empty() {
+                None
+            } else {
+                Some(make_dep_vec)
+            },
+            run: None,
         })
     }
 }
@@ -0,0 +1,786 @@
+//This file is part of tmplgen
+//
+//tmplgen is free software: you can redistribute it and/or modify
+//it under the terms of the GNU General Public License as published by
+//the Free Software Foundation, either version 3 of the License, or
+//(at your option) any later version.
+//
+//tmplgen is distributed in the hope that it will be useful,
+//but WITHOUT ANY WARRANTY; without even the implied warranty of
+//MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//GNU General Public License for more details.
+//
+//You should have received a copy of the GNU General Public License
+//along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::errors::{Error, ProviderError};
+use crate::helpers::user_agent;
+use crate::registry::Registries;
+use crate::types::PkgType;
+use futures::future::{self, Either, Loop};
+use futures::sync::oneshot;
+use futures::{Future, Stream};
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use reqwest::r#async::Client;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::env::{var, var_os};
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::timer::{Delay, Timeout};
+
+/// A boxed future, which is what the async functions of libtmplgen return
+pub(super) type BoxFuture<T> = Box<dyn Future<Item = T, Error = Error> + Send>;
+
+lazy_static! {
+    static ref HTTP_MODE: RwLock<HttpMode> = RwLock::new(HttpMode::from_env());
+    static ref NETWORK_POLICY: RwLock<NetworkPolicy> = RwLock::new(NetworkPolicy::from_env());
+    static ref CRATE_LIMITER: Limiter = Limiter::new();
+    static ref GEM_LIMITER: Limiter = Limiter::new();
+    static ref PERLDIST_LIMITER: Limiter = Limiter::new();
+    /// For everything that isn't hosted by a registry, e.g. distfiles on GitHub
+    static ref OTHER_LIMITER: Limiter = Limiter::new();
+}
+
+/// How libtmplgen sends requests: how often failed requests are retried and how long it waits in
+/// between, how long a request may take and how many requests are sent to a registry at the same
+/// time. Transport errors, timeouts, errors of the server and `429 Too Many Requests` are retried,
+/// waiting for as long as the `Retry-After` header of the answer asks for.
+///
+/// The policy is read from the environment variables `TMPLGEN_RETRIES`, `TMPLGEN_BACKOFF_MS`,
+/// `TMPLGEN_TIMEOUT` (in seconds, `0` disables the timeout) and
+/// `TMPLGEN_MAX_CONCURRENT_REQUESTS` on first use. It can be changed at runtime with
+/// [set_network_policy](crate::http::set_network_policy).
+///
+/// # Example
+///
+/// ```
+/// use libtmplgen::*;
+/// use std::time::Duration;
+///
+/// set_network_policy(NetworkPolicy {
+///     retries: 5,
+///     timeout: Some(Duration::from_secs(10)),
+///     ..NetworkPolicy::default()
+/// });
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NetworkPolicy {
+    /// How often a failed request is retried
+    pub retries: u32,
+    /// How long to wait before the first retry, doubled for every further one
+    pub backoff: Duration,
+    /// How long we wait at most if a registry asks us to via `Retry-After`. If it asks for longer,
+    /// we give up instead.
+    pub max_retry_after: Duration,
+    /// How long to wait for an answer before giving up on a request
+    pub timeout: Option<Duration>,
+    /// How many requests are sent to a single registry at the same time at most
+    pub max_concurrent_requests: usize,
+}
+
+impl Default for NetworkPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            backoff: Duration::from_millis(100),
+            max_retry_after: Duration::from_secs(60),
+            timeout: Some(Duration::from_secs(30)),
+            max_concurrent_requests: 8,
+        }
+    }
+}
+
+impl NetworkPolicy {
+    /// Reads the policy from the environment, invalid values are warned about and ignored
+    fn from_env() -> Self {
+        fn env_var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            let value = var(name).ok().filter(|x| !x.is_empty())?;
+
+            match value.trim().parse() {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    warn!("Ignoring invalid {} '{}'", name, value);
+                    None
+                }
+            }
+        }
+
+        let mut policy = Self::default();
+
+        if let Some(retries) = env_var("TMPLGEN_RETRIES") {
+            policy.retries = retries;
+        }
+        if let Some(backoff) = env_var("TMPLGEN_BACKOFF_MS") {
+            policy.backoff = Duration::from_millis(backoff);
+        }
+        if let Some(timeout) = env_var::<u64>("TMPLGEN_TIMEOUT") {
+            policy.timeout =
+                Some(Duration::from_secs(timeout)).filter(|x| *x > Duration::from_secs(0));
+        }
+        if let Some(max) = env_var::<usize>("TMPLGEN_MAX_CONCURRENT_REQUESTS") {
+            policy.max_concurrent_requests = max.max(1);
+        }
+
+        policy
+    }
+
+    /// How long to wait before retrying a request that failed for the `attempt + 1`th time, or
+    /// None if it shouldn't be retried. `status` is None if the request failed before the server
+    /// answered (e.g. because it timed out), `retry_after` is the delay the server asked for.
+    pub(super) fn retry_delay(
+        &self,
+        attempt: u32,
+        status: Option<reqwest::StatusCode>,
+        retry_after: Option<Duration>,
+    ) -> Option<Duration> {
+        if attempt >= self.retries {
+            return None;
+        }
+
+        match status {
+            Some(status)
+                if status != reqwest::StatusCode::TOO_MANY_REQUESTS
+                    && !status.is_server_error() =>
+            {
+                None
+            }
+            _ => match retry_after {
+                Some(retry_after) if retry_after > self.max_retry_after => None,
+                Some(retry_after) => Some(retry_after),
+                None => Some(self.backoff * 2u32.pow(attempt.min(16))),
+            },
+        }
+    }
+}
+
+/// Sets how libtmplgen sends requests, see [NetworkPolicy](crate::http::NetworkPolicy)
+pub fn set_network_policy(policy: NetworkPolicy) {
+    *NETWORK_POLICY.write().unwrap() = policy;
+}
+
+/// Returns how libtmplgen currently sends requests
+pub fn network_policy() -> NetworkPolicy {
+    *NETWORK_POLICY.read().unwrap()
+}
+
+/// Reads how long the server asks us to wait before retrying from the `Retry-After` header. Only
+/// delays in seconds are supported, not dates.
+pub(super) fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// How libtmplgen talks to the registries (and the hosts of distfiles). Recorded responses can be
+/// replayed later on, so e.g. the tests of tmplgen don't need network access.
+///
+/// The mode is read from the environment variable `TMPLGEN_HTTP_MODE` (`live`, `record` or
+/// `replay`) on first use, the recorded responses are stored in `TMPLGEN_HTTP_FIXTURES`. It can
+/// be changed at runtime with [set_http_mode](crate::http::set_http_mode).
+///
+/// # Example
+///
+/// ```
+/// use libtmplgen::*;
+///
+/// // Answer all requests with the responses recorded in ./fixtures
+/// set_http_mode(HttpMode::Replay("fixtures".into()));
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HttpMode {
+    /// Send requests over the network
+    Live,
+    /// Send requests over the network and save the responses in the dir
+    Record(PathBuf),
+    /// Answer requests with the responses saved in the dir, without touching the network
+    Replay(PathBuf),
+}
+
+impl HttpMode {
+    /// Reads the mode from `TMPLGEN_HTTP_MODE` and `TMPLGEN_HTTP_FIXTURES`, sending requests over
+    /// the network unless told otherwise. The fixtures default to `src/lib/tests/fixtures`.
+    fn from_env() -> Self {
+        let fixtures = var_os("TMPLGEN_HTTP_FIXTURES")
+            .map(PathBuf::from)
+            .unwrap_or_else(default_fixtures_dir);
+
+        match var("TMPLGEN_HTTP_MODE").as_ref().map(String::as_str) {
+            Ok("record") => HttpMode::Record(fixtures),
+            Ok("replay") => HttpMode::Replay(fixtures),
+            Ok("live") => HttpMode::Live,
+            Ok(mode) if !mode.is_empty() => {
+                log::warn!("Ignoring unknown TMPLGEN_HTTP_MODE '{}'", mode);
+                HttpMode::Live
+            }
+            _ => HttpMode::Live,
+        }
+    }
+}
+
+/// The fixtures recorded for the tests of tmplgen
+fn default_fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lib/tests/fixtures")
+}
+
+/// Sets how libtmplgen talks to the registries, see [HttpMode](crate::http::HttpMode)
+pub fn set_http_mode(mode: HttpMode) {
+    *HTTP_MODE.write().unwrap() = mode;
+}
+
+/// Returns how libtmplgen currently talks to the registries
+pub fn http_mode() -> HttpMode {
+    HTTP_MODE.read().unwrap().clone()
+}
+
+/// The whole response to a GET request
+#[derive(Debug)]
+pub(super) struct Response {
+    pub url: String,
+    pub status: reqwest::StatusCode,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        self.status.is_success()
+    }
+
+    /// Turns an answer other than a 2xx into an Error
+    pub fn error_for_status(self) -> Result<Self, Error> {
+        if self.is_success() {
+            Ok(self)
+        } else {
+            Err(status_error(&self.url, self.status))
+        }
+    }
+
+    /// Deserializes the body of the response as JSON
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        serde_json::from_slice(&self.body).map_err(|e| {
+            ProviderError::parse("Failed to parse the answer")
+                .with_url(&self.url)
+                .with_source(e)
+                .into()
+        })
+    }
+
+    /// Returns the body of the response as text
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// The error for an answer other than a 2xx, a 404 means that what we asked about doesn't exist
+fn status_error(url: &str, status: reqwest::StatusCode) -> Error {
+    let message = format!("The server answered with {}", status);
+
+    let error = if status == reqwest::StatusCode::NOT_FOUND {
+        ProviderError::not_found(message)
+    } else {
+        ProviderError::network(message)
+    };
+
+    error.with_url(url).into()
+}
+
+/// The part of a recorded response that's stored next to its body
+#[derive(Debug, Deserialize, Serialize)]
+struct Fixture {
+    url: String,
+    status: u16,
+}
+
+/// Where the response to a GET request for `url` is stored in `dir`, as `<host>/<hash>.json`
+/// (the url and status) and `<host>/<hash>.body`
+fn fixture_paths(dir: &Path, url: &str) -> (PathBuf, PathBuf) {
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|x| x.host_str().map(|x| x.to_string()))
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let hash = format!("{:x}", Sha256::digest(url.as_bytes()));
+    let base = dir.join(host).join(&hash[..16]);
+
+    (base.with_extension("json"), base.with_extension("body"))
+}
+
+/// Saves `response` in `dir`, so it can be replayed later on
+///
+/// # Errors
+///
+/// * Errors out if the fixture can't be written
+pub(super) fn record(dir: &Path, response: &Response) -> Result<(), Error> {
+    static RECORDING: AtomicUsize = AtomicUsize::new(0);
+
+    let (meta_path, body_path) = fixture_paths(dir, &response.url);
+    let fixture = Fixture {
+        url: response.url.clone(),
+        status: response.status.as_u16(),
+    };
+
+    debug!("Recording {} to {}", response.url, meta_path.display());
+
+    if let Some(parent) = meta_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Tests run in parallel and may record the same URL at the same time, so we write to a
+    // temporary file first and only move complete fixtures into place
+    let tmp_suffix = format!(
+        "{}-{}.tmp",
+        std::process::id(),
+        RECORDING.fetch_add(1, Ordering::SeqCst)
+    );
+    let meta = serde_json::to_vec_pretty(&fixture).map_err(|e| Error::File(e.to_string()))?;
+
+    for (path, contents) in &[(body_path, &response.body[..]), (meta_path, &meta[..])] {
+        let tmp_path = path.with_extension(&tmp_suffix);
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, path)?;
+    }
+
+    Ok(())
+}
+
+/// Loads the response to a GET request for `url` recorded in `dir`
+///
+/// # Errors
+///
+/// * Errors out if no response has been recorded for `url`
+pub(super) fn replay(dir: &Path, url: &str) -> Result<Response, Error> {
+    let (meta_path, body_path) = fixture_paths(dir, url);
+
+    debug!("Replaying {} from {}", url, meta_path.display());
+
+    if !meta_path.exists() {
+        return Err(ProviderError::network(format!(
+            "No response has been recorded in {}, record it with `TMPLGEN_HTTP_MODE=record cargo test`",
+            dir.display()
+        ))
+        .with_url(url)
+        .into());
+    }
+
+    let invalid_fixture =
+        || ProviderError::parse(format!("Invalid fixture {}", meta_path.display()));
+
+    let fixture: Fixture = serde_json::from_slice(&std::fs::read(&meta_path)?)
+        .map_err(|e| invalid_fixture().with_url(url).with_source(e))?;
+
+    if fixture.url != url {
+        return Err(ProviderError::parse(format!(
+            "The fixture {} belongs to {}",
+            meta_path.display(),
+            fixture.url
+        ))
+        .with_url(url)
+        .into());
+    }
+
+    Ok(Response {
+        url: fixture.url,
+        status: reqwest::StatusCode::from_u16(fixture.status)
+            .map_err(|e| invalid_fixture().with_url(url).with_source(e))?,
+        body: std::fs::read(&body_path)?,
+    })
+}
+
+/// Limits how many requests are sent to a registry at the same time, see
+/// [NetworkPolicy](crate::http::NetworkPolicy). Requests that have to wait are started in the
+/// order they were made.
+struct Limiter {
+    state: Mutex<LimiterState>,
+}
+
+struct LimiterState {
+    in_flight: usize,
+    waiting: VecDeque<oneshot::Sender<Permit>>,
+}
+
+/// Allows sending a request until it's dropped
+pub(super) struct Permit {
+    limiter: &'static Limiter,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limiter.release();
+    }
+}
+
+impl Limiter {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(LimiterState {
+                in_flight: 0,
+                waiting: VecDeque::new(),
+            }),
+        }
+    }
+
+    fn acquire(&'static self) -> impl Future<Item = Permit, Error = Error> + Send {
+        let mut state = self.state.lock().unwrap();
+
+        if state.in_flight < network_policy().max_concurrent_requests.max(1) {
+            state.in_flight += 1;
+            Either::A(future::ok(Permit { limiter: self }))
+        } else {
+            let (sender, receiver) = oneshot::channel();
+            state.waiting.push_back(sender);
+            Either::B(receiver.map_err(|_| {
+                Error::from(ProviderError::network(
+                    "The request was cancelled while waiting to be sent",
+                ))
+            }))
+        }
+    }
+
+    fn release(&'static self) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+
+        // Hand permits to the requests that still wait for one, the limit may have been raised
+        while state.in_flight < network_policy().max_concurrent_requests.max(1) {
+            let sender = match state.waiting.pop_front() {
+                Some(sender) => sender,
+                None => break,
+            };
+
+            state.in_flight += 1;
+
+            if let Err(permit) = sender.send(Permit { limiter: self }) {
+                // Dropping the permit would release it again while we hold the lock
+                std::mem::forget(permit);
+                state.in_flight -= 1;
+            }
+        }
+    }
+}
+
+fn limiter(pkg_type: PkgType) -> &'static Limiter {
+    match pkg_type {
+        PkgType::Crate => &*CRATE_LIMITER,
+        PkgType::Gem => &*GEM_LIMITER,
+        PkgType::PerlDist => &*PERLDIST_LIMITER,
+    }
+}
+
+/// The limiter of the registry `url` belongs to
+fn limiter_for(url: &str) -> &'static Limiter {
+    let registries = Registries::get();
+
+    PkgType::all()
+        .iter()
+        .find(|pkg_type| {
+            let registry = registries.registry(**pkg_type);
+
+            url.starts_with(&registry.api) || url.starts_with(&registry.download)
+        })
+        .map(|pkg_type| limiter(*pkg_type))
+        .unwrap_or(&*OTHER_LIMITER)
+}
+
+/// Sends a GET request for `url` and blocks until the answer arrives, or replays the answer
+/// depending on the [HttpMode](crate::http::HttpMode). Transport errors and errors of the server
+/// are retried with an exponential backoff.
+///
+/// # Errors
+///
+/// * Errors out if `url` can't be reached
+/// * Errors out if no response to `url` has been recorded when replaying
+pub(super) fn get(url: &str) -> Result<Response, Error> {
+    match http_mode() {
+        HttpMode::Live => read_response(url, get_live(url)?.0),
+        HttpMode::Record(dir) => {
+            let response = read_response(url, get_live(url)?.0)?;
+            record(&dir, &response)?;
+            Ok(response)
+        }
+        HttpMode::Replay(dir) => replay(&dir, url),
+    }
+}
+
+/// Sends a GET request for `url` over the network, see [get](crate::http::get). The body of the
+/// response is left to the caller, so big downloads don't have to be held in memory. The request
+/// counts against the concurrency limit of its registry until the returned permit is dropped.
+fn get_live(url: &str) -> Result<(reqwest::Response, Permit), Error> {
+    let policy = network_policy();
+    let client = reqwest::Client::builder().timeout(policy.timeout).build()?;
+    let user_agent = user_agent();
+
+    let permit = limiter_for(url).acquire().wait()?;
+    let mut attempt = 0;
+
+    loop {
+        debug!("GET: {}", url);
+
+        let result = client
+            .get(url)
+            .header(reqwest::header::USER_AGENT, user_agent.as_str())
+            .send();
+
+        let delay = match &result {
+            Ok(response) => policy.retry_delay(
+                attempt,
+                Some(response.status()),
+                retry_after(response.headers()),
+            ),
+            Err(_) => policy.retry_delay(attempt, None, None),
+        };
+
+        match delay {
+            Some(delay) => {
+                debug!("Retrying {} in {:?}", url, delay);
+                std::thread::sleep(delay);
+                attempt += 1;
+            }
+            None => {
+                return result.map(|response| (response, permit)).map_err(|e| {
+                    ProviderError::network("Couldn't reach the server")
+                        .with_url(url)
+                        .with_source(e)
+                        .into()
+                })
+            }
+        }
+    }
+}
+
+/// Reads the whole body of `response` to the GET request for `url`
+fn read_response(url: &str, mut response: reqwest::Response) -> Result<Response, Error> {
+    let mut body = Vec::new();
+    response.read_to_end(&mut body)?;
+
+    Ok(Response {
+        url: url.to_string(),
+        status: response.status(),
+        body,
+    })
+}
+
+/// Like [get](crate::http::get), but turns an answer other than a 2xx into an Error
+pub(super) fn get_ok(url: &str) -> Result<Response, Error> {
+    get(url)?.error_for_status()
+}
+
+/// Like [get](crate::http::get), but deserializes the JSON the registry answers with.
+/// `not_found` is returned if the registry doesn't know what we asked it about, other errors
+/// (e.g. an answer we can't parse) name the same package as `not_found`.
+pub(super) fn get_json<T: DeserializeOwned>(url: &str, not_found: Error) -> Result<T, Error> {
+    let response = get(url).map_err(|e| e.for_pkg_of(&not_found))?;
+
+    if response.status == reqwest::StatusCode::NOT_FOUND {
+        return Err(not_found.for_url(url));
+    }
+
+    response
+        .error_for_status()
+        .and_then(|x| x.json())
+        .map_err(|e| e.for_pkg_of(&not_found))
+}
+
+/// A file that's being downloaded, see [download](crate::http::download)
+pub(super) enum Download {
+    /// Streamed from the network, the permit is held until the download is done
+    Live {
+        response: reqwest::Response,
+        _permit: Permit,
+    },
+    /// Already read completely, because it was recorded or replayed
+    Buffered(Cursor<Vec<u8>>),
+}
+
+impl Download {
+    /// The size of the file, if it's known
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            Download::Live { response, .. } => response.content_length(),
+            Download::Buffered(body) => Some(body.get_ref().len() as u64),
+        }
+    }
+}
+
+impl Read for Download {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Download::Live { response, .. } => response.read(buf),
+            Download::Buffered(body) => body.read(buf),
+        }
+    }
+}
+
+/// Starts downloading `url`, see [get](crate::http::get). Unless the download is recorded or
+/// replayed, the file is streamed instead of being held in memory.
+///
+/// # Errors
+///
+/// * Errors out if `url` can't be reached or answers with something other than a 2xx
+pub(super) fn download(url: &str) -> Result<Download, Error> {
+    match http_mode() {
+        HttpMode::Live => {
+            let (response, permit) = get_live(url)?;
+
+            if !response.status().is_success() {
+                return Err(status_error(url, response.status()));
+            }
+
+            Ok(Download::Live {
+                response,
+                _permit: permit,
+            })
+        }
+        _ => Ok(Download::Buffered(Cursor::new(
+            get(url)?.error_for_status()?.body,
+        ))),
+    }
+}
+
+/// Creates the client the async functions of libtmplgen send their requests with
+///
+/// # Errors
+///
+/// * Errors out if the TLS backend can't be initialized
+pub(super) fn async_client() -> Result<Client, Error> {
+    Ok(Client::builder().build()?)
+}
+
+/// Sends a GET request for `url`, which belongs to the registry of `pkg_type`, and reads the whole
+/// response. Failed requests are retried and limited as the
+/// [NetworkPolicy](crate::http::NetworkPolicy) says, requests over the concurrency limit wait
+/// until one of the others is done.
+///
+/// Depending on the [HttpMode](crate::http::HttpMode) the response is recorded or replayed.
+///
+/// Has to be run on a tokio runtime.
+pub(super) fn get_async(client: &Client, pkg_type: PkgType, url: &str) -> BoxFuture<Response> {
+    match http_mode() {
+        HttpMode::Live => get_live_async(client, pkg_type, url),
+        HttpMode::Record(dir) => Box::new(
+            get_live_async(client, pkg_type, url)
+                .and_then(move |response| record(&dir, &response).map(|_| response)),
+        ),
+        HttpMode::Replay(dir) => Box::new(future::result(replay(&dir, url))),
+    }
+}
+
+/// Sends a GET request for `url` over the network, see [get_async](crate::http::get_async)
+fn get_live_async(client: &Client, pkg_type: PkgType, url: &str) -> BoxFuture<Response> {
+    let client = client.clone();
+    let url = url.to_string();
+    let policy = network_policy();
+
+    Box::new(limiter(pkg_type).acquire().and_then(move |permit| {
+        future::loop_fn(0, move |attempt| {
+            debug!("GET: {}", url);
+
+            let response_url = url.clone();
+            let error_url = url.clone();
+
+            let request = client
+                .get(url.as_str())
+                .header(reqwest::header::USER_AGENT, user_agent())
+                .send()
+                .and_then(|response| {
+                    let status = response.status();
+                    let retry_after = retry_after(response.headers());
+
+                    response.into_body().concat2().map(move |body| {
+                        let response = Response {
+                            url: response_url,
+                            status,
+                            body: body.to_vec(),
+                        };
+
+                        (response, retry_after)
+                    })
+                });
+
+            let request = match policy.timeout {
+                Some(timeout) => Either::A(Timeout::new(request, timeout).map_err(|e| {
+                    if e.is_elapsed() {
+                        ProviderError::network("The request timed out")
+                    } else if let Some(e) = e.into_inner() {
+                        ProviderError::network("Couldn't reach the server").with_source(e)
+                    } else {
+                        ProviderError::network("The timer of the request failed")
+                    }
+                })),
+                None => Either::B(request.map_err(|e| {
+                    ProviderError::network("Couldn't reach the server").with_source(e)
+                })),
+            };
+
+            request.then(move |result| {
+                let delay = match &result {
+                    Ok((response, retry_after)) => {
+                        policy.retry_delay(attempt, Some(response.status), *retry_after)
+                    }
+                    Err(_) => policy.retry_delay(attempt, None, None),
+                };
+
+                match delay {
+                    Some(delay) => {
+                        debug!("Retrying in {:?}", delay);
+
+                        Either::A(
+                            Delay::new(Instant::now() + delay)
+                                .map(move |_| Loop::Continue(attempt + 1))
+                                .map_err(|e| {
+                                    Error::from(
+                                        ProviderError::network("The timer of the request failed")
+                                            .with_source(e),
+                                    )
+                                }),
+                        )
+                    }
+                    None => Either::B(future::result(
+                        result
+                            .map(|(response, _)| Loop::Break(response))
+                            .map_err(|e| {
+                                Error::from(e.with_url(&error_url).with_pkg_type(pkg_type))
+                            }),
+                    )),
+                }
+            })
+        })
+        .then(move |result| {
+            drop(permit);
+            result
+        })
+    }))
+}
+
+/// Like [get_async](crate::http::get_async), but deserializes the JSON the registry answers with.
+/// `not_found` is returned if the registry doesn't know what we asked it about, other errors
+/// (e.g. an answer we can't parse) name the same package as `not_found`.
+pub(super) fn get_json_async<T: DeserializeOwned + Send + 'static>(
+    client: &Client,
+    pkg_type: PkgType,
+    url: &str,
+    not_found: Error,
+) -> BoxFuture<T> {
+    Box::new(get_async(client, pkg_type, url).then(move |result| {
+        let response = result.map_err(|e| e.for_pkg_of(&not_found))?;
+
+        if response.status == reqwest::StatusCode::NOT_FOUND {
+            Err(not_found.for_url(&response.url))
+        } else {
+            response
+                .error_for_status()
+                .and_then(|x| x.json())
+                .map_err(|e| e.for_pkg_of(&not_found))
+        }
+    }))
+}
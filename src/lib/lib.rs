@@ -66,12 +66,41 @@
 //! }
 //! ```
 //!
+//! If you're using libtmplgen in async code, (get_info_async)[crate::TmplBuilder::get_info_async]
+//! and (gen_deps_async)[crate::TmplBuilder::gen_deps_async] return futures instead of blocking a
+//! thread per request. They have to be run on a tokio runtime:
+//! ```
+//! use futures::Future;
+//! use libtmplgen::*;
+//!
+//! fn gen_templates() -> Result<Vec<Template>, Error> {
+//!     let mut tmpl_builder = TmplBuilder::new("rspec");
+//!     tmpl_builder.set_type(PkgType::Gem);
+//!
+//!     let mut runtime = tokio::runtime::Runtime::new()?;
+//!
+//!     runtime.block_on(tmpl_builder.get_info_async().and_then(|tmpl_builder| {
+//!         let template = tmpl_builder.generate(true);
+//!         // Templates for the gems rspec depends on
+//!         tmpl_builder.gen_deps_async(None).and_then(move |mut templates| {
+//!             templates.push(template?);
+//!             Ok(templates)
+//!         })
+//!     }))
+//! }
+//! ```
+//!
 //! See [TmplBuilder](crate::types::TmplBuilder) for most of the exciting other stuff.
 
 mod crates;
+mod data;
 mod gems;
 mod helpers;
+mod http;
 mod perldist;
+mod registry;
+mod skeleton;
+mod spdx;
 #[cfg(test)]
 mod tests;
 
@@ -79,6 +108,11 @@ pub mod errors;
 pub mod tmplwriter;
 pub mod types;
 
+pub use crate::data::{data_tables, KnownData};
 pub use crate::errors::*;
+pub use crate::http::{
+    http_mode, network_policy, set_http_mode, set_network_policy, HttpMode, NetworkPolicy,
+};
+pub use crate::registry::{try_load_registries, Registries, Registry};
 pub use crate::tmplwriter::*;
 pub use crate::types::*;
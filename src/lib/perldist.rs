@@ -13,12 +13,18 @@
 //You should have received a copy of the GNU General Public License
 //along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::errors::Error;
+use crate::data::KnownData;
+use crate::errors::{Error, ProviderError};
 use crate::helpers::*;
+use crate::http::*;
+use crate::registry::Registries;
 use crate::types::*;
+use futures::future::{self, Either};
+use futures::Future;
 use log::debug;
 use rayon::prelude::*;
-use retry::retry_exponentially;
+use std::collections::HashMap;
+use std::io::Cursor;
 
 /// Query the metacpan.org API. If `perldist_name` is the name of a perl module, it will query
 /// the perldist instead.
@@ -26,71 +32,347 @@ use retry::retry_exponentially;
 /// # Errors
 /// * Errors out if metacpan.org can't be reached
 /// * Errors out if the perldist (or the module it is the parent of) can't be queried.
-/// * Errors out if `gen_checksum` Errors.
-pub(super) fn perldist_info(perldist_name: &str) -> Result<PkgInfo, Error> {
-    let client = metacpan_api::SyncClient::new();
-
-    let query_result = client.perl_info(&perldist_name);
-
-    // Determine the query result. If the Error is "Not Found" the user either tries to
-    // query an non-existent perldist _or_ it's a module. In that case we want to try to
-    // query the module to get the perldist it belongs to and query the perldist module times.
-    let query_result = match query_result {
-        Ok(query_result) => query_result,
-        Err(query_err) => {
-            // TODO: Properly match this!
-            if query_err.to_string() == "Not found" {
-                match retry_exponentially(
-                    3,
-                    10.0,
-                    &mut || client.perl_info(&client.get_dist(&perldist_name)?),
-                    |result| result.is_ok(),
-                ) {
-                    Ok(response) => response?,
-                    Err(error) => return Err(Error::Gem(error.to_string())),
-                }
-            } else {
-                // If the Error isn't "Not Found" we're most likely dealing with a network error,
-                // so let's retry this a few times
-                match retry_exponentially(
-                    3,
-                    10.0,
-                    &mut || client.perl_info(&perldist_name),
-                    |result| result.is_ok(),
-                ) {
-                    Ok(response) => response?,
-                    Err(error) => return Err(Error::PerlDist(error.to_string())),
+/// * Errors out if `inspect_distfile` Errors.
+/// * Errors out if tmplgen doesn't know about the perl version of `runtime_versions`
+pub(super) fn perldist_info(
+    perldist_name: &str,
+    runtime_versions: &RuntimeVersions,
+) -> Result<PkgInfo, Error> {
+    let release_json = get_release(perldist_name)?;
+
+    debug!("metacpan.org query result: {:?}", release_json);
+
+    let release = perl_release(&release_json)?;
+
+    let native = is_xs_dist(&release.download_url)?;
+
+    let required_versions = parse_required_versions(&release_json);
+    let needed_deps = needed_perldeps(&release.dependencies, &required_versions, runtime_versions)?;
+
+    let dist_names = needed_deps
+        .par_iter()
+        .map(|x| perl_dist_name(&x.module))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let dependencies = order_perldeps(
+        &needed_deps,
+        &dist_names,
+        &required_versions,
+        runtime_versions,
+    )?;
+
+    let distfile_info = inspect_distfile(&release.download_url)?;
+
+    perldist_pkg_info(release, dependencies, native, distfile_info)
+}
+
+/// Gets the latest release of the perldist `perldist_name` from metacpan.org. If `perldist_name`
+/// is the name of a module, the release of the perldist it belongs to is returned instead.
+///
+/// # Errors
+///
+/// * Errors out if metacpan.org can't be reached
+/// * Errors out if neither a perldist nor a module called `perldist_name` exists
+fn get_release(perldist_name: &str) -> Result<serde_json::Value, Error> {
+    let response = get(&release_url(perldist_name))?;
+
+    // If metacpan.org doesn't know the perldist, the user might have given us the name of a
+    // module, so we look up the perldist it belongs to
+    if response.status != reqwest::StatusCode::NOT_FOUND {
+        return response
+            .error_for_status()
+            .and_then(|x| x.json())
+            .map_err(|e| e.for_pkg(PkgType::PerlDist, perldist_name));
+    }
+
+    let dist_name = perl_dist_name(perldist_name)?;
+
+    get_json(&release_url(&dist_name), perldist_not_found(&dist_name))
+}
+
+/// The URL of the latest release of the perldist in the API of the configured metacpan.org
+fn release_url(perldist_name: &str) -> String {
+    Registries::get().api_url(PkgType::PerlDist, &format!("release/{}", perldist_name))
+}
+
+/// The URL of the module in the API of the configured metacpan.org
+fn module_url(module: &str) -> String {
+    Registries::get().api_url(PkgType::PerlDist, &format!("module/{}", module))
+}
+
+/// Gets the name of the perldist from what metacpan.org told us about a module
+fn module_dist_name(module: &str, module_json: &serde_json::Value) -> Result<String, Error> {
+    match module_json["distribution"].as_str() {
+        Some(dist_name) => Ok(dist_name.to_string()),
+        None => Err(ProviderError::parse(format!(
+            "metacpan.org didn't tell us which perldist module {} belongs to",
+            module
+        ))
+        .with_pkg_type(PkgType::PerlDist)
+        .into()),
+    }
+}
+
+/// The Error we return if metacpan.org knows neither a perldist nor a module called `name`
+fn no_such_module(name: &str) -> Error {
+    ProviderError::not_found(
+        "Neither a perldist nor a module with this name exists on metacpan.org",
+    )
+    .with_pkg(PkgType::PerlDist, name)
+    .into()
+}
+
+/// The Error we return if metacpan.org doesn't know the perldist `dist_name`
+fn perldist_not_found(dist_name: &str) -> Error {
+    ProviderError::not_found("The perldist doesn't exist on metacpan.org")
+        .with_pkg(PkgType::PerlDist, dist_name)
+        .into()
+}
+
+/// Like [perldist_info](crate::perldist::perldist_info), but queries metacpan.org with `client`
+/// without blocking
+pub(super) fn perldist_info_async(
+    client: &reqwest::r#async::Client,
+    perldist_name: &str,
+    runtime_versions: &RuntimeVersions,
+) -> BoxFuture<PkgInfo> {
+    let client = client.clone();
+    let runtime_versions = runtime_versions.clone();
+
+    Box::new(
+        get_release_async(&client, perldist_name).and_then(move |release_json| {
+            debug!("metacpan.org query result: {:?}", release_json);
+
+            let release = match perl_release(&release_json) {
+                Ok(release) => release,
+                Err(e) => return Either::A(future::err(e)),
+            };
+            let required_versions = parse_required_versions(&release_json);
+            let needed_deps =
+                match needed_perldeps(&release.dependencies, &required_versions, &runtime_versions)
+                {
+                    Ok(needed_deps) => needed_deps,
+                    Err(e) => return Either::A(future::err(e)),
+                };
+
+            let dist_names = future::join_all(
+                needed_deps
+                    .iter()
+                    .map(|x| perl_dist_name_async(&client, &x.module))
+                    .collect::<Vec<_>>(),
+            )
+            .and_then(move |dist_names| {
+                order_perldeps(
+                    &needed_deps,
+                    &dist_names,
+                    &required_versions,
+                    &runtime_versions,
+                )
+            });
+
+            let distfile_info = get_async(
+                &client,
+                PkgType::PerlDist,
+                &Registries::get().download_url(&release.download_url),
+            )
+            .and_then(|response| {
+                inspect_distfile_source(Cursor::new(response.error_for_status()?.body))
+            });
+
+            Either::B(
+                dist_names
+                    .join3(
+                        is_xs_dist_async(&client, &release.download_url),
+                        distfile_info,
+                    )
+                    .and_then(move |(dependencies, native, distfile_info)| {
+                        perldist_pkg_info(release, dependencies, native, distfile_info)
+                    }),
+            )
+        }),
+    )
+}
+
+/// Gets the latest release of the perldist `perldist_name` from metacpan.org. If `perldist_name`
+/// is the name of a module, the release of the perldist it belongs to is returned instead.
+fn get_release_async(
+    client: &reqwest::r#async::Client,
+    perldist_name: &str,
+) -> BoxFuture<serde_json::Value> {
+    let client = client.clone();
+    let perldist_name = perldist_name.to_string();
+
+    Box::new(
+        get_async(&client, PkgType::PerlDist, &release_url(&perldist_name)).and_then(
+            move |response| {
+                if response.status != reqwest::StatusCode::NOT_FOUND {
+                    return Either::A(future::result(
+                        response
+                            .error_for_status()
+                            .and_then(|x| x.json())
+                            .map_err(|e| e.for_pkg(PkgType::PerlDist, &perldist_name)),
+                    ));
                 }
+
+                let release_client = client.clone();
+
+                Either::B(perl_dist_name_async(&client, &perldist_name).and_then(
+                    move |dist_name| {
+                        get_json_async(
+                            &release_client,
+                            PkgType::PerlDist,
+                            &release_url(&dist_name),
+                            perldist_not_found(&dist_name),
+                        )
+                    },
+                ))
+            },
+        ),
+    )
+}
+
+/// Gets the name of the perldist the module `module` belongs to without blocking, see
+/// [perl_dist_name](crate::perldist::perl_dist_name)
+fn perl_dist_name_async(client: &reqwest::r#async::Client, module: &str) -> BoxFuture<String> {
+    let module = module.to_string();
+
+    Box::new(
+        get_json_async::<serde_json::Value>(
+            client,
+            PkgType::PerlDist,
+            &module_url(&module),
+            no_such_module(&module),
+        )
+        .and_then(move |module_json| module_dist_name(&module, &module_json)),
+    )
+}
+
+/// Like [is_xs_dist](crate::perldist::is_xs_dist), but without blocking
+fn is_xs_dist_async(client: &reqwest::r#async::Client, download_url: &str) -> BoxFuture<bool> {
+    let (manifest_url, search_url) = match xs_dist_urls(download_url) {
+        Ok(urls) => urls,
+        Err(e) => return Box::new(future::err(e)),
+    };
+    let client = client.clone();
+
+    Box::new(
+        get_async(&client, PkgType::PerlDist, &manifest_url).and_then(move |response| {
+            if response.is_success() {
+                return Either::A(future::ok(manifest_has_xs(&String::from_utf8_lossy(
+                    &response.body,
+                ))));
             }
-        }
+
+            debug!(
+                "{} doesn't exist, searching for XS files instead",
+                manifest_url
+            );
+
+            Either::B(
+                get_async(&client, PkgType::PerlDist, &search_url).and_then(|response| {
+                    let search_result: serde_json::Value = response.error_for_status()?.json()?;
+
+                    Ok(search_has_hits(&search_result))
+                }),
+            )
+        }),
+    )
+}
+
+/// Reads the release of a perldist from the JSON metacpan.org answered with
+///
+/// # Errors
+///
+/// * Errors out if the release doesn't have a name, version or download URL
+pub(super) fn perl_release(release: &serde_json::Value) -> Result<PerlRelease, Error> {
+    let field = |name: &str| match &release[name] {
+        serde_json::Value::String(value) => Ok(value.clone()),
+        // metacpan sometimes returns versions as numbers
+        serde_json::Value::Number(value) => Ok(value.to_string()),
+        _ => Err(ProviderError::parse(format!(
+            "metacpan.org didn't tell us the {} of the release",
+            name
+        ))
+        .with_pkg_type(PkgType::PerlDist)),
     };
 
-    debug!("metacpan.org query result: {:?}", query_result);
+    Ok(PerlRelease {
+        name: field("distribution")?,
+        version: field("version")?,
+        download_url: field("download_url")?,
+        homepage: release["resources"]["homepage"]
+            .as_str()
+            .map(|x| x.to_string()),
+        description: release["abstract"].as_str().map(|x| x.to_string()),
+        license: release["license"].as_array().map(|x| {
+            x.iter()
+                .filter_map(|x| x.as_str())
+                .map(|x| x.to_string())
+                .collect()
+        }),
+        dependencies: release["dependency"]
+            .as_array()
+            .unwrap_or(&Vec::new())
+            .iter()
+            .filter_map(|x| {
+                Some(PerlDependency {
+                    module: x["module"].as_str()?.to_string(),
+                    phase: x["phase"].as_str()?.to_string(),
+                })
+            })
+            .collect(),
+    })
+}
 
-    let download_url = query_result.download_url.replace(
-        &query_result.version.as_str().unwrap_or_default(),
-        "${version}",
-    );
+/// Puts together the PkgInfo of the perldist from what metacpan.org told us about its release,
+/// its dependencies (see [order_perldeps](crate::perldist::order_perldeps)) and what we found
+/// in its distfile
+///
+/// # Errors
+///
+/// * Errors out if the native dependencies can't be determined
+pub(super) fn perldist_pkg_info(
+    release: PerlRelease,
+    mut dependencies: Dependencies,
+    native: bool,
+    distfile_info: DistfileInfo,
+) -> Result<PkgInfo, Error> {
+    let download_url = release.download_url.replace(&release.version, "${version}");
+
+    // XS perldists compile C code, so they need perl (and maybe some C libraries) in makedepends
+    if native {
+        debug!("Perldist {} contains XS code", &release.name);
+
+        let mut make_vec = vec!["perl".to_string()];
+        make_vec.append(&mut dependencies.make.unwrap_or_default());
+
+        if let Some(native_deps) = check_native_deps(&release.name, PkgType::PerlDist, None)? {
+            make_vec.append(&mut native_deps.make.unwrap_or_default());
+        }
+
+        dependencies.make = Some(make_vec);
+    }
 
     let pkg_info = PkgInfo {
-        pkg_name: format!("perl-{}", &query_result.name),
-        version: query_result
-            .version
-            .as_str()
-            .unwrap_or_default()
-            .to_string(),
-        homepage: query_result
-            .resources
-            .homepage
-            .clone()
-            .unwrap_or_else(|| format!("https://metacpan.org/pod/{}", query_result.name)),
-        description: query_result.description,
-        license: query_result.license,
-        dependencies: Some(order_perldeps(
-            &query_result.dependency.unwrap_or_default(),
-        )?),
-        sha: gen_checksum(&query_result.download_url)?,
+        pkg_name: format!("perl-{}", &release.name),
+        version: release.version,
+        homepage: match release.homepage {
+            Some(homepage) => homepage,
+            None => format!("https://metacpan.org/pod/{}", release.name),
+        },
+        description: release.description,
+        license: release.license,
+        dependencies: Some(dependencies),
+        sha: distfile_info.sha,
         download_url: Some(download_url),
+        native,
+        license_files: distfile_info.license_files,
+        // perl-module installs the man pages generated from the pods to usr/share/man
+        subpackages: doc_subpackage(distfile_info.doc_size, &["usr/share/man"])
+            .into_iter()
+            .collect(),
+        make_install_args: None,
+        wrksrc: None,
     };
 
     debug!("All pkg related info: {:?}", pkg_info);
@@ -98,62 +380,287 @@ pub(super) fn perldist_info(perldist_name: &str) -> Result<PkgInfo, Error> {
     Ok(pkg_info)
 }
 
-fn order_perldeps(dep_vec: &[metacpan_api::PerlDep]) -> Result<Dependencies, Error> {
-    let client = metacpan_api::SyncClient::new();
+/// Checks if the perldist downloaded from `download_url` contains XS code by looking for `.xs`
+/// files in its MANIFEST. If the release doesn't ship a MANIFEST we search metacpan's file listing
+/// for `.xs` files instead.
+///
+/// # Errors
+///
+/// * Errors out if the author and release can't be determined from `download_url`
+/// * Errors out if metacpan.org can't be reached
+fn is_xs_dist(download_url: &str) -> Result<bool, Error> {
+    let (manifest_url, search_url) = xs_dist_urls(download_url)?;
 
-    let make_vec: Result<Vec<String>, Error> = dep_vec
-        .par_iter()
-        .filter(|&x| {
-            !TmplBuilder::new(&x.module)
-                .set_type(PkgType::PerlDist)
-                .is_built_in()
-                .unwrap_or(false)
-        })
-        .filter(|&x| x.phase == "configure")
-        .map(|x| {
-            let query_result = client.perl_info(&x.module);
-
-            let result = match query_result {
-                Ok(query_result) => query_result,
-                Err(_) => client.perl_info(
-                    &client
-                        .get_dist(&x.module)
-                        .map_err(|e| Error::PerlDist(e.to_string()))?,
-                )?,
-            };
+    let manifest_resp = get(&manifest_url)?;
 
-            Ok(result.name)
-        })
-        .collect();
+    if manifest_resp.is_success() {
+        return Ok(manifest_has_xs(&manifest_resp.text()));
+    }
 
-    let run_vec: Result<Vec<String>, Error> = dep_vec
-        .par_iter()
-        .filter(|&x| {
-            !TmplBuilder::new(&x.module)
-                .set_type(PkgType::PerlDist)
-                .is_built_in()
-                .unwrap_or(false)
-        })
-        .filter(|&x| x.phase == "runtime")
-        .map(|x| {
-            let query_result = client.perl_info(&x.module);
-
-            let result = match query_result {
-                Ok(query_result) => query_result,
-                Err(_) => client.perl_info(
-                    &client
-                        .get_dist(&x.module)
-                        .map_err(|e| Error::PerlDist(e.to_string()))?,
-                )?,
+    debug!(
+        "{} doesn't exist, searching for XS files instead",
+        manifest_url
+    );
+
+    let search_result: serde_json::Value = get_ok(&search_url)?.json()?;
+
+    Ok(search_has_hits(&search_result))
+}
+
+/// Whether a search on metacpan.org found anything. Elasticsearch 7+ (which metacpan.org runs)
+/// answers with `"total": { "value": n, .. }` instead of `"total": n`.
+pub(super) fn search_has_hits(search_result: &serde_json::Value) -> bool {
+    let hits = &search_result["hits"];
+
+    let total = match &hits["total"] {
+        serde_json::Value::Object(total) => total.get("value").and_then(|x| x.as_u64()),
+        total => total.as_u64(),
+    };
+
+    total.unwrap_or(0) > 0
+        || hits["hits"]
+            .as_array()
+            .map(|x| !x.is_empty())
+            .unwrap_or(false)
+}
+
+/// Determines the URL of the MANIFEST of the release `download_url` belongs to and the URL that
+/// searches for its `.xs` files, see [is_xs_dist](crate::perldist::is_xs_dist)
+///
+/// # Errors
+///
+/// * Errors out if the author and release can't be determined from `download_url`
+pub(super) fn xs_dist_urls(download_url: &str) -> Result<(String, String), Error> {
+    // download_url looks like https://cpan.metacpan.org/authors/id/E/ET/ETHER/Moose-2.2011.tar.gz
+    let url_parts = download_url.rsplit('/').collect::<Vec<&str>>();
+
+    if url_parts.len() < 2 {
+        return Err(
+            ProviderError::parse("Can't determine author and release of the distfile")
+                .with_pkg_type(PkgType::PerlDist)
+                .with_url(download_url)
+                .into(),
+        );
+    }
+
+    let release = url_parts[0]
+        .trim_end_matches(".tar.gz")
+        .trim_end_matches(".tar.bz2")
+        .trim_end_matches(".tgz")
+        .trim_end_matches(".zip");
+    let author = url_parts[1];
+
+    let registries = Registries::get();
+
+    Ok((
+        registries.api_url(
+            PkgType::PerlDist,
+            &format!("source/{}/{}/MANIFEST", author, release),
+        ),
+        registries.api_url(
+            PkgType::PerlDist,
+            &format!(
+                "file/_search?q=author:{}%20AND%20release:{}%20AND%20name:*.xs&size=0",
+                author, release
+            ),
+        ),
+    ))
+}
+
+/// Checks if the MANIFEST of a perldist lists `.xs` files
+pub(super) fn manifest_has_xs(manifest: &str) -> bool {
+    manifest
+        .lines()
+        .filter_map(|x| x.split_whitespace().next())
+        .any(|x| x.ends_with(".xs"))
+}
+
+/// Searches metacpan.org for the latest releases of perldists matching `query`, returning at most
+/// `limit` of them. A perldist whose name matches `query` exactly is ranked first.
+///
+/// # Errors
+///
+/// * Errors out if metacpan.org can't be reached
+pub(super) fn search_perldists(query: &str, limit: usize) -> Result<Vec<SearchResult>, Error> {
+    // Module names like Foo::Bar are distributed as Foo-Bar
+    let dist_name = query.replace("::", "-");
+    let size = limit.to_string();
+    let url = reqwest::Url::parse_with_params(
+        &Registries::get().api_url(PkgType::PerlDist, "release/_search"),
+        &[
+            (
+                "q",
+                format!(
+                    "status:latest AND (distribution:\"{0}\"^10 OR distribution:*{0}*)",
+                    dist_name
+                )
+                .as_str(),
+            ),
+            ("size", size.as_str()),
+            ("_source", "distribution,version,abstract"),
+        ],
+    )?;
+
+    let search_result: serde_json::Value = get_ok(url.as_str())?.json()?;
+
+    Ok(search_result["hits"]["hits"]
+        .as_array()
+        .unwrap_or(&Vec::new())
+        .iter()
+        .filter_map(|hit| {
+            let source = &hit["_source"];
+
+            // Versions can be both strings and numbers
+            let version = match &source["version"] {
+                serde_json::Value::String(version) => version.clone(),
+                serde_json::Value::Number(version) => version.to_string(),
+                _ => return None,
             };
 
-            Ok(result.name)
+            Some(SearchResult {
+                name: source["distribution"].as_str()?.to_string(),
+                version,
+                description: source["abstract"].as_str().map(|x| x.trim().to_string()),
+                pkg_type: PkgType::PerlDist,
+                template_exists: false,
+            })
         })
-        .collect();
+        .collect())
+}
+
+/// Filters the configure and runtime dependencies of a perldist which aren't built into the perl
+/// version of `runtime_versions` in the version `required_versions` requires
+///
+/// # Errors
+///
+/// * Errors out if tmplgen doesn't know about the perl version of `runtime_versions`
+pub(super) fn needed_perldeps(
+    dep_vec: &[PerlDependency],
+    required_versions: &HashMap<String, String>,
+    runtime_versions: &RuntimeVersions,
+) -> Result<Vec<PerlDependency>, Error> {
+    let known_data = KnownData::get();
+    let mut needed = Vec::new();
+
+    for dep in dep_vec
+        .iter()
+        .filter(|x| x.phase == "configure" || x.phase == "runtime")
+    {
+        if !known_data.is_built_in(
+            &dep.module,
+            PkgType::PerlDist,
+            required_versions.get(&dep.module).map(|x| x.as_str()),
+            runtime_versions,
+        )? {
+            needed.push(dep.clone());
+        }
+    }
+
+    Ok(needed)
+}
+
+/// Gets the name of the perldist the module `module` belongs to
+///
+/// # Errors
+///
+/// * Errors out if metacpan.org can't be reached
+/// * Errors out if the module can't be found on metacpan.org
+fn perl_dist_name(module: &str) -> Result<String, Error> {
+    let module_json = get_json(&module_url(module), no_such_module(module))?;
+
+    module_dist_name(module, &module_json)
+}
+
+/// Sorts the dependencies `needed_deps` of a perldist (see
+/// [needed_perldeps](crate::perldist::needed_perldeps)), which belong to the perldists
+/// `dist_names`, into make and run dependencies. Modules that are built into perl in a too old
+/// version are added as `{dist}>={required_version}`.
+///
+/// # Errors
+///
+/// * Errors out if tmplgen doesn't know about the perl version of `runtime_versions`
+pub(super) fn order_perldeps(
+    needed_deps: &[PerlDependency],
+    dist_names: &[String],
+    required_versions: &HashMap<String, String>,
+    runtime_versions: &RuntimeVersions,
+) -> Result<Dependencies, Error> {
+    let known_data = KnownData::get();
+    let perl_version = runtime_versions.perl.as_ref().map(|x| x.as_str());
+
+    let mut make_vec = Vec::new();
+    let mut run_vec = Vec::new();
+
+    for (dep, dist_name) in needed_deps.iter().zip(dist_names) {
+        // The version shipped with perl is too old, so we need the newer one from CPAN
+        let dep_string = match required_versions.get(&dep.module) {
+            Some(required_version)
+                if known_data
+                    .perl_core_version(&dep.module, perl_version)?
+                    .is_some() =>
+            {
+                format!("{}>={}", dist_name, required_version)
+            }
+            _ => dist_name.clone(),
+        };
+
+        if dep.phase == "configure" {
+            make_vec.push(dep_string);
+        } else {
+            run_vec.push(dep_string);
+        }
+    }
 
     Ok(Dependencies {
         host: Some(vec!["perl".to_string()]),
-        make: Some(make_vec?),
-        run: Some(run_vec?),
+        make: Some(make_vec),
+        run: Some(run_vec),
     })
 }
+
+/// Gets the versions of the modules the release `release` (as returned by metacpan.org)
+/// requires, e.g. `Test::More` = `0.88`
+pub(super) fn parse_required_versions(release: &serde_json::Value) -> HashMap<String, String> {
+    let mut required_versions = HashMap::new();
+
+    for dep in release["dependency"].as_array().unwrap_or(&Vec::new()) {
+        // Versions can be both strings and numbers
+        let version = match &dep["version"] {
+            serde_json::Value::String(version) => version.clone(),
+            serde_json::Value::Number(version) => version.to_string(),
+            _ => continue,
+        };
+
+        if let Some(module) = dep["module"].as_str() {
+            // Several phases can require different versions, we have to satisfy the highest one
+            let current = required_versions
+                .entry(module.to_string())
+                .or_insert_with(|| version.clone());
+
+            if numify_perl_version(&version) > numify_perl_version(current) {
+                *current = version;
+            }
+        }
+    }
+
+    required_versions
+}
+
+/// Converts a perl version (e.g. `1.302190`, `v1.2.3` or `1.2.3`) to its decimal form (e.g.
+/// `1.002003`), so versions can be compared. Unparsable versions are treated as `0`.
+pub(super) fn numify_perl_version(version: &str) -> f64 {
+    let version = version.trim();
+    let is_dotted = version.starts_with('v') || version.matches('.').count() > 1;
+    let version = version.trim_start_matches('v');
+
+    if is_dotted {
+        version
+            .replace('_', ".")
+            .split('.')
+            .enumerate()
+            .map(|(i, x)| x.parse::<f64>().unwrap_or(0.0) / 1000_f64.powi(i as i32))
+            .sum()
+    } else {
+        version.replace('_', "").parse::<f64>().unwrap_or(0.0)
+    }
+}
@@ -0,0 +1,292 @@
+//This file is part of tmplgen
+//
+//tmplgen is free software: you can redistribute it and/or modify
+//it under the terms of the GNU General Public License as published by
+//the Free Software Foundation, either version 3 of the License, or
+//(at your option) any later version.
+//
+//tmplgen is distributed in the hope that it will be useful,
+//but WITHOUT ANY WARRANTY; without even the implied warranty of
+//MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//GNU General Public License for more details.
+//
+//You should have received a copy of the GNU General Public License
+//along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::data::config_dirs;
+use crate::errors::Error;
+use crate::types::*;
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use std::env::var;
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    static ref REGISTRIES: Registries = load_registries();
+}
+
+/// The endpoints of the registry of a provider
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Registry {
+    /// The base URL of the API of the registry, e.g. `https://crates.io/api/v1`
+    pub api: String,
+    /// The base URL packages are downloaded from, e.g. `https://static.crates.io/crates`
+    pub download: String,
+}
+
+/// The endpoints tmplgen queries the registries of all providers at. By default these are the
+/// official registries, but they can be pointed at mirrors (or mock servers) via
+/// `registries.toml` in the config dirs (`$XDG_CONFIG_HOME/tmplgen` and `$XBPS_DISTDIR/.tmplgen`)
+/// and the environment variables `TMPLGEN_{CRATE,GEM,PERLDIST}_{API,DOWNLOAD}`, e.g.
+///
+/// ```toml
+/// [crate]
+/// api = "http://localhost:8080/api/v1"
+/// download = "http://localhost:8080/crates"
+/// ```
+///
+/// The distfiles written to templates always point at the official registries, only the
+/// downloads tmplgen does itself go to the configured ones.
+///
+/// # Example
+///
+/// ```
+/// use libtmplgen::*;
+///
+/// let registries = Registries::get();
+///
+/// println!("Querying crates.io at {}", registries.registry(PkgType::Crate).api);
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Registries {
+    crate_registry: Registry,
+    gem_registry: Registry,
+    perldist_registry: Registry,
+}
+
+impl Default for Registries {
+    fn default() -> Self {
+        Self {
+            crate_registry: Registry {
+                api: "https://crates.io/api/v1".to_string(),
+                download: "https://static.crates.io/crates".to_string(),
+            },
+            gem_registry: Registry {
+                api: "https://rubygems.org/api".to_string(),
+                download: "https://rubygems.org/downloads".to_string(),
+            },
+            perldist_registry: Registry {
+                api: "https://fastapi.metacpan.org/v1".to_string(),
+                download: "https://cpan.metacpan.org".to_string(),
+            },
+        }
+    }
+}
+
+impl Registries {
+    /// Returns the registries that are configured, they're loaded once on first use. Invalid
+    /// configuration files are warned about and ignored, use
+    /// [try_load_registries](crate::registry::try_load_registries) to get an Error instead.
+    pub fn get() -> &'static Self {
+        &REGISTRIES
+    }
+
+    /// Returns the registry of `pkg_type`
+    pub fn registry(&self, pkg_type: PkgType) -> &Registry {
+        match pkg_type {
+            PkgType::Crate => &self.crate_registry,
+            PkgType::Gem => &self.gem_registry,
+            PkgType::PerlDist => &self.perldist_registry,
+        }
+    }
+
+    fn registry_mut(&mut self, pkg_type: PkgType) -> &mut Registry {
+        match pkg_type {
+            PkgType::Crate => &mut self.crate_registry,
+            PkgType::Gem => &mut self.gem_registry,
+            PkgType::PerlDist => &mut self.perldist_registry,
+        }
+    }
+
+    /// Returns the URL of `path` (e.g. `crates/tmplgen`) in the API of the registry of `pkg_type`
+    pub fn api_url(&self, pkg_type: PkgType, path: &str) -> String {
+        format!("{}/{}", self.registry(pkg_type).api, path)
+    }
+
+    /// Returns the URL `url` (which points at one of the official registries) has to be
+    /// downloaded from. URLs of distfiles which aren't hosted by a registry (e.g. on GitHub) are
+    /// returned unchanged.
+    pub fn download_url(&self, url: &str) -> String {
+        let default = Self::default();
+
+        for pkg_type in PkgType::all() {
+            let default_download = &default.registry(*pkg_type).download;
+
+            if url.starts_with(&format!("{}/", default_download)) {
+                return format!(
+                    "{}{}",
+                    self.registry(*pkg_type).download,
+                    &url[default_download.len()..]
+                );
+            }
+        }
+
+        url.to_string()
+    }
+
+    /// Overrides the endpoints of the registries with the ones set in `overlay`
+    pub(super) fn merge(&mut self, overlay: TomlRegistries) {
+        for (pkg_type, overlay) in vec![
+            (PkgType::Crate, overlay.crate_registry),
+            (PkgType::Gem, overlay.gem),
+            (PkgType::PerlDist, overlay.perldist),
+        ] {
+            let registry = self.registry_mut(pkg_type);
+
+            if let Some(api) = overlay.api {
+                registry.api = api.trim_end_matches('/').to_string();
+            }
+            if let Some(download) = overlay.download {
+                registry.download = download.trim_end_matches('/').to_string();
+            }
+        }
+    }
+}
+
+/// The name of the provider in `registries.toml` and the environment variables
+fn config_name(pkg_type: PkgType) -> &'static str {
+    match pkg_type {
+        PkgType::Crate => "crate",
+        PkgType::Gem => "gem",
+        PkgType::PerlDist => "perldist",
+    }
+}
+
+/// Returns the paths of the user-supplied registry configuration, `registries.toml` in each of
+/// the [config_dirs](crate::data::config_dirs), in order of increasing precedence
+pub(super) fn registries_paths() -> Vec<PathBuf> {
+    config_dirs()
+        .into_iter()
+        .map(|x| x.join("registries.toml"))
+        .collect()
+}
+
+/// Reads the registry configuration at `path`, returns None if the file doesn't exist
+///
+/// # Errors
+///
+/// * Errors out if the file can't be read
+/// * Errors out if the file isn't valid TOML, doesn't match the layout of `registries.toml` or
+///   contains invalid URLs
+pub(super) fn read_registries_file(path: &Path) -> Result<Option<TomlRegistries>, Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    debug!("Reading registries from {}", path.display());
+
+    let registries_string = std::fs::read_to_string(path)?;
+
+    let registries: TomlRegistries = toml::from_str(&registries_string)
+        .map_err(|e| Error::Registry(format!("{}: {}", path.display(), e)))?;
+
+    validate_registries(&registries)
+        .map_err(|e| Error::Registry(format!("{}: {}", path.display(), e)))?;
+
+    Ok(Some(registries))
+}
+
+/// Reads the registry configuration from the environment variables
+/// `TMPLGEN_{CRATE,GEM,PERLDIST}_{API,DOWNLOAD}`
+///
+/// # Errors
+///
+/// * Errors out if one of the variables is set to an invalid URL
+fn read_registries_env() -> Result<TomlRegistries, Error> {
+    let env_var = |pkg_type: PkgType, endpoint: &str| {
+        var(format!(
+            "TMPLGEN_{}_{}",
+            config_name(pkg_type).to_uppercase(),
+            endpoint
+        ))
+        .ok()
+        .filter(|x| !x.is_empty())
+    };
+
+    let toml_registry = |pkg_type: PkgType| TomlRegistry {
+        api: env_var(pkg_type, "API"),
+        download: env_var(pkg_type, "DOWNLOAD"),
+    };
+
+    let registries = TomlRegistries {
+        crate_registry: toml_registry(PkgType::Crate),
+        gem: toml_registry(PkgType::Gem),
+        perldist: toml_registry(PkgType::PerlDist),
+    };
+
+    validate_registries(&registries)
+        .map_err(|e| Error::Registry(format!("environment variables: {}", e)))?;
+
+    Ok(registries)
+}
+
+/// Checks that all endpoints in `registries` are valid URLs
+///
+/// # Errors
+///
+/// * Errors out with the first invalid URL
+fn validate_registries(registries: &TomlRegistries) -> Result<(), Error> {
+    for registry in &[
+        &registries.crate_registry,
+        &registries.gem,
+        &registries.perldist,
+    ] {
+        for url in registry.api.iter().chain(registry.download.iter()) {
+            reqwest::Url::parse(url)
+                .map_err(|e| Error::Registry(format!("Invalid URL '{}': {}", url, e)))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the official registries overridden by the user-supplied configuration (see
+/// [registries_paths](crate::registry::registries_paths)) and the environment variables, which
+/// take precedence over the files
+///
+/// # Errors
+///
+/// * Errors out if one of the user-supplied files or environment variables is invalid
+pub fn try_load_registries() -> Result<Registries, Error> {
+    let mut registries = Registries::default();
+
+    for path in registries_paths() {
+        if let Some(overlay) = read_registries_file(&path)? {
+            registries.merge(overlay);
+        }
+    }
+
+    registries.merge(read_registries_env()?);
+
+    Ok(registries)
+}
+
+/// Like `try_load_registries`, but warns about and ignores invalid configuration
+fn load_registries() -> Registries {
+    let mut registries = Registries::default();
+
+    for path in registries_paths() {
+        match read_registries_file(&path) {
+            Ok(Some(overlay)) => registries.merge(overlay),
+            Ok(None) => {}
+            Err(e) => warn!("Ignoring invalid registry configuration: {}", e),
+        }
+    }
+
+    match read_registries_env() {
+        Ok(overlay) => registries.merge(overlay),
+        Err(e) => warn!("Ignoring invalid registry configuration: {}", e),
+    }
+
+    registries
+}
@@ -0,0 +1,301 @@
+//This file is part of tmplgen
+//
+//tmplgen is free software: you can redistribute it and/or modify
+//it under the terms of the GNU General Public License as published by
+//the Free Software Foundation, either version 3 of the License, or
+//(at your option) any later version.
+//
+//tmplgen is distributed in the hope that it will be useful,
+//but WITHOUT ANY WARRANTY; without even the implied warranty of
+//MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//GNU General Public License for more details.
+//
+//You should have received a copy of the GNU General Public License
+//along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::data::config_dirs;
+use crate::errors::Error;
+use crate::types::*;
+use log::debug;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Returns the skeleton tmplgen ships for templates of `pkg_type`
+pub(super) fn default_skeleton(pkg_type: PkgType) -> &'static str {
+    match pkg_type {
+        PkgType::Crate => include_str!("skeletons/crate.in"),
+        PkgType::Gem => include_str!("skeletons/gem.in"),
+        PkgType::PerlDist => include_str!("skeletons/perldist.in"),
+    }
+}
+
+/// Returns the paths of the user-supplied skeletons for templates of `pkg_type`, e.g.
+/// `skeletons/crate.in` in each of the [config_dirs](crate::data::config_dirs), in order of
+/// increasing precedence
+pub(super) fn skeleton_paths(pkg_type: PkgType) -> Vec<PathBuf> {
+    let file_name = match pkg_type {
+        PkgType::Crate => "crate.in",
+        PkgType::Gem => "gem.in",
+        PkgType::PerlDist => "perldist.in",
+    };
+
+    config_dirs()
+        .into_iter()
+        .map(|x| x.join("skeletons").join(file_name))
+        .collect()
+}
+
+/// Returns the skeleton for templates of `pkg_type`: the user-supplied one with the highest
+/// precedence (see [skeleton_paths](crate::skeleton::skeleton_paths)) or the default one
+///
+/// # Errors
+///
+/// * Errors out if a user-supplied skeleton exists, but can't be read
+pub(super) fn load_skeleton(pkg_type: PkgType) -> Result<String, Error> {
+    match skeleton_paths(pkg_type)
+        .into_iter()
+        .filter(|x| x.exists())
+        .last()
+    {
+        Some(path) => {
+            debug!("Using the template skeleton {}", path.display());
+
+            std::fs::read_to_string(&path)
+                .map_err(|e| Error::Skeleton(format!("Can't read {}: {}", path.display(), e)))
+        }
+        None => Ok(default_skeleton(pkg_type).to_string()),
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Text(String),
+    Var(String),
+    Tag(String),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Node {
+    Text(String),
+    Var(String),
+    If {
+        name: String,
+        negated: bool,
+        then_nodes: Vec<Node>,
+        else_nodes: Vec<Node>,
+    },
+    For {
+        item: String,
+        list: String,
+        body: Vec<Node>,
+    },
+}
+
+/// Splits `skeleton` into text, `{{ variable }}`s and `{% tag %}`s. Tags that are on a line of
+/// their own swallow that line, so they don't leave empty lines behind.
+fn tokenize(skeleton: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut rest = skeleton;
+    // Whether `rest` starts at the beginning of a line
+    let mut at_line_begin = true;
+
+    while let Some(start) = rest.find("{{").into_iter().chain(rest.find("{%")).min() {
+        let mut text = rest[..start].to_string();
+        let is_tag = rest[start..].starts_with("{%");
+        let closing = if is_tag { "%}" } else { "}}" };
+
+        // The closing delimiter can't overlap with the opening one, e.g. in `{%}`
+        let end = match rest[start + 2..].find(closing) {
+            Some(end) => start + 2 + end,
+            None => {
+                return Err(Error::Skeleton(format!(
+                    "Missing '{}' after '{}'",
+                    closing,
+                    rest[start..].lines().next().unwrap_or_default()
+                )));
+            }
+        };
+
+        let inner = rest[start + 2..end].trim().to_string();
+        rest = &rest[end + 2..];
+
+        if is_tag {
+            let line_start = text.rfind('\n').map(|x| x + 1).unwrap_or(0);
+            let at_line_start =
+                text[line_start..].trim().is_empty() && (line_start > 0 || at_line_begin);
+            let line_end = rest.find('\n');
+            let at_line_end = rest[..line_end.unwrap_or_else(|| rest.len())]
+                .trim()
+                .is_empty();
+
+            if at_line_start && at_line_end {
+                text.truncate(line_start);
+                rest = &rest[line_end.map(|x| x + 1).unwrap_or_else(|| rest.len())..];
+                at_line_begin = true;
+            } else {
+                at_line_begin = false;
+            }
+        } else {
+            at_line_begin = false;
+        }
+
+        if !text.is_empty() {
+            tokens.push(Token::Text(text));
+        }
+
+        tokens.push(if is_tag {
+            Token::Tag(inner)
+        } else {
+            Token::Var(inner)
+        });
+    }
+
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest.to_string()));
+    }
+
+    Ok(tokens)
+}
+
+/// Parses tokens into nodes until one of the tags `until` (e.g. `endif`) is reached, which is
+/// returned alongside the nodes
+fn parse_nodes(
+    tokens: &[Token],
+    pos: &mut usize,
+    until: &[&str],
+) -> Result<(Vec<Node>, Option<String>), Error> {
+    let mut nodes = Vec::new();
+
+    while let Some(token) = tokens.get(*pos) {
+        *pos += 1;
+
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text.clone())),
+            Token::Var(name) => nodes.push(Node::Var(name.clone())),
+            Token::Tag(tag) => {
+                let words = tag.split_whitespace().collect::<Vec<_>>();
+
+                match words.as_slice() {
+                    [end] if until.contains(end) => return Ok((nodes, Some(end.to_string()))),
+                    ["if", name] | ["if", "not", name] => {
+                        let (then_nodes, end) = parse_nodes(tokens, pos, &["else", "endif"])?;
+
+                        let else_nodes = match end.as_ref().map(|x| x.as_str()) {
+                            Some("else") => parse_nodes(tokens, pos, &["endif"])?.0,
+                            _ => Vec::new(),
+                        };
+
+                        nodes.push(Node::If {
+                            name: name.to_string(),
+                            negated: words.len() == 3,
+                            then_nodes,
+                            else_nodes,
+                        });
+                    }
+                    ["for", item, "in", list] => {
+                        let (body, _) = parse_nodes(tokens, pos, &["endfor"])?;
+
+                        nodes.push(Node::For {
+                            item: item.to_string(),
+                            list: list.to_string(),
+                            body,
+                        });
+                    }
+                    _ => {
+                        return Err(Error::Skeleton(format!("Unexpected tag '{{% {} %}}'", tag)));
+                    }
+                }
+            }
+        }
+    }
+
+    if until.is_empty() {
+        Ok((nodes, None))
+    } else {
+        Err(Error::Skeleton(format!(
+            "Missing '{{% {} %}}'",
+            until.last().unwrap_or(&"end")
+        )))
+    }
+}
+
+/// Checks if the variable `name` is set and isn't empty
+fn is_truthy(vars: &BTreeMap<String, SkeletonValue>, name: &str) -> bool {
+    match vars.get(name) {
+        Some(SkeletonValue::Str(value)) => !value.is_empty(),
+        Some(SkeletonValue::List(values)) => !values.is_empty(),
+        None => false,
+    }
+}
+
+fn render_nodes(
+    nodes: &[Node],
+    vars: &BTreeMap<String, SkeletonValue>,
+    output: &mut String,
+) -> Result<(), Error> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Var(name) => match vars.get(name) {
+                Some(SkeletonValue::Str(value)) => output.push_str(value),
+                Some(SkeletonValue::List(values)) => output.push_str(&values.join(" ")),
+                None => {
+                    return Err(Error::Skeleton(format!(
+                        "Unknown variable '{}', only use optional variables inside of '{{% if {} %}}'",
+                        name, name
+                    )));
+                }
+            },
+            Node::If {
+                name,
+                negated,
+                then_nodes,
+                else_nodes,
+            } => {
+                if is_truthy(vars, name) != *negated {
+                    render_nodes(then_nodes, vars, output)?;
+                } else {
+                    render_nodes(else_nodes, vars, output)?;
+                }
+            }
+            Node::For { item, list, body } => {
+                let values = match vars.get(list) {
+                    Some(SkeletonValue::List(values)) => values.clone(),
+                    Some(SkeletonValue::Str(value)) => vec![value.clone()],
+                    None => Vec::new(),
+                };
+
+                let mut scope = vars.clone();
+
+                for value in values {
+                    scope.insert(item.clone(), SkeletonValue::Str(value));
+                    render_nodes(body, &scope, output)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Renders the template skeleton `skeleton` with the variables `vars`. Skeletons support
+/// `{{ variable }}`, `{% if variable %}`/`{% if not variable %}` (with an optional
+/// `{% else %}`) and `{% for item in list %}`. Lists are joined with spaces when used as a
+/// variable.
+///
+/// # Errors
+///
+/// * Errors out if `skeleton` isn't a valid skeleton
+/// * Errors out if `skeleton` uses a variable that isn't set outside of an `{% if %}`
+pub(super) fn render_skeleton(
+    skeleton: &str,
+    vars: &BTreeMap<String, SkeletonValue>,
+) -> Result<String, Error> {
+    let tokens = tokenize(skeleton)?;
+    let (nodes, _) = parse_nodes(&tokens, &mut 0, &[])?;
+
+    let mut output = String::new();
+    render_nodes(&nodes, vars, &mut output)?;
+
+    Ok(output)
+}
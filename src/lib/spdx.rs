@@ -0,0 +1,298 @@
+//This file is part of tmplgen
+//
+//tmplgen is free software: you can redistribute it and/or modify
+//it under the terms of the GNU General Public License as published by
+//the Free Software Foundation, either version 3 of the License, or
+//(at your option) any later version.
+//
+//tmplgen is distributed in the hope that it will be useful,
+//but WITHOUT ANY WARRANTY; without even the implied warranty of
+//MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//GNU General Public License for more details.
+//
+//You should have received a copy of the GNU General Public License
+//along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::errors::Error;
+use crate::types::*;
+use lazy_static::lazy_static;
+use log::warn;
+
+lazy_static! {
+    /// The SPDX license list, which is embedded into tmplgen and as such only parsed once
+    static ref SPDX_DATA: SpdxData = toml::from_str(include_str!("spdx.toml")).unwrap();
+}
+
+/// A parsed SPDX license expression, e.g. `(MIT OR Apache-2.0) AND GPL-2.0+ WITH GCC-exception-2.0`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) enum LicenseExpr {
+    License {
+        id: String,
+        or_later: bool,
+        exception: Option<String>,
+    },
+    And(Box<LicenseExpr>, Box<LicenseExpr>),
+    Or(Box<LicenseExpr>, Box<LicenseExpr>),
+}
+
+impl LicenseExpr {
+    /// Converts the expression to the list of licenses Void's `license=` field expects.
+    /// Void doesn't distinguish between `AND` and `OR`, so all licenses of the expression are
+    /// listed, with the identifiers normalized to non-deprecated SPDX identifiers
+    /// (e.g. `GPL-2.0+` becomes `GPL-2.0-or-later`).
+    pub fn to_void_licenses(&self) -> Vec<String> {
+        let mut licenses = Vec::new();
+
+        self.collect_licenses(&SPDX_DATA, &mut licenses);
+
+        licenses
+    }
+
+    /// Returns all identifiers (licenses and exceptions) of the expression that aren't on the
+    /// SPDX license list
+    pub fn unknown_ids(&self) -> Vec<String> {
+        let mut unknown = Vec::new();
+
+        self.collect_unknown_ids(&SPDX_DATA, &mut unknown);
+
+        unknown
+    }
+
+    fn collect_unknown_ids(&self, spdx_data: &SpdxData, unknown: &mut Vec<String>) {
+        match self {
+            LicenseExpr::License { id, exception, .. } => {
+                // LicenseRefs are user defined, so they can't be on the list
+                if !id.starts_with("LicenseRef-") && find_id(spdx_data, id).is_none() {
+                    unknown.push(id.clone());
+                }
+
+                if let Some(exception) = exception {
+                    if !spdx_data
+                        .exceptions
+                        .iter()
+                        .any(|x| x.to_lowercase() == exception.to_lowercase())
+                    {
+                        unknown.push(exception.clone());
+                    }
+                }
+            }
+            LicenseExpr::And(left, right) | LicenseExpr::Or(left, right) => {
+                left.collect_unknown_ids(spdx_data, unknown);
+                right.collect_unknown_ids(spdx_data, unknown);
+            }
+        }
+    }
+
+    fn collect_licenses(&self, spdx_data: &SpdxData, licenses: &mut Vec<String>) {
+        match self {
+            LicenseExpr::License {
+                id,
+                or_later,
+                exception,
+            } => {
+                let mut license = normalize_id(spdx_data, id, *or_later);
+
+                if let Some(exception) = exception {
+                    let exception = spdx_data
+                        .exceptions
+                        .iter()
+                        .find(|x| x.to_lowercase() == exception.to_lowercase())
+                        .unwrap_or(exception);
+
+                    license = format!("{} WITH {}", license, exception);
+                }
+
+                if !licenses.contains(&license) {
+                    licenses.push(license);
+                }
+            }
+            LicenseExpr::And(left, right) | LicenseExpr::Or(left, right) => {
+                left.collect_licenses(spdx_data, licenses);
+                right.collect_licenses(spdx_data, licenses);
+            }
+        }
+    }
+}
+
+/// Looks up the correctly capitalized form of `id` on the SPDX license list
+fn find_id<'a>(spdx_data: &'a SpdxData, id: &str) -> Option<&'a String> {
+    let id = id.to_lowercase();
+
+    spdx_data
+        .licenses
+        .iter()
+        .chain(spdx_data.deprecated.iter())
+        .find(|x| x.to_lowercase() == id)
+}
+
+/// Normalizes the SPDX identifier `id`, e.g. `gpl-2.0` becomes `GPL-2.0-only` and `GPL-2.0` with
+/// `or_later` set (`GPL-2.0+`) becomes `GPL-2.0-or-later`.
+fn normalize_id(spdx_data: &SpdxData, id: &str, or_later: bool) -> String {
+    let id = find_id(spdx_data, id)
+        .cloned()
+        .unwrap_or_else(|| id.to_string());
+
+    let base_id = id.trim_end_matches("-only").trim_end_matches("-or-later");
+
+    let or_later_id = format!("{}-or-later", base_id);
+    let only_id = format!("{}-only", base_id);
+
+    if or_later || id.ends_with("-or-later") {
+        if spdx_data.licenses.contains(&or_later_id) {
+            or_later_id
+        } else {
+            // The deprecated `+` suffix is the only way of expressing this for non-GNU licenses
+            format!("{}+", id)
+        }
+    } else if spdx_data.licenses.contains(&only_id) {
+        // Deprecated GNU identifiers (e.g. `GPL-2.0`) mean `-only`
+        only_id
+    } else {
+        id
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    OpenParen,
+    CloseParen,
+    And,
+    Or,
+    With,
+    Id(String),
+}
+
+fn tokenize(expr: &str) -> Vec<Token> {
+    // The legacy syntax `MIT/Apache-2.0` (which is still used by some crates) means `OR`
+    expr.replace("(", " ( ")
+        .replace(")", " ) ")
+        .replace("/", " OR ")
+        .split_whitespace()
+        .map(|x| match x {
+            "(" => Token::OpenParen,
+            ")" => Token::CloseParen,
+            "AND" | "and" => Token::And,
+            "OR" | "or" => Token::Or,
+            "WITH" | "with" => Token::With,
+            _ => Token::Id(x.to_string()),
+        })
+        .collect()
+}
+
+/// Parses an SPDX license expression. Supports `AND`, `OR`, `WITH`, parentheses, the deprecated
+/// `+` suffix and the legacy `/` syntax, which means `OR`.
+///
+/// # Errors
+///
+/// * Errors out if `expr` isn't a valid license expression
+pub(super) fn parse_license_expr(expr: &str) -> Result<LicenseExpr, Error> {
+    let tokens = tokenize(expr);
+    let mut pos = 0;
+
+    let license_expr = parse_or(&tokens, &mut pos, expr)?;
+
+    if pos < tokens.len() {
+        return Err(Error::License(format!(
+            "Unexpected token {:?} in license expression '{}'",
+            tokens[pos], expr
+        )));
+    }
+
+    Ok(license_expr)
+}
+
+fn parse_or(tokens: &[Token], pos: &mut usize, expr: &str) -> Result<LicenseExpr, Error> {
+    let mut left = parse_and(tokens, pos, expr)?;
+
+    while tokens.get(*pos) == Some(&Token::Or) {
+        *pos += 1;
+        let right = parse_and(tokens, pos, expr)?;
+        left = LicenseExpr::Or(Box::new(left), Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_and(tokens: &[Token], pos: &mut usize, expr: &str) -> Result<LicenseExpr, Error> {
+    let mut left = parse_with(tokens, pos, expr)?;
+
+    while tokens.get(*pos) == Some(&Token::And) {
+        *pos += 1;
+        let right = parse_with(tokens, pos, expr)?;
+        left = LicenseExpr::And(Box::new(left), Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_with(tokens: &[Token], pos: &mut usize, expr: &str) -> Result<LicenseExpr, Error> {
+    match tokens.get(*pos) {
+        Some(Token::OpenParen) => {
+            *pos += 1;
+            let inner = parse_or(tokens, pos, expr)?;
+
+            if tokens.get(*pos) != Some(&Token::CloseParen) {
+                return Err(Error::License(format!(
+                    "Missing closing parenthesis in license expression '{}'",
+                    expr
+                )));
+            }
+            *pos += 1;
+
+            Ok(inner)
+        }
+        Some(Token::Id(id)) => {
+            *pos += 1;
+
+            let exception = if tokens.get(*pos) == Some(&Token::With) {
+                *pos += 1;
+                match tokens.get(*pos) {
+                    Some(Token::Id(exception)) => {
+                        *pos += 1;
+                        Some(exception.clone())
+                    }
+                    _ => {
+                        return Err(Error::License(format!(
+                            "Missing exception after WITH in license expression '{}'",
+                            expr
+                        )));
+                    }
+                }
+            } else {
+                None
+            };
+
+            Ok(LicenseExpr::License {
+                id: id.trim_end_matches('+').to_string(),
+                or_later: id.ends_with('+'),
+                exception,
+            })
+        }
+        token => Err(Error::License(format!(
+            "Expected a license but got {:?} in license expression '{}'",
+            token, expr
+        ))),
+    }
+}
+
+/// Converts the license expression `expr` to the licenses Void's `license=` field expects and
+/// warns about identifiers that aren't on the SPDX license list. If `expr` can't be parsed, it's
+/// returned as is.
+pub(super) fn spdx_to_void(pkg_name: &str, expr: &str) -> Vec<String> {
+    match parse_license_expr(expr) {
+        Ok(license_expr) => {
+            for unknown_id in license_expr.unknown_ids() {
+                warn!(
+                    "License {} of package {} isn't a known SPDX identifier, please check it!",
+                    unknown_id, pkg_name
+                );
+            }
+
+            license_expr.to_void_licenses()
+        }
+        Err(e) => {
+            warn!("{}", e);
+            vec![expr.to_string()]
+        }
+    }
+}
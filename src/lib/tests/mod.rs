@@ -13,10 +13,17 @@
 //You should have received a copy of the GNU General Public License
 //along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
 
+use super::crates::*;
+use super::data::*;
+use super::errors::*;
 use super::gems::*;
 use super::helpers::*;
+use super::http::*;
+use super::perldist::*;
+use super::registry::*;
+use super::skeleton::*;
+use super::spdx::*;
 use super::types::*;
-use rubygems_api::GemRunDeps;
 use std::env::set_var;
 
 fn set_env() {
@@ -51,6 +58,106 @@ fn test_query_perldist() {
     assert_eq!(tmpl_builder.pkg_info.unwrap().pkg_name, "perl-Moose")
 }
 
+#[test]
+fn test_get_info_async() {
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+
+    for (pkg_name, pkg_type) in &[
+        ("rubygems_api", PkgType::Crate),
+        ("ffi", PkgType::Gem),
+        ("Moose", PkgType::PerlDist),
+    ] {
+        let mut tmpl_builder = TmplBuilder::new(pkg_name);
+        tmpl_builder.set_type(*pkg_type);
+
+        let pkg_info = runtime
+            .block_on(tmpl_builder.get_info_async())
+            .unwrap()
+            .pkg_info
+            .unwrap();
+
+        let mut tmpl_builder = TmplBuilder::new(pkg_name);
+        let pkg_info_sync = tmpl_builder
+            .set_type(*pkg_type)
+            .get_info()
+            .unwrap()
+            .pkg_info
+            .as_ref()
+            .unwrap();
+
+        assert_eq!(&pkg_info, pkg_info_sync);
+    }
+
+    let err = runtime
+        .block_on(TmplBuilder::new("hdusapiduwipa").get_info_async())
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("Can't get PkgInfo"));
+
+    let mut tmpl_builder = TmplBuilder::new("hdusapiduwipa");
+    tmpl_builder.set_type(PkgType::Gem);
+    assert!(runtime.block_on(tmpl_builder.get_info_async()).is_err());
+}
+
+#[test]
+fn test_perl_release() {
+    let release = serde_json::json!({
+        "distribution": "Moose",
+        "version": 2.2011,
+        "download_url": "https://cpan.metacpan.org/authors/id/E/ET/ETHER/Moose-2.2011.tar.gz",
+        "abstract": "A postmodern object system for Perl 5",
+        "license": ["perl_5"],
+        "resources": {},
+        "dependency": [
+            { "module": "Carp", "phase": "runtime", "version": "1.22" },
+            { "module": "ExtUtils::MakeMaker", "phase": "configure", "version": 0 },
+        ],
+    });
+
+    let release = perl_release(&release).unwrap();
+    assert_eq!(release.name, "Moose");
+    assert_eq!(release.version, "2.2011");
+    assert_eq!(release.homepage, None);
+    assert_eq!(release.license, Some(vec!["perl_5".to_string()]));
+    assert_eq!(
+        release.dependencies[1],
+        PerlDependency {
+            module: "ExtUtils::MakeMaker".to_string(),
+            phase: "configure".to_string(),
+        }
+    );
+
+    assert!(perl_release(&serde_json::json!({ "distribution": "Moose" })).is_err());
+}
+
+#[test]
+fn test_xs_dist_urls() {
+    assert_eq!(
+        xs_dist_urls("https://cpan.metacpan.org/authors/id/E/ET/ETHER/Moose-2.2011.tar.gz")
+            .unwrap()
+            .0,
+        "https://fastapi.metacpan.org/v1/source/ETHER/Moose-2.2011/MANIFEST"
+    );
+    assert!(xs_dist_urls("Moose-2.2011.tar.gz").is_err());
+
+    assert!(manifest_has_xs(
+        "Changes\nlib/Moose.pm\nxs/Moose.xs    The XS code\n"
+    ));
+    assert!(!manifest_has_xs("Changes\nlib/Moose.pm\nt/xs.t\n"));
+
+    // Elasticsearch 7+ answers with an object as total
+    assert!(search_has_hits(&serde_json::json!({
+        "hits": { "total": { "value": 2, "relation": "eq" }, "hits": [{}, {}] }
+    })));
+    assert!(!search_has_hits(&serde_json::json!({
+        "hits": { "total": { "value": 0, "relation": "eq" }, "hits": [] }
+    })));
+    assert!(search_has_hits(
+        &serde_json::json!({ "hits": { "total": 1, "hits": [{}] } })
+    ));
+    assert!(!search_has_hits(&serde_json::json!({})));
+}
+
 #[test]
 fn test_tmplwriter_correctness() {
     let dir = tempfile::tempdir().unwrap();
@@ -72,6 +179,11 @@ fn test_tmplwriter_correctness() {
         download_url: Some(
             "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
         ),
+        native: true,
+        license_files: None,
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
     };
 
     let tmpl_string_crate = TmplBuilder::from_pkg_info(pkg_info_crate.clone())
@@ -122,6 +234,11 @@ fn test_tmplwriter_correctness() {
         download_url: Some(
             "https://cpan.metacpan.org/authors/id/E/ET/ETHER/Moose-${version}.tar.gz".to_string(),
         ),
+        native: false,
+        license_files: None,
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
     };
 
     let tmpl_string_perl = TmplBuilder::from_pkg_info(pkg_info_perl)
@@ -154,7 +271,12 @@ fn test_tmplwriter_correctness() {
         license: Some(vec!["MIT".to_string(),"BSD-2-Clause".to_string()]),
         dependencies: Some(Dependencies { host: None, make: None, run: Some(vec!["ruby-metaclass>=0.0.1".to_string()]) }),
         sha: "5491a93cad7e3cf6682ab5977d00194e446db7b69bd34c549128c080e3a54f89".to_string(),
-        download_url: None
+        download_url: None,
+        native: false,
+        license_files: Some(vec!["COPYING.md".to_string(), "MIT-LICENSE.md".to_string()]),
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
     };
 
     let tmpl_string_ruby = TmplBuilder::from_pkg_info(pkg_info_ruby)
@@ -206,6 +328,50 @@ fn test_provider_selector() {
     );
 }
 
+#[test]
+fn test_find_providers() {
+    assert_eq!(
+        TmplBuilder::new("tmplgen")
+            .find_providers()
+            .unwrap()
+            .iter()
+            .map(|x| x.pkg_type)
+            .collect::<Vec<_>>(),
+        vec![PkgType::Crate]
+    );
+
+    let matches = TmplBuilder::new("ffi").find_providers().unwrap();
+    assert!(matches.iter().any(|x| x.pkg_type == PkgType::Crate));
+    assert!(matches.iter().any(|x| x.pkg_type == PkgType::Gem));
+    assert!(matches.iter().all(|x| !x.version.is_empty()));
+
+    assert!(TmplBuilder::new("hdusapiduwipa")
+        .find_providers()
+        .unwrap()
+        .is_empty());
+
+    match TmplBuilder::new("ffi").get_type() {
+        Err(Error::AmbPkg(err)) => {
+            assert!(err.contains("crates.io"));
+            assert!(err.contains("rubygems.org"));
+            assert!(!err.contains("metacpan.org"));
+        }
+        _ => panic!("ffi should be ambiguous"),
+    }
+
+    match TmplBuilder::new("hdusapiduwipa").get_type() {
+        Err(Error::NoSuchPkg(_)) => {}
+        _ => panic!("hdusapiduwipa shouldn't exist"),
+    }
+}
+
+#[test]
+fn test_registry_name() {
+    assert_eq!(registry_name(PkgType::Crate), "crates.io");
+    assert_eq!(registry_name(PkgType::Gem), "rubygems.org");
+    assert_eq!(registry_name(PkgType::PerlDist), "metacpan.org");
+}
+
 #[test]
 #[should_panic]
 fn test_figure_out_provider_panic() {
@@ -321,156 +487,143 @@ fn test_gen_dep_string_split() {
 #[test]
 fn test_crate_check_native_deps() {
     assert_eq!(
-        &check_native_deps("openssl", PkgType::Crate)
+        &check_native_deps("openssl", PkgType::Crate, None)
             .unwrap()
             .unwrap()
             .make
             .unwrap()[0],
         "libressl-devel"
-    )
-}
-
-#[test]
-fn test_determine_gem_run_deps() {
-    let rubygem_deps = vec![
-        GemRunDeps {
-            name: "dep1".to_string(),
-            requirements: ">= 0".to_string(),
-        },
-        GemRunDeps {
-            name: "dep2".to_string(),
-            requirements: ">= 1".to_string(),
-        },
-        GemRunDeps {
-            name: "dep3".to_string(),
-            requirements: "> 2".to_string(),
-        },
-        GemRunDeps {
-            name: "dep4".to_string(),
-            requirements: "~> 1".to_string(),
-        },
-    ];
-
-    let mut dep_string = String::new();
-
-    for x in rubygem_deps {
-        dep_string.push_str(&parse_gem_version_req(&x));
-        dep_string.push_str(" ");
-    }
+    );
 
     assert_eq!(
-        &dep_string,
-        "ruby-dep1 ruby-dep2>=1 ruby-dep3>2 ruby-dep4>=1 "
-    )
-}
+        &check_native_deps("XML-LibXML", PkgType::PerlDist, None)
+            .unwrap()
+            .unwrap()
+            .make
+            .unwrap()[0],
+        "libxml2-devel"
+    );
 
-#[test]
-fn test_correct_license() {
-    assert_eq!(correct_license("GPL-1.0+"), "GPL-1.0-or-later".to_string());
     assert_eq!(
-        correct_license("perl_5"),
-        "Artistic-1.0-Perl, GPL-1.0-or-later".to_string()
+        check_native_deps("Try-Tiny", PkgType::PerlDist, None).unwrap(),
+        None
     );
+
     assert_eq!(
-        correct_license("GPL-3.0-or-later"),
-        "GPL-3.0-or-later".to_string()
+        check_native_deps("nokogiri", PkgType::Gem, None)
+            .unwrap()
+            .unwrap()
+            .make
+            .unwrap(),
+        vec!["libxml2-devel".to_string(), "libxslt-devel".to_string()]
     );
 }
 
 #[test]
-fn test_template_updater() {
-    let dir = tempfile::tempdir().unwrap();
-
-    set_var("XBPS_DISTDIR", dir.path());
-
-    set_env();
+fn test_crate_features() {
+    let features = TmplBuilder::new("git2")
+        .set_type(PkgType::Crate)
+        .get_features()
+        .unwrap();
 
-    let pkg_info_good = PkgInfo {
-        pkg_name: "rust-tmplgen".to_string(),
-        version: "0.3.1".to_string(),
-        description: Some(
-            "Void Linux template generator for language-specific package managers".to_string(),
-        ),
-        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
-        license: Some(vec!["GPL-3.0-or-later".to_string()]),
-        dependencies: None,
-        sha: "dummy_sha".to_string(),
-        download_url: Some(
-            "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
-        ),
-    };
+    assert!(features["default"].contains(&"https".to_string()));
 
-    let pkg_info_bad = PkgInfo {
-        pkg_name: "rust-tmplgen".to_string(),
-        version: "0.2.9".to_string(),
-        description: Some("gibberish".to_string()),
-        homepage: "htt/ri/tmplgen".to_string(),
-        license: Some(vec!["GPL-3.0-or-later".to_string()]),
-        dependencies: None,
-        sha: "dummy".to_string(),
-        download_url: Some("This Shouldn't be here".to_string()),
+    let no_features = CrateFeatures {
+        features: Vec::new(),
+        no_default_features: true,
     };
 
-    let bad_tmpl = TmplBuilder::from_pkg_info(pkg_info_bad)
-        .set_type(PkgType::Crate)
-        .generate(true)
+    let make_deps = check_native_deps("git2", PkgType::Crate, Some(&no_features))
+        .unwrap()
+        .unwrap()
+        .make
         .unwrap();
 
-    let good_template = TmplBuilder::from_pkg_info(pkg_info_good.clone())
-        .set_type(PkgType::Crate)
-        .update(&bad_tmpl, true)
+    assert!(make_deps.contains(&"libgit2-devel".to_string()));
+    assert!(!make_deps.contains(&"libressl-devel".to_string()));
+
+    let https_features = CrateFeatures {
+        features: vec!["https".to_string()],
+        no_default_features: true,
+    };
+
+    let make_deps = check_native_deps("git2", PkgType::Crate, Some(&https_features))
+        .unwrap()
+        .unwrap()
+        .make
         .unwrap();
 
-    assert_eq!(good_template.inner, include_str!("template_test_crate.in"));
+    assert!(make_deps.contains(&"libressl-devel".to_string()));
+}
 
-    let pkg_info_ok = PkgInfo {
-        pkg_name: "rust-tmplgen".to_string(),
-        version: "0.2.9".to_string(),
-        description: Some(
-            "Void Linux template generator for language-specific package managers".to_string(),
-        ),
-        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
-        license: Some(vec!["GPL-3.0-or-later".to_string()]),
-        dependencies: None,
-        sha: "dummy".to_string(),
-        download_url: Some(
-            "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
-        ),
+#[test]
+#[should_panic]
+fn test_crate_features_panic() {
+    let features = CrateFeatures {
+        features: vec!["nonexistent-feature".to_string()],
+        no_default_features: false,
     };
 
-    let ok_tmpl = TmplBuilder::from_pkg_info(pkg_info_ok)
-        .set_type(PkgType::Crate)
-        .generate(true)
-        .unwrap();
+    check_native_deps("git2", PkgType::Crate, Some(&features)).unwrap();
+}
 
-    let good_templ = TmplBuilder::from_pkg_info(pkg_info_good)
-        .set_type(PkgType::Crate)
-        .update(&ok_tmpl, false)
-        .unwrap();
+#[test]
+fn test_enabled_features() {
+    let mut crate_features = std::collections::HashMap::new();
+    crate_features.insert("default".to_string(), vec!["ssh".to_string()]);
+    crate_features.insert(
+        "ssh".to_string(),
+        vec!["libssh2-sys".to_string(), "libgit2-sys/ssh".to_string()],
+    );
+    crate_features.insert(
+        "https".to_string(),
+        vec![
+            "dep:openssl-sys".to_string(),
+            "openssl-probe?/vendored".to_string(),
+        ],
+    );
 
-    assert_eq!(good_templ.inner, include_str!("template_test_crate.in"));
+    let default_features = enabled_features(&crate_features, &CrateFeatures::default());
 
-    let diff_url_pkg_info_ok = PkgInfo {
-        pkg_name: "rust-tmplgen".to_string(),
-        version: "0.2.9".to_string(),
-        description: Some(
-            "Void Linux template generator for language-specific package managers".to_string(),
-        ),
-        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
-        license: Some(vec!["GPL-3.0-or-later".to_string()]),
-        dependencies: None,
-        sha: "dummy".to_string(),
-        download_url: Some(
-            "https://github.com/Cogitri/tmplgen/archive/v${version}.tar.gz".to_string(),
-        ),
-    };
+    assert!(default_features.contains("libssh2-sys"));
+    assert!(default_features.contains("libgit2-sys"));
+    assert!(!default_features.contains("openssl-sys"));
 
-    let diff_url_ok_tmpl = TmplBuilder::from_pkg_info(diff_url_pkg_info_ok)
-        .set_type(PkgType::Crate)
-        .generate(true)
-        .unwrap();
+    let https_features = enabled_features(
+        &crate_features,
+        &CrateFeatures {
+            features: vec!["https".to_string()],
+            no_default_features: true,
+        },
+    );
 
-    let different_url_pkg_info = PkgInfo {
+    assert!(https_features.contains("openssl-sys"));
+    assert!(!https_features.contains("openssl-probe"));
+    assert!(!https_features.contains("libssh2-sys"));
+
+    let unknown_feature = crate_native_deps(
+        "git2",
+        &[],
+        &crate_features,
+        Some(&CrateFeatures {
+            features: vec!["vendored".to_string()],
+            no_default_features: false,
+        }),
+    );
+    assert_eq!(
+        unknown_feature.err(),
+        Some(Error::UnknownFeature {
+            crate_name: "git2".to_string(),
+            feature: "vendored".to_string(),
+        })
+    );
+}
+
+#[test]
+fn test_configure_args() {
+    set_env();
+
+    let pkg_info = PkgInfo {
         pkg_name: "rust-tmplgen".to_string(),
         version: "0.3.1".to_string(),
         description: Some(
@@ -479,190 +632,2148 @@ fn test_template_updater() {
         homepage: "https://github.com/Cogitri/tmplgen".to_string(),
         license: Some(vec!["GPL-3.0-or-later".to_string()]),
         dependencies: None,
-        sha: "dummy".to_string(),
+        sha: "dummy_sha".to_string(),
         download_url: Some(
             "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
         ),
+        native: true,
+        license_files: None,
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
     };
 
-    let diff_sha_templ = TmplBuilder::from_pkg_info(different_url_pkg_info)
+    let template = TmplBuilder::from_pkg_info(pkg_info)
         .set_type(PkgType::Crate)
-        .update(&diff_url_ok_tmpl, false)
+        .set_features(CrateFeatures {
+            features: vec!["foo".to_string(), "bar".to_string()],
+            no_default_features: true,
+        })
+        .generate(true)
         .unwrap();
 
     assert_eq!(
-        diff_sha_templ.inner,
-        include_str!("template_test_crate.in")
-            .replace(
-                "dummy_sha",
-                "ac295b3b50ba071599ef2e8aa1bfc2d8d69a69267dee865e3fe0091c769221ca"
-            )
-            .replace(
-                "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate",
-                "https://github.com/Cogitri/tmplgen/archive/v${version}.tar.gz"
-            )
+        template.inner,
+        include_str!("template_test_crate.in").replace(
+            "build_style=cargo\n",
+            "build_style=cargo\nconfigure_args=\"--no-default-features --features foo,bar\"\n"
+        )
     );
-
-    dir.close().unwrap();
 }
 
 #[test]
-#[should_panic]
-fn test_template_updater_panic() {
-    let pkg_info_ok = PkgInfo {
+fn test_custom_skeleton() {
+    set_env();
+
+    let pkg_info = PkgInfo {
         pkg_name: "rust-tmplgen".to_string(),
-        version: "0.2.9".to_string(),
-        description: Some(
-            "Void Linux template generator for language-specific package managers".to_string(),
-        ),
+        version: "0.3.1".to_string(),
+        description: None,
         homepage: "https://github.com/Cogitri/tmplgen".to_string(),
-        license: Some(vec!["GPL-3.0-or-later".to_string()]),
+        license: Some(vec!["MIT".to_string()]),
         dependencies: None,
-        sha: "dummy".to_string(),
-        download_url: Some(
-            "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
-        ),
+        sha: "dummy_sha".to_string(),
+        download_url: None,
+        native: true,
+        license_files: Some(vec!["LICENSE".to_string(), "COPYING".to_string()]),
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
     };
 
-    let old_tmpl = TmplBuilder::from_pkg_info(pkg_info_ok)
+    let template = TmplBuilder::from_pkg_info(pkg_info)
         .set_type(PkgType::Crate)
+        .set_skeleton(
+            "pkgname={{ pkgname }}\n{% if distfiles %}\ndistfiles={{ distfiles }}\n{% endif %}\nshort_desc=\"{{ short_desc }}\"\nmake_check={{ make_check }}\n\ndo_check() {\n\tcargo test\n}\n{% for license_file in license_files %}\n\tvlicense {{ license_file }}\n{% endfor %}\n",
+        )
+        .set_variable("make_check", "no")
         .generate(true)
         .unwrap();
 
-    TmplBuilder::new("tmplgen")
-        .update(&old_tmpl, false)
-        .unwrap();
+    assert_eq!(
+        template.inner,
+        "pkgname=rust-tmplgen\nshort_desc=\"@description@\"\nmake_check=no\n\ndo_check() {\n\tcargo test\n}\n\tvlicense LICENSE\n\tvlicense COPYING\n"
+    );
 }
 
 #[test]
-fn test_get_git_author() {
-    set_env();
+fn test_render_skeleton() {
+    let mut vars = std::collections::BTreeMap::new();
+    vars.insert("name".to_string(), SkeletonValue::Str("foo".to_string()));
+    vars.insert("empty".to_string(), SkeletonValue::Str(String::new()));
+    vars.insert(
+        "list".to_string(),
+        SkeletonValue::List(vec!["a".to_string(), "b".to_string()]),
+    );
 
     assert_eq!(
-        &get_git_author().unwrap(),
-        "tmplgentests <tmplgentests@github.com>"
+        render_skeleton("x={{ name }} {{list}}", &vars).unwrap(),
+        "x=foo a b"
+    );
+    assert_eq!(
+        render_skeleton(
+            "{% if empty %}yes{% else %}no{% endif %} {% if not missing %}unset{% endif %}",
+            &vars
+        )
+        .unwrap(),
+        "no unset"
     );
+    assert_eq!(
+        render_skeleton(
+            "a\n  {% for x in list %}\n{{ x }}={{ name }}\n  {% endfor %}\nb\n",
+            &vars
+        )
+        .unwrap(),
+        "a\na=foo\nb=foo\nb\n"
+    );
+
+    assert!(render_skeleton("{{ missing }}", &vars).is_err());
+    assert!(render_skeleton("{% if name %}foo", &vars).is_err());
+    assert!(render_skeleton("{% endfor %}", &vars).is_err());
+    assert!(render_skeleton("{{ name ", &vars).is_err());
+    assert_eq!(
+        render_skeleton("{%}", &vars),
+        Err(Error::Skeleton("Missing '%}' after '{%}'".to_string()))
+    );
+    assert!(render_skeleton("{{}", &vars).is_err());
 }
 
 #[test]
-#[should_panic]
-fn test_get_info_panic() {
-    TmplBuilder::new("tmplgen").get_info().unwrap();
+fn test_default_skeletons() {
+    for pkg_type in &[PkgType::Crate, PkgType::Gem, PkgType::PerlDist] {
+        assert!(default_skeleton(*pkg_type).contains("pkgname={{ pkgname }}"));
+    }
 }
 
 #[test]
-fn test_set_info() {
-    let pkg_info_good = PkgInfo {
-        pkg_name: "rust-tmplgen".to_string(),
-        version: "0.3.1".to_string(),
-        description: Some(
-            "Void Linux template generator for language-specific package managers".to_string(),
-        ),
-        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
-        license: Some(vec!["GPL-3.0-or-later".to_string()]),
-        dependencies: None,
-        sha: "dummy_sha".to_string(),
-        download_url: Some(
-            "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
-        ),
-    };
+fn test_parse_build_script() {
+    let cargo_toml = r#"
+[package]
+name = "foo-sys"
+version = "0.1.0"
+links = "foo"
+build = "build/main.rs"
+
+[build-dependencies]
+pkg-config = "0.3"
+cmake = "0.1"
+"#;
+
+    let build_script = r#"
+fn main() {
+    if pkg_config::probe_library("libxml-2.0").is_ok() {
+        return;
+    }
+    pkg_config::Config::new().atleast_version("1.2").probe( "zlib").unwrap();
+    pkg_config::probe_library(&lib_name()).unwrap();
+    cmake::build("vendor");
+}
+"#;
 
-    let mut builder = TmplBuilder::new("tmplgen");
-    builder.set_info(pkg_info_good.clone());
+    let build_script_info = parse_build_script(cargo_toml, build_script);
 
-    assert_eq!(builder.pkg_info.unwrap(), pkg_info_good);
+    assert_eq!(build_script_info.links, Some("foo".to_string()));
+    assert_eq!(
+        build_script_info.pkg_config,
+        vec!["libxml-2.0".to_string(), "zlib".to_string()]
+    );
+    assert!(build_script_info.uses_cmake);
+    assert!(!build_script_info.uses_cc);
+    assert!(!build_script_info.uses_bindgen);
+
+    assert_eq!(
+        parse_build_script("[package]\nname = \"foo\"", ""),
+        BuildScriptInfo::default()
+    );
 }
 
 #[test]
-fn test_gen_deps() {
-    TmplBuilder::new("Scalar-List-Utils")
-        .set_type(PkgType::PerlDist)
-        .get_info()
-        .unwrap()
-        .gen_deps(None)
-        .unwrap();
-    TmplBuilder::new("rspec")
+fn test_map_pkg_config() {
+    assert_eq!(
+        map_pkg_config("libxml-2.0"),
+        Some("libxml2-devel".to_string())
+    );
+    assert_eq!(map_pkg_config("z"), Some("zlib-devel".to_string()));
+    assert_eq!(map_pkg_config("ssl"), Some("libressl-devel".to_string()));
+    assert_eq!(map_pkg_config("nonexistent-library"), None);
+}
+
+#[test]
+fn test_gem_native() {
+    let mut builder = TmplBuilder::new("nokogiri");
+    let pkg_info = builder
         .set_type(PkgType::Gem)
         .get_info()
         .unwrap()
-        .gen_deps(None)
+        .pkg_info
+        .as_ref()
         .unwrap();
 
-    let dir = tempfile::tempdir().unwrap();
-    set_var("XBPS_DISTDIR", dir.path());
+    assert!(pkg_info.native);
 
-    TmplBuilder::new("Scalar-List-Utils")
-        .set_type(PkgType::PerlDist)
-        .get_info()
+    let make_deps = pkg_info
+        .dependencies
+        .as_ref()
         .unwrap()
-        .gen_deps(dir.path().to_str())
+        .make
+        .as_ref()
         .unwrap();
-    TmplBuilder::new("diff-lcs")
+
+    assert!(make_deps.contains(&"ruby-devel".to_string()));
+    assert!(make_deps.contains(&"libxml2-devel".to_string()));
+
+    let mut builder_pure = TmplBuilder::new("diff-lcs");
+    let pkg_info_pure = builder_pure
         .set_type(PkgType::Gem)
         .get_info()
         .unwrap()
-        .gen_deps(dir.path().to_str())
+        .pkg_info
+        .as_ref()
         .unwrap();
 
-    // Test if already existing deps are successfully skipped.
-    TmplBuilder::new("rspec-core")
-        .set_type(PkgType::Gem)
-        .get_info()
+    assert!(!pkg_info_pure.native);
+    assert_eq!(pkg_info_pure.dependencies.as_ref().unwrap().make, None);
+}
+
+#[test]
+fn test_gem_sources() {
+    assert_eq!(
+        github_repo("https://github.com/sparklemotion/nokogiri"),
+        Some(("sparklemotion", "nokogiri"))
+    );
+    assert_eq!(
+        github_repo("https://github.com/ffi/ffi/"),
+        Some(("ffi", "ffi"))
+    );
+    assert_eq!(
+        github_repo("https://github.com/ffi/ffi.git"),
+        Some(("ffi", "ffi"))
+    );
+    assert_eq!(github_repo("https://github.com/ffi/ffi/tree/master"), None);
+    assert_eq!(
+        github_repo("https://github.com/rails/rails/tree/v6.0.0/activesupport"),
+        None
+    );
+    assert_eq!(github_repo("https://github.com/ffi/ffi#readme"), None);
+    assert_eq!(github_repo("https://github.com/ffi"), None);
+    assert_eq!(github_repo("https://gitlab.com/gitlab-org/gitlab"), None);
+
+    let version_info = GemVersionInfo {
+        platform: "ruby".to_string(),
+        source_code_uri: Some("https://github.com/sparklemotion/nokogiri".to_string()),
+    };
+
+    assert_eq!(
+        gem_sources(&version_info),
+        vec![
+            GemSource {
+                url: "https://github.com/sparklemotion/nokogiri/archive/v${version}.tar.gz"
+                    .to_string(),
+                wrksrc: "nokogiri-${version}".to_string(),
+            },
+            GemSource {
+                url: "https://github.com/sparklemotion/nokogiri/archive/${version}.tar.gz"
+                    .to_string(),
+                wrksrc: "nokogiri-${version}".to_string(),
+            },
+        ]
+    );
+    assert!(gem_sources_to_inspect("nokogiri", &version_info, false).is_empty());
+
+    let branch_info = GemVersionInfo {
+        platform: "ruby".to_string(),
+        source_code_uri: Some("https://github.com/sparklemotion/nokogiri/tree/main".to_string()),
+    };
+
+    assert!(gem_sources(&branch_info).is_empty());
+    assert!(gem_sources(&GemVersionInfo::default()).is_empty());
+}
+
+#[test]
+fn test_gem_source_fallback() {
+    set_env();
+
+    // Nothing listens there, so the sources can't be downloaded
+    let sources = vec![GemSource {
+        url: "http://127.0.0.1:1/archive/v${version}.tar.gz".to_string(),
+        wrksrc: "ffi-${version}".to_string(),
+    }];
+
+    assert!(find_gem_source("ffi", "1.11.1", sources.clone()).is_none());
+    assert!(find_gem_source("ffi", "1.11.1", Vec::new()).is_none());
+
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = async_client().unwrap();
+
+    assert!(runtime
+        .block_on(find_gem_source_async(
+            &client,
+            "ffi",
+            "1.11.1",
+            sources.clone()
+        ))
         .unwrap()
+        .is_none());
+
+    let gem_meta = || GemMeta {
+        version: "1.11.1".to_string(),
+        info: Some("Ruby FFI".to_string()),
+        homepage_uri: Some("https://github.com/ffi/ffi/wiki".to_string()),
+        licenses: Some(vec!["BSD-3-Clause".to_string()]),
+        sha: "dummy_sha".to_string(),
+        runtime_deps: Vec::new(),
+    };
+    let gem_contents = || GemContents {
+        extensions: vec!["ext/ffi_c/extconf.rb".to_string()],
+        license_files: vec!["LICENSE".to_string()],
+        doc_size: 0,
+    };
+
+    // Without the sources the gem is built from the gem
+    let pkg_info = gem_pkg_info(
+        "ffi",
+        gem_meta(),
+        &RuntimeVersions::default(),
+        true,
+        gem_contents(),
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(pkg_info.download_url, None);
+    assert_eq!(pkg_info.wrksrc, None);
+
+    let template = TmplBuilder::from_pkg_info(pkg_info)
+        .set_type(PkgType::Gem)
         .generate(true)
         .unwrap();
-    TmplBuilder::new("rspec")
+
+    assert!(template.inner.contains("build_style=gem\n"));
+    assert!(!template.inner.contains("wrksrc="));
+
+    // With them it's built from its gemspec in the directory named after the repository
+    let pkg_info = gem_pkg_info(
+        "ffi",
+        gem_meta(),
+        &RuntimeVersions::default(),
+        true,
+        gem_contents(),
+        Some((
+            sources[0].clone(),
+            DistfileInfo {
+                sha: "source_sha".to_string(),
+                license_files: Some(vec!["LICENSE".to_string()]),
+                doc_size: 0,
+                files: Vec::new(),
+                manifests: std::collections::BTreeMap::new(),
+                build_scripts: std::collections::BTreeMap::new(),
+            },
+        )),
+    )
+    .unwrap();
+
+    let template = TmplBuilder::from_pkg_info(pkg_info)
         .set_type(PkgType::Gem)
-        .get_info()
-        .unwrap()
-        .gen_deps(dir.path().to_str())
+        .generate(true)
         .unwrap();
 
-    dir.close().unwrap();
+    assert!(template.inner.contains("build_style=gemspec\n"));
+    assert!(template.inner.contains("wrksrc=\"ffi-${version}\"\n"));
+    assert!(template.inner.contains("checksum=source_sha\n"));
 }
 
 #[test]
-#[should_panic]
-fn test_gen_deps_panic() {
-    TmplBuilder::new("tmplgen")
-        .set_type(PkgType::Crate)
-        .get_info()
-        .unwrap()
-        .gen_deps(None)
-        .unwrap();
-    TmplBuilder::new("Moose")
-        .set_type(PkgType::PerlDist)
-        .gen_deps(None)
-        .unwrap();
+fn test_parse_gem_extensions() {
+    let metadata = "--- !ruby/object:Gem::Specification
+name: nokogiri
+executables:
+- nokogiri
+extensions:
+- ext/nokogiri/extconf.rb
+- ext/nokogiri/other/extconf.rb
+extra_rdoc_files: []
+";
+
+    assert_eq!(
+        parse_gem_extensions(metadata),
+        vec![
+            "ext/nokogiri/extconf.rb".to_string(),
+            "ext/nokogiri/other/extconf.rb".to_string()
+        ]
+    );
+
+    assert!(parse_gem_extensions("name: rake\nextensions: []\n").is_empty());
+    assert!(parse_gem_extensions("name: rake\n").is_empty());
 }
 
 #[test]
-fn test_crate_or_licensing() {
-    let mut builder1 = TmplBuilder::new("cargo-crev");
-    let pkg_info1 = builder1
-        .set_type(PkgType::Crate)
+fn test_perldist_xs() {
+    set_env();
+
+    let mut builder_xs = TmplBuilder::new("XML-LibXML");
+    let pkg_info_xs = builder_xs
+        .set_type(PkgType::PerlDist)
         .get_info()
         .unwrap()
         .pkg_info
         .as_ref()
         .unwrap();
 
-    assert_eq!(
-        pkg_info1.license.as_ref().unwrap().join(","),
-        "MPL-2.0,MIT,Apache-2.0"
-    );
+    assert!(pkg_info_xs.native);
 
-    let mut builder2 = TmplBuilder::new("amethyst");
-    let pkg_info2 = builder2
-        .set_type(PkgType::Crate)
+    let make_deps = pkg_info_xs
+        .dependencies
+        .as_ref()
+        .unwrap()
+        .make
+        .as_ref()
+        .unwrap();
+
+    assert!(make_deps.contains(&"perl".to_string()));
+    assert!(make_deps.contains(&"libxml2-devel".to_string()));
+
+    let template_xs = builder_xs.generate(true).unwrap();
+
+    assert!(!template_xs.inner.contains("archs="));
+    assert!(template_xs.inner.contains(" libxml2-devel"));
+    assert!(!template_xs.inner.contains("perl-libxml2-devel"));
+
+    let mut builder_pp = TmplBuilder::new("Try-Tiny");
+    let pkg_info_pp = builder_pp
+        .set_type(PkgType::PerlDist)
         .get_info()
         .unwrap()
         .pkg_info
         .as_ref()
         .unwrap();
 
+    assert!(!pkg_info_pp.native);
+}
+
+#[test]
+fn test_determine_gem_run_deps() {
+    let rubygem_deps = vec![
+        GemResponseDep {
+            name: "dep1".to_string(),
+            requirements: ">= 0".to_string(),
+        },
+        GemResponseDep {
+            name: "dep2".to_string(),
+            requirements: ">= 1".to_string(),
+        },
+        GemResponseDep {
+            name: "dep3".to_string(),
+            requirements: "> 2".to_string(),
+        },
+        GemResponseDep {
+            name: "dep4".to_string(),
+            requirements: "~> 1".to_string(),
+        },
+    ];
+
+    let mut dep_string = String::new();
+
+    for x in rubygem_deps {
+        dep_string.push_str(&parse_gem_version_req(&x));
+        dep_string.push_str(" ");
+    }
+
     assert_eq!(
-        pkg_info2.license.as_ref().unwrap().join(","),
-        "MIT,Apache-2.0"
+        &dep_string,
+        "ruby-dep1 ruby-dep2>=1 ruby-dep3>2 ruby-dep4>=1 "
+    )
+}
+
+#[test]
+fn test_correct_license() {
+    assert_eq!(
+        correct_license("tmplgen", PkgType::Crate, "GPL-1.0+"),
+        vec!["GPL-1.0-or-later".to_string()]
+    );
+    assert_eq!(
+        correct_license("Moose", PkgType::PerlDist, "perl_5"),
+        vec![
+            "Artistic-1.0-Perl".to_string(),
+            "GPL-1.0-or-later".to_string()
+        ]
+    );
+    assert_eq!(
+        correct_license("tmplgen", PkgType::Crate, "GPL-3.0-or-later"),
+        vec!["GPL-3.0-or-later".to_string()]
+    );
+}
+
+#[test]
+fn test_correct_license_perl() {
+    assert_eq!(
+        correct_license("Moose", PkgType::PerlDist, "artistic_2"),
+        vec!["Artistic-2.0".to_string()]
+    );
+    assert_eq!(
+        correct_license("Moose", PkgType::PerlDist, "apache_2_0"),
+        vec!["Apache-2.0".to_string()]
+    );
+    assert_eq!(
+        correct_license("Moose", PkgType::PerlDist, "mozilla_1_1"),
+        vec!["MPL-1.1".to_string()]
+    );
+    assert_eq!(
+        correct_license("Moose", PkgType::PerlDist, "lgpl_2_1"),
+        vec!["LGPL-2.1-only".to_string()]
+    );
+    assert_eq!(
+        correct_license("Moose", PkgType::PerlDist, "mit"),
+        vec!["MIT".to_string()]
+    );
+
+    // Ambiguous keys are left for the maintainer to decide
+    assert!(correct_license("Moose", PkgType::PerlDist, "bsd").is_empty());
+    assert!(correct_license("Moose", PkgType::PerlDist, "open_source").is_empty());
+    assert!(correct_license("Moose", PkgType::PerlDist, "unrestricted").is_empty());
+
+    // CPAN::Meta keys are only valid for perldists
+    assert_eq!(
+        correct_license("tmplgen", PkgType::Crate, "perl_5"),
+        vec!["perl_5".to_string()]
+    );
+}
+
+#[test]
+fn test_correct_license_ruby() {
+    assert_eq!(
+        correct_license("mocha", PkgType::Gem, "Apache 2.0"),
+        vec!["Apache-2.0".to_string()]
+    );
+    assert_eq!(
+        correct_license("mocha", PkgType::Gem, "the  mit license"),
+        vec!["MIT".to_string()]
+    );
+    assert_eq!(
+        correct_license("mocha", PkgType::Gem, "2-clause BSDL"),
+        vec!["BSD-2-Clause".to_string()]
+    );
+    assert_eq!(
+        correct_license("mocha", PkgType::Gem, "BSD-3-Clause"),
+        vec!["BSD-3-Clause".to_string()]
+    );
+    assert!(correct_license("mocha", PkgType::Gem, "BSD").is_empty());
+    assert!(correct_license("mocha", PkgType::Gem, "Public Domain").is_empty());
+}
+
+#[test]
+fn test_spdx_expressions() {
+    assert_eq!(
+        parse_license_expr("MIT AND Apache-2.0")
+            .unwrap()
+            .to_void_licenses(),
+        vec!["MIT".to_string(), "Apache-2.0".to_string()]
+    );
+
+    assert_eq!(
+        parse_license_expr("MIT/Apache-2.0").unwrap(),
+        parse_license_expr("MIT OR Apache-2.0").unwrap()
+    );
+
+    // AND binds stronger than OR
+    assert_eq!(
+        parse_license_expr("MIT OR Apache-2.0 AND Zlib").unwrap(),
+        parse_license_expr("MIT OR (Apache-2.0 AND Zlib)").unwrap()
+    );
+
+    assert_eq!(
+        parse_license_expr("(gpl-2.0+ WITH classpath-exception-2.0 OR MIT) AND GPL-3.0")
+            .unwrap()
+            .to_void_licenses(),
+        vec![
+            "GPL-2.0-or-later WITH Classpath-exception-2.0".to_string(),
+            "MIT".to_string(),
+            "GPL-3.0-only".to_string(),
+        ]
+    );
+
+    assert_eq!(
+        parse_license_expr("MPL-1.1+").unwrap().to_void_licenses(),
+        vec!["MPL-1.1+".to_string()]
+    );
+
+    assert_eq!(
+        parse_license_expr("MIT OR Foo-1.0 WITH Bar-exception OR LicenseRef-Custom")
+            .unwrap()
+            .unknown_ids(),
+        vec!["Foo-1.0".to_string(), "Bar-exception".to_string()]
+    );
+
+    assert!(parse_license_expr("MIT OR").is_err());
+    assert!(parse_license_expr("(MIT OR Apache-2.0").is_err());
+    assert!(parse_license_expr("MIT Apache-2.0").is_err());
+    assert!(parse_license_expr("GPL-2.0 WITH").is_err());
+
+    assert_eq!(
+        correct_license(
+            "tmplgen",
+            PkgType::Crate,
+            "MIT AND (Apache-2.0 OR GPL-2.0+)"
+        ),
+        vec![
+            "MIT".to_string(),
+            "Apache-2.0".to_string(),
+            "GPL-2.0-or-later".to_string()
+        ]
+    );
+}
+
+#[test]
+fn test_template_updater() {
+    let dir = tempfile::tempdir().unwrap();
+
+    set_var("XBPS_DISTDIR", dir.path());
+
+    set_env();
+
+    let pkg_info_good = PkgInfo {
+        pkg_name: "rust-tmplgen".to_string(),
+        version: "0.3.1".to_string(),
+        description: Some(
+            "Void Linux template generator for language-specific package managers".to_string(),
+        ),
+        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
+        license: Some(vec!["GPL-3.0-or-later".to_string()]),
+        dependencies: None,
+        sha: "dummy_sha".to_string(),
+        download_url: Some(
+            "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
+        ),
+        native: true,
+        license_files: None,
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
+    };
+
+    let pkg_info_bad = PkgInfo {
+        pkg_name: "rust-tmplgen".to_string(),
+        version: "0.2.9".to_string(),
+        description: Some("gibberish".to_string()),
+        homepage: "htt/ri/tmplgen".to_string(),
+        license: Some(vec!["GPL-3.0-or-later".to_string()]),
+        dependencies: None,
+        sha: "dummy".to_string(),
+        download_url: Some("This Shouldn't be here".to_string()),
+        native: true,
+        license_files: None,
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
+    };
+
+    let bad_tmpl = TmplBuilder::from_pkg_info(pkg_info_bad)
+        .set_type(PkgType::Crate)
+        .generate(true)
+        .unwrap();
+
+    let good_template = TmplBuilder::from_pkg_info(pkg_info_good.clone())
+        .set_type(PkgType::Crate)
+        .update(&bad_tmpl, true)
+        .unwrap();
+
+    assert_eq!(good_template.inner, include_str!("template_test_crate.in"));
+
+    let pkg_info_ok = PkgInfo {
+        pkg_name: "rust-tmplgen".to_string(),
+        version: "0.2.9".to_string(),
+        description: Some(
+            "Void Linux template generator for language-specific package managers".to_string(),
+        ),
+        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
+        license: Some(vec!["GPL-3.0-or-later".to_string()]),
+        dependencies: None,
+        sha: "dummy".to_string(),
+        download_url: Some(
+            "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
+        ),
+        native: true,
+        license_files: None,
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
+    };
+
+    let ok_tmpl = TmplBuilder::from_pkg_info(pkg_info_ok)
+        .set_type(PkgType::Crate)
+        .generate(true)
+        .unwrap();
+
+    let good_templ = TmplBuilder::from_pkg_info(pkg_info_good)
+        .set_type(PkgType::Crate)
+        .update(&ok_tmpl, false)
+        .unwrap();
+
+    assert_eq!(good_templ.inner, include_str!("template_test_crate.in"));
+
+    let diff_url_pkg_info_ok = PkgInfo {
+        pkg_name: "rust-tmplgen".to_string(),
+        version: "0.2.9".to_string(),
+        description: Some(
+            "Void Linux template generator for language-specific package managers".to_string(),
+        ),
+        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
+        license: Some(vec!["GPL-3.0-or-later".to_string()]),
+        dependencies: None,
+        sha: "dummy".to_string(),
+        download_url: Some(
+            "https://github.com/Cogitri/tmplgen/archive/v${version}.tar.gz".to_string(),
+        ),
+        native: true,
+        license_files: None,
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
+    };
+
+    let diff_url_ok_tmpl = TmplBuilder::from_pkg_info(diff_url_pkg_info_ok)
+        .set_type(PkgType::Crate)
+        .generate(true)
+        .unwrap();
+
+    let different_url_pkg_info = PkgInfo {
+        pkg_name: "rust-tmplgen".to_string(),
+        version: "0.3.1".to_string(),
+        description: Some(
+            "Void Linux template generator for language-specific package managers".to_string(),
+        ),
+        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
+        license: Some(vec!["GPL-3.0-or-later".to_string()]),
+        dependencies: None,
+        sha: "dummy".to_string(),
+        download_url: Some(
+            "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
+        ),
+        native: true,
+        license_files: None,
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
+    };
+
+    let diff_sha_templ = TmplBuilder::from_pkg_info(different_url_pkg_info)
+        .set_type(PkgType::Crate)
+        .update(&diff_url_ok_tmpl, false)
+        .unwrap();
+
+    assert_eq!(
+        diff_sha_templ.inner,
+        include_str!("template_test_crate.in")
+            .replace(
+                "dummy_sha",
+                "ac295b3b50ba071599ef2e8aa1bfc2d8d69a69267dee865e3fe0091c769221ca"
+            )
+            .replace(
+                "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate",
+                "https://github.com/Cogitri/tmplgen/archive/v${version}.tar.gz"
+            )
+    );
+
+    dir.close().unwrap();
+}
+
+#[test]
+#[should_panic]
+fn test_template_updater_panic() {
+    let pkg_info_ok = PkgInfo {
+        pkg_name: "rust-tmplgen".to_string(),
+        version: "0.2.9".to_string(),
+        description: Some(
+            "Void Linux template generator for language-specific package managers".to_string(),
+        ),
+        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
+        license: Some(vec!["GPL-3.0-or-later".to_string()]),
+        dependencies: None,
+        sha: "dummy".to_string(),
+        download_url: Some(
+            "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
+        ),
+        native: true,
+        license_files: None,
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
+    };
+
+    let old_tmpl = TmplBuilder::from_pkg_info(pkg_info_ok)
+        .set_type(PkgType::Crate)
+        .generate(true)
+        .unwrap();
+
+    TmplBuilder::new("tmplgen")
+        .update(&old_tmpl, false)
+        .unwrap();
+}
+
+#[test]
+fn test_get_git_author() {
+    set_env();
+
+    assert_eq!(
+        &get_git_author().unwrap(),
+        "tmplgentests <tmplgentests@github.com>"
+    );
+}
+
+#[test]
+#[should_panic]
+fn test_get_info_panic() {
+    TmplBuilder::new("tmplgen").get_info().unwrap();
+}
+
+#[test]
+fn test_set_info() {
+    let pkg_info_good = PkgInfo {
+        pkg_name: "rust-tmplgen".to_string(),
+        version: "0.3.1".to_string(),
+        description: Some(
+            "Void Linux template generator for language-specific package managers".to_string(),
+        ),
+        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
+        license: Some(vec!["GPL-3.0-or-later".to_string()]),
+        dependencies: None,
+        sha: "dummy_sha".to_string(),
+        download_url: Some(
+            "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
+        ),
+        native: true,
+        license_files: None,
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
+    };
+
+    let mut builder = TmplBuilder::new("tmplgen");
+    builder.set_info(pkg_info_good.clone());
+
+    assert_eq!(builder.pkg_info.unwrap(), pkg_info_good);
+}
+
+#[test]
+fn test_gen_deps() {
+    TmplBuilder::new("Scalar-List-Utils")
+        .set_type(PkgType::PerlDist)
+        .get_info()
+        .unwrap()
+        .gen_deps(None)
+        .unwrap();
+    let rspec_deps = TmplBuilder::new("rspec")
+        .set_type(PkgType::Gem)
+        .get_info()
+        .unwrap()
+        .gen_deps(None)
+        .unwrap();
+    assert!(rspec_deps.iter().any(|x| x.name == "ruby-rspec-core"));
+
+    let dir = tempfile::tempdir().unwrap();
+    set_var("XBPS_DISTDIR", dir.path());
+
+    TmplBuilder::new("Scalar-List-Utils")
+        .set_type(PkgType::PerlDist)
+        .get_info()
+        .unwrap()
+        .gen_deps(dir.path().to_str())
+        .unwrap();
+    TmplBuilder::new("diff-lcs")
+        .set_type(PkgType::Gem)
+        .get_info()
+        .unwrap()
+        .gen_deps(dir.path().to_str())
+        .unwrap();
+
+    // Test if already existing deps are successfully skipped.
+    TmplBuilder::new("rspec-core")
+        .set_type(PkgType::Gem)
+        .get_info()
+        .unwrap()
+        .generate(true)
+        .unwrap();
+    TmplBuilder::new("rspec")
+        .set_type(PkgType::Gem)
+        .get_info()
+        .unwrap()
+        .gen_deps(dir.path().to_str())
+        .unwrap();
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_gen_deps_async() {
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let mut tmpl_builder = TmplBuilder::new("rspec");
+    tmpl_builder.set_type(PkgType::Gem);
+
+    let tmpl_builder = runtime.block_on(tmpl_builder.get_info_async()).unwrap();
+
+    let templates = runtime.block_on(tmpl_builder.gen_deps_async(None)).unwrap();
+    let mut names = templates.iter().map(|x| x.name.clone()).collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+
+    // Every dependency is only resolved once
+    assert_eq!(names.len(), templates.len());
+
+    // The direct dependencies of rspec and the ones they depend on in turn
+    for name in &[
+        "ruby-rspec-core",
+        "ruby-rspec-expectations",
+        "ruby-rspec-mocks",
+        "ruby-rspec-support",
+        "ruby-diff-lcs",
+    ] {
+        assert!(names.contains(&name.to_string()), "{} is missing", name);
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    set_var("XBPS_DISTDIR", dir.path());
+
+    assert!(runtime
+        .block_on(tmpl_builder.gen_deps_async(dir.path().to_str()))
+        .is_ok());
+    assert!(runtime
+        .block_on(TmplBuilder::new("Moose").gen_deps_async(None))
+        .is_err());
+}
+
+#[test]
+fn test_gen_deps_without_deps() {
+    let pkg_info = PkgInfo {
+        pkg_name: "rust-tmplgen".to_string(),
+        version: "0.3.1".to_string(),
+        description: None,
+        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
+        license: None,
+        dependencies: None,
+        sha: "dummy_sha".to_string(),
+        download_url: None,
+        native: true,
+        license_files: None,
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
+    };
+
+    let mut tmpl_builder = TmplBuilder::new("tmplgen");
+    tmpl_builder.set_type(PkgType::Crate).set_info(pkg_info);
+
+    assert!(tmpl_builder.gen_deps(None).unwrap().is_empty());
+
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    assert!(runtime
+        .block_on(tmpl_builder.gen_deps_async(None))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+#[should_panic]
+fn test_gen_deps_panic() {
+    TmplBuilder::new("Moose")
+        .set_type(PkgType::PerlDist)
+        .gen_deps(None)
+        .unwrap();
+}
+
+#[test]
+fn test_crate_or_licensing() {
+    let mut builder1 = TmplBuilder::new("cargo-crev");
+    let pkg_info1 = builder1
+        .set_type(PkgType::Crate)
+        .get_info()
+        .unwrap()
+        .pkg_info
+        .as_ref()
+        .unwrap();
+
+    assert_eq!(
+        pkg_info1.license.as_ref().unwrap().join(","),
+        "MPL-2.0,MIT,Apache-2.0"
+    );
+
+    let mut builder2 = TmplBuilder::new("amethyst");
+    let pkg_info2 = builder2
+        .set_type(PkgType::Crate)
+        .get_info()
+        .unwrap()
+        .pkg_info
+        .as_ref()
+        .unwrap();
+
+    assert_eq!(
+        pkg_info2.license.as_ref().unwrap().join(","),
+        "MIT,Apache-2.0"
+    );
+}
+
+#[test]
+fn test_is_license_file() {
+    assert!(is_license_file("LICENSE"));
+    assert!(is_license_file("LICENSE-MIT"));
+    assert!(is_license_file("LICENCE.txt"));
+    assert!(is_license_file("COPYING"));
+    assert!(is_license_file("MIT-LICENSE.md"));
+    assert!(is_license_file("UNLICENSE"));
+    assert!(!is_license_file("README.md"));
+    assert!(!is_license_file("license_checker.rs"));
+}
+
+#[test]
+fn test_inspect_archive() {
+    let mut builder = tar::Builder::new(Vec::new());
+
+    let cargo_toml = "[package]\nname = \"foo\"\n";
+
+    for (path, contents) in &[
+        ("foo-0.1.0/Cargo.toml", cargo_toml),
+        ("foo-0.1.0/lib/Foo.pod", "=head1 NAME\n"),
+        ("foo-0.1.0/doc/manual.md", "# Manual\n"),
+        ("foo-0.1.0/doc", ""),
+        ("foo-0.1.0/src/doc/lib.rs", "fn main() {}\n"),
+        ("foo-0.1.0/build.rs", "fn main() {}\n"),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, path, contents.as_bytes())
+            .unwrap();
+    }
+
+    let tarball = builder.into_inner().unwrap();
+    let contents = inspect_archive(&mut tar::Archive::new(&tarball[..]), 1).unwrap();
+
+    assert_eq!(contents.manifests.get(""), Some(&cargo_toml.to_string()));
+    assert!(contents.files.contains(&"lib/Foo.pod".to_string()));
+    assert_eq!(
+        contents.build_scripts.keys().collect::<Vec<_>>(),
+        vec!["build.rs"]
+    );
+    assert_eq!(
+        contents.doc_size,
+        ("=head1 NAME\n".len() + "# Manual\n".len()) as u64
+    );
+
+    assert!(doc_subpackage(contents.doc_size, &["usr/share/man"]).is_none());
+    assert_eq!(
+        doc_subpackage(1024 * 1024, &["usr/share/man"])
+            .unwrap()
+            .files,
+        vec!["usr/share/man".to_string()]
+    );
+}
+
+#[test]
+fn test_matching_version() {
+    let crate_response: CrateResponse = serde_json::from_value(serde_json::json!({
+        "crate": { "max_version": "0.10.0", "description": null, "homepage": null },
+        "versions": [
+            { "num": "0.11.0-beta.1", "license": "MIT" },
+            { "num": "0.10.0", "license": "MIT" },
+            { "num": "0.9.3", "license": "MIT", "yanked": true },
+            { "num": "0.9.10", "license": "MIT" },
+            { "num": "0.9.2", "license": "MIT" },
+            { "num": "0.8.0", "license": "MIT" },
+        ]
+    }))
+    .unwrap();
+
+    let matching = |req| {
+        matching_version("foo-sys", &crate_response, req)
+            .unwrap()
+            .num
+            .clone()
+    };
+
+    assert_eq!(matching("^0.9"), "0.9.10");
+    assert_eq!(matching("=0.9.3"), "0.10.0");
+    assert_eq!(matching(">= 0.8, < 0.9"), "0.8.0");
+    assert_eq!(matching("not a requirement"), "0.10.0");
+
+    // The version we package is max_version, not the newest (pre)release
+    assert_eq!(
+        latest_version("foo-sys", &crate_response).unwrap().num,
+        "0.10.0"
+    );
+
+    let yanked_response: CrateResponse = serde_json::from_value(serde_json::json!({
+        "crate": { "max_version": "0.1.0", "description": null, "homepage": null },
+        "versions": [{ "num": "0.1.0", "license": "MIT", "yanked": true }]
+    }))
+    .unwrap();
+    assert!(latest_version("foo-sys", &yanked_response)
+        .unwrap_err()
+        .is_not_found());
+}
+
+#[test]
+fn test_is_cargo_c_lib() {
+    assert!(is_cargo_c_lib(
+        "[package]\nname = \"rav1e\"\n[package.metadata.capi]\n[lib]\ncrate-type = [\"lib\", \"cdylib\"]\n"
+    ));
+    assert!(!is_cargo_c_lib(
+        "[package]\nname = \"foo\"\n[lib]\ncrate-type = [\"cdylib\"]\n"
+    ));
+    assert!(!is_cargo_c_lib(
+        "[package]\nname = \"foo\"\n[package.metadata.capi]\n"
+    ));
+    assert!(!is_cargo_c_lib("not toml"));
+}
+
+#[test]
+fn test_crate_targets() {
+    let files = vec![
+        "Cargo.toml".to_string(),
+        "src/lib.rs".to_string(),
+        "src/main.rs".to_string(),
+        "src/bin/foo-helper.rs".to_string(),
+        "src/bin/bar/main.rs".to_string(),
+        "src/bin/bar/util.rs".to_string(),
+        "cli/Cargo.toml".to_string(),
+        "cli/src/main.rs".to_string(),
+    ];
+
+    let targets = crate_targets("[package]\nname = \"foo\"\n", &files, "");
+    assert!(targets.lib);
+    assert_eq!(
+        targets.bins,
+        vec![
+            "foo".to_string(),
+            "foo-helper".to_string(),
+            "bar".to_string()
+        ]
+    );
+
+    let no_autobins = crate_targets(
+        "[package]\nname = \"foo\"\nautobins = false\n[[bin]]\nname = \"baz\"\npath = \"src/baz.rs\"\n",
+        &files,
+        "",
+    );
+    assert_eq!(no_autobins.bins, vec!["baz".to_string()]);
+
+    let library = crate_targets(
+        "[package]\nname = \"foo\"\n",
+        &["Cargo.toml".to_string(), "src/lib.rs".to_string()],
+        "",
+    );
+    assert!(library.lib);
+    assert!(library.bins.is_empty());
+
+    let cli = crate_targets("[package]\nname = \"foo-cli\"\n", &files, "cli");
+    assert!(!cli.lib);
+    assert_eq!(cli.bins, vec!["foo-cli".to_string()]);
+}
+
+#[test]
+fn test_workspace_members() {
+    let mut manifests = std::collections::BTreeMap::new();
+    manifests.insert(String::new(), String::new());
+    manifests.insert("cli".to_string(), String::new());
+    manifests.insert("crates/foo".to_string(), String::new());
+    manifests.insert("crates/foo/fuzz".to_string(), String::new());
+    manifests.insert("examples/bar".to_string(), String::new());
+
+    assert_eq!(
+        workspace_members(
+            "[workspace]\nmembers = [\"cli\", \"crates/*\"]\n",
+            &manifests
+        ),
+        vec![&"cli".to_string(), &"crates/foo".to_string()]
+    );
+    assert!(workspace_members("[package]\nname = \"foo\"\n", &manifests).is_empty());
+}
+
+#[test]
+fn test_binary_crate_template() {
+    set_env();
+
+    let pkg_info = PkgInfo {
+        pkg_name: "tmplgen".to_string(),
+        version: "0.3.1".to_string(),
+        description: Some(
+            "Void Linux template generator for language-specific package managers".to_string(),
+        ),
+        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
+        license: Some(vec!["GPL-3.0-or-later".to_string()]),
+        dependencies: None,
+        sha: "dummy_sha".to_string(),
+        download_url: Some(
+            "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
+        ),
+        native: true,
+        license_files: None,
+        subpackages: Vec::new(),
+        make_install_args: Some("--path cli".to_string()),
+        wrksrc: None,
+    };
+
+    let template = TmplBuilder::from_pkg_info(pkg_info)
+        .set_type(PkgType::Crate)
+        .generate(true)
+        .unwrap();
+
+    assert_eq!(
+        template.inner,
+        include_str!("template_test_crate_noprefix.in").replace(
+            "build_style=cargo\n",
+            "build_style=cargo\nmake_install_args=\"--path cli\"\n"
+        )
+    );
+}
+
+#[test]
+fn test_subpackages() {
+    set_env();
+
+    let pkg_info = PkgInfo {
+        pkg_name: "rust-tmplgen".to_string(),
+        version: "0.3.1".to_string(),
+        description: Some(
+            "Void Linux template generator for language-specific package managers".to_string(),
+        ),
+        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
+        license: Some(vec!["GPL-3.0-or-later".to_string()]),
+        dependencies: Some(Dependencies {
+            host: Some(vec!["cargo-c".to_string()]),
+            make: None,
+            run: None,
+        }),
+        sha: "dummy_sha".to_string(),
+        download_url: Some(
+            "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
+        ),
+        native: true,
+        license_files: None,
+        subpackages: vec![SubPackage {
+            suffix: "devel".to_string(),
+            short_desc: "development files".to_string(),
+            depends: vec!["${sourcepkg}>=${version}_${revision}".to_string()],
+            noarch: false,
+            files: vec!["usr/include".to_string(), "usr/lib/*.so".to_string()],
+        }],
+        make_install_args: None,
+        wrksrc: None,
+    };
+
+    let template = TmplBuilder::from_pkg_info(pkg_info)
+        .set_type(PkgType::Crate)
+        .generate(true)
+        .unwrap();
+
+    assert_eq!(template.subpackages, vec!["rust-tmplgen-devel".to_string()]);
+    assert_eq!(
+        template.inner,
+        include_str!("template_test_crate.in")
+            .replace(
+                "build_style=cargo\n",
+                "build_style=cargo\nhostmakedepends=\"cargo-c\"\n"
+            )
+            .replace(
+                "checksum=dummy_sha\n",
+                "checksum=dummy_sha\n
+do_build() {
+\tcargo cbuild --release --target ${RUST_TARGET} --prefix /usr ${configure_args}
+}
+
+do_install() {
+\tcargo cinstall --release --target ${RUST_TARGET} --prefix /usr --destdir ${DESTDIR} ${configure_args}
+}
+
+rust-tmplgen-devel_package() {
+\tdepends=\"${sourcepkg}>=${version}_${revision}\"
+\tshort_desc+=\" - development files\"
+\tpkg_install() {
+\t\tvmove usr/include
+\t\tvmove \"usr/lib/*.so\"
+\t}
+}
+"
+            )
+    );
+}
+
+#[test]
+fn test_cargo_c_template() {
+    set_env();
+
+    let pkg_info = PkgInfo {
+        pkg_name: "rust-tmplgen".to_string(),
+        version: "0.3.1".to_string(),
+        description: Some(
+            "Void Linux template generator for language-specific package managers".to_string(),
+        ),
+        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
+        license: Some(vec!["MIT".to_string()]),
+        dependencies: Some(Dependencies {
+            host: Some(vec!["cargo-c".to_string()]),
+            make: None,
+            run: None,
+        }),
+        sha: "dummy_sha".to_string(),
+        download_url: Some(
+            "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
+        ),
+        native: true,
+        license_files: Some(vec!["LICENSE".to_string()]),
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
+    };
+
+    let template = TmplBuilder::from_pkg_info(pkg_info)
+        .set_type(PkgType::Crate)
+        .generate(true)
+        .unwrap();
+
+    // cargo-c replaces the build and install steps of the cargo build style, `cargo install`
+    // fails for crates that only have a cdylib/staticlib
+    assert!(template.inner.contains(
+        "do_build() {\n\tcargo cbuild --release --target ${RUST_TARGET} --prefix /usr ${configure_args}\n}\n"
+    ));
+    assert!(template.inner.contains(
+        "do_install() {\n\tcargo cinstall --release --target ${RUST_TARGET} --prefix /usr --destdir ${DESTDIR} ${configure_args}\n\tvlicense LICENSE\n}\n"
+    ));
+    assert!(!template.inner.contains("post_build"));
+    assert!(!template.inner.contains("post_install"));
+}
+
+#[test]
+fn test_archive_license_files() {
+    let mut builder = tar::Builder::new(Vec::new());
+
+    for path in &[
+        "tmplgen-0.1.0/LICENSE-MIT",
+        "tmplgen-0.1.0/LICENSE-APACHE",
+        "tmplgen-0.1.0/README.md",
+        "tmplgen-0.1.0/vendor/LICENSE",
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(0);
+        header.set_cksum();
+        builder.append_data(&mut header, path, &[][..]).unwrap();
+    }
+
+    let tarball = builder.into_inner().unwrap();
+
+    assert_eq!(
+        inspect_archive(&mut tar::Archive::new(&tarball[..]), 1)
+            .unwrap()
+            .license_files,
+        vec!["LICENSE-APACHE".to_string(), "LICENSE-MIT".to_string()]
+    );
+    assert!(inspect_archive(&mut tar::Archive::new(&tarball[..]), 0)
+        .unwrap()
+        .license_files
+        .is_empty());
+}
+
+#[test]
+fn test_crate_license_files() {
+    let mut tmpl_builder = TmplBuilder::new("serde");
+    tmpl_builder.set_type(PkgType::Crate).get_info().unwrap();
+
+    assert_eq!(
+        tmpl_builder.pkg_info.unwrap().license_files,
+        Some(vec![
+            "LICENSE-APACHE".to_string(),
+            "LICENSE-MIT".to_string()
+        ])
+    );
+}
+
+#[test]
+fn test_merge_data() {
+    let mut data: TomlData = toml::from_str(include_str!("../data.toml")).unwrap();
+
+    let overlay: TomlData = toml::from_str(
+        r#"
+[builtin]
+perl = ["Foo-Bar"]
+
+[builtin.perl_dists]
+Foo-Utils = "Foo::Util"
+
+[[licenses.perl]]
+is = "bsd"
+should = "BSD-3-Clause"
+
+[[native_deps.rust]]
+name = "openssl-sys"
+dep = "openssl-devel"
+
+[[native_deps.rust]]
+name = "foo-sys"
+dep = "foo-devel"
+"#,
+    )
+    .unwrap();
+
+    merge_data(&mut data, overlay);
+
+    assert!(data.builtin.perl.contains(&"Foo-Bar".to_string()));
+    assert!(data.builtin.perl.contains(&"perl".to_string()));
+    assert_eq!(
+        data.builtin.perl_dists.get("Foo-Utils"),
+        Some(&"Foo::Util".to_string())
+    );
+    assert_eq!(
+        data.builtin.perl_dists.get("PathTools"),
+        Some(&"Cwd".to_string())
+    );
+
+    let bsd = data
+        .licenses
+        .perl
+        .iter()
+        .filter(|x| x.is == "bsd")
+        .collect::<Vec<&CorrectedLicenses>>();
+    assert_eq!(bsd.len(), 1);
+    assert_eq!(bsd[0].should, Some("BSD-3-Clause".to_string()));
+
+    // Overrides replace the built-in entries instead of adding to them
+    assert_eq!(
+        data.native_deps
+            .rust
+            .iter()
+            .filter(|x| x.name == "openssl-sys")
+            .map(|x| x.dep.as_str())
+            .collect::<Vec<&str>>(),
+        vec!["openssl-devel"]
+    );
+    assert!(data
+        .native_deps
+        .rust
+        .iter()
+        .any(|x| x.name == "foo-sys" && x.dep == "foo-devel"));
+    assert!(data
+        .native_deps
+        .rust
+        .iter()
+        .any(|x| x.name == "libgit2-sys"));
+}
+
+#[test]
+fn test_known_data() {
+    let builtin_data: TomlData = toml::from_str(include_str!("../data.toml")).unwrap();
+    let known_data = KnownData::new(builtin_data).unwrap();
+
+    let runtime_versions = RuntimeVersions::default();
+    let is_built_in = |pkg_name: &str, pkg_type: PkgType| {
+        known_data
+            .is_built_in(pkg_name, pkg_type, None, &runtime_versions)
+            .unwrap()
+    };
+
+    assert!(is_built_in("Carp", PkgType::PerlDist));
+    assert!(is_built_in("Getopt::Long", PkgType::PerlDist));
+    assert!(is_built_in("PathTools", PkgType::PerlDist));
+    assert!(is_built_in("bundler", PkgType::Gem));
+    assert!(!is_built_in("Moose", PkgType::PerlDist));
+    assert!(!is_built_in("rake", PkgType::Crate));
+
+    assert_eq!(
+        known_data.native_deps("nokogiri", PkgType::Gem),
+        &["libxml2-devel".to_string(), "libxslt-devel".to_string()]
+    );
+    assert!(known_data
+        .native_deps("nokogiri", PkgType::Crate)
+        .is_empty());
+    assert!(known_data.is_native_dep("libressl-devel"));
+    assert!(!known_data.is_native_dep("perl-Moose"));
+    assert_eq!(known_data.map_pkg_config("ssl"), Some("libressl-devel"));
+
+    assert_eq!(
+        known_data
+            .license_correction("Perl_5", PkgType::PerlDist)
+            .unwrap()
+            .should,
+        Some("Artistic-1.0-Perl, GPL-1.0-or-later".to_string())
+    );
+    assert_eq!(
+        known_data
+            .license_correction("GPL-2.0+", PkgType::Gem)
+            .unwrap()
+            .should,
+        Some("GPL-2.0-or-later".to_string())
+    );
+    assert!(known_data
+        .license_correction("perl_5", PkgType::Crate)
+        .is_none());
+}
+
+#[test]
+fn test_known_data_invalid() {
+    let invalid_native_dep: TomlData =
+        toml::from_str("[[native_deps.rust]]\nname = \"foo-sys\"\ndep = \"\"\n").unwrap();
+    assert!(KnownData::new(invalid_native_dep).is_err());
+
+    let invalid_license: TomlData = toml::from_str(
+        "[[licenses.perl]]\nis = \"bsd\"\nshould = \"BSD-3-Clause\"\ncandidates = [\"BSD-2-Clause\"]\n",
+    )
+    .unwrap();
+    assert!(KnownData::new(invalid_license).is_err());
+}
+
+#[test]
+fn test_perl_corelist() {
+    let known_data = KnownData::get();
+
+    let perl_526 = RuntimeVersions {
+        perl: Some("5.26".to_string()),
+        ..RuntimeVersions::default()
+    };
+    let perl_536 = RuntimeVersions {
+        perl: Some("5.36.0".to_string()),
+        ..RuntimeVersions::default()
+    };
+
+    // Locale::Codes was removed from core in perl 5.30
+    assert!(known_data
+        .is_built_in("Locale::Codes", PkgType::PerlDist, None, &perl_526)
+        .unwrap());
+    assert!(!known_data
+        .is_built_in("Locale-Codes", PkgType::PerlDist, None, &perl_536)
+        .unwrap());
+
+    // Test::More is dual-lived, perl 5.36.0 ships version 1.302190
+    assert_eq!(
+        known_data
+            .perl_core_version("Test::More", Some("5.36"))
+            .unwrap(),
+        Some("1.302190")
+    );
+    assert!(known_data
+        .is_built_in("Test::More", PkgType::PerlDist, Some("0.88"), &perl_536)
+        .unwrap());
+    assert!(!known_data
+        .is_built_in("Test::More", PkgType::PerlDist, Some("1.302200"), &perl_536)
+        .unwrap());
+
+    // Scalar-List-Utils is dual-lived and checked via List::Util, perl 5.26 ships 1.46_02 and
+    // perl 5.36.0 ships 1.62
+    assert!(!known_data
+        .is_built_in(
+            "Scalar-List-Utils",
+            PkgType::PerlDist,
+            Some("1.56"),
+            &perl_526
+        )
+        .unwrap());
+    assert!(known_data
+        .is_built_in(
+            "Scalar-List-Utils",
+            PkgType::PerlDist,
+            Some("1.56"),
+            &perl_536
+        )
+        .unwrap());
+    assert!(known_data
+        .is_built_in("PathTools", PkgType::PerlDist, None, &perl_526)
+        .unwrap());
+    assert!(!known_data
+        .is_built_in("PathTools", PkgType::PerlDist, Some("3.75"), &perl_526)
+        .unwrap());
+
+    assert!(known_data.perl_versions().contains(&"5.36.0"));
+    assert!(known_data.perl_core_version("Carp", Some("5.8")).is_err());
+}
+
+#[test]
+fn test_numify_perl_version() {
+    let assert_numified = |version: &str, numified: f64| {
+        assert!((numify_perl_version(version) - numified).abs() < 1e-9);
+    };
+
+    assert_numified("1.302190", 1.302190);
+    assert_numified("v1.2.3", 1.002003);
+    assert_numified("1.2.3", 1.002003);
+    assert_numified("2.000_001", 2.000001);
+    assert_numified("0", 0.0);
+    assert_numified("garbage", 0.0);
+    assert!(numify_perl_version("1.10") > numify_perl_version("1.9.0"));
+}
+
+#[test]
+fn test_ruby_gems() {
+    let known_data = KnownData::get();
+
+    let ruby_33 = RuntimeVersions {
+        ruby: Some("3.3".to_string()),
+        ..RuntimeVersions::default()
+    };
+
+    // rake is a bundled gem, json a default gem
+    assert_eq!(
+        known_data.ruby_gem_version("rake", Some("3.3")).unwrap(),
+        Some("13.1.0")
+    );
+    assert_eq!(
+        known_data.ruby_gem_version("json", Some("3.3.0")).unwrap(),
+        Some("2.7.1")
+    );
+    assert_eq!(known_data.ruby_gem_version("nokogiri", None).unwrap(), None);
+
+    assert!(known_data
+        .is_built_in("rake", PkgType::Gem, Some(">= 12.0"), &ruby_33)
+        .unwrap());
+    assert!(known_data
+        .is_built_in("rake", PkgType::Gem, Some("~> 13.0"), &ruby_33)
+        .unwrap());
+    assert!(!known_data
+        .is_built_in("rake", PkgType::Gem, Some("~> 14.0"), &ruby_33)
+        .unwrap());
+    assert!(!known_data
+        .is_built_in("nokogiri", PkgType::Gem, None, &ruby_33)
+        .unwrap());
+
+    assert!(known_data.ruby_versions().contains(&"3.3.0"));
+    assert!(known_data.ruby_gem_version("rake", Some("1.8")).is_err());
+}
+
+#[test]
+fn test_gem_requirement_satisfied() {
+    use std::cmp::Ordering;
+
+    assert_eq!(compare_gem_versions("1.10", "1.9"), Ordering::Greater);
+    assert_eq!(compare_gem_versions("1.0", "1"), Ordering::Equal);
+    assert_eq!(compare_gem_versions("1.0.0.rc1", "1.0.0"), Ordering::Less);
+
+    assert!(gem_requirement_satisfied("13.1.0", ">= 0"));
+    assert!(gem_requirement_satisfied("13.1.0", "13.1.0"));
+    assert!(gem_requirement_satisfied("2.7.1", ">= 2.0, < 3"));
+    assert!(!gem_requirement_satisfied("2.7.1", ">= 2.0, < 2.5"));
+    assert!(gem_requirement_satisfied("2.0.5", "~> 2.0.3"));
+    assert!(!gem_requirement_satisfied("2.1.0", "~> 2.0.3"));
+    assert!(gem_requirement_satisfied("2.9", "~> 2.1"));
+    assert!(!gem_requirement_satisfied("3.0", "~> 2.1"));
+    assert!(!gem_requirement_satisfied("1.0", "!= 1.0"));
+}
+
+#[test]
+fn test_from_template() {
+    let template = |inner: &str, name: &str| Template {
+        inner: inner.to_string(),
+        name: name.to_string(),
+        subpackages: Vec::new(),
+    };
+
+    let tmpl_builder = TmplBuilder::from_template(&template(
+        include_str!("template_test_crate.in"),
+        "rust-tmplgen",
+    ))
+    .unwrap();
+    assert_eq!(tmpl_builder.pkg_name, "tmplgen");
+    assert_eq!(tmpl_builder.pkg_type, Some(PkgType::Crate));
+
+    let tmpl_builder = TmplBuilder::from_template(&template(
+        include_str!("template_test_crate_noprefix.in"),
+        "tmplgen",
+    ))
+    .unwrap();
+    assert_eq!(tmpl_builder.pkg_name, "tmplgen");
+
+    let tmpl_builder = TmplBuilder::from_template(&template(
+        include_str!("template_test_perl.in"),
+        "perl-Moose",
+    ))
+    .unwrap();
+    assert_eq!(tmpl_builder.pkg_name, "Moose");
+    assert_eq!(tmpl_builder.pkg_type, Some(PkgType::PerlDist));
+
+    let tmpl_builder = TmplBuilder::from_template(&template(
+        include_str!("template_test_ruby.in"),
+        "ruby-mocha",
+    ))
+    .unwrap();
+    assert_eq!(tmpl_builder.pkg_name, "mocha");
+    assert_eq!(tmpl_builder.pkg_type, Some(PkgType::Gem));
+
+    assert!(TmplBuilder::from_template(&template(
+        "pkgname=foo\nversion=1.0\nbuild_style=gnu-configure\n",
+        "foo"
+    ))
+    .is_err());
+}
+
+#[test]
+fn test_template_field() {
+    let template = Template {
+        inner: include_str!("template_test_crate.in").to_string(),
+        name: "rust-tmplgen".to_string(),
+        subpackages: Vec::new(),
+    };
+
+    assert_eq!(template.get_field("version"), Some("0.3.1".to_string()));
+    assert_eq!(
+        template.get_field("distfiles"),
+        Some("https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string())
+    );
+    assert_eq!(template.get_field("makedepends"), None);
+}
+
+#[test]
+fn test_split_lang_dep() {
+    assert_eq!(
+        split_lang_dep("Moo>=2.0", PkgType::PerlDist),
+        Some(("Moo".to_string(), Some("2.0".to_string())))
+    );
+    assert_eq!(
+        split_lang_dep("Try-Tiny", PkgType::PerlDist),
+        Some(("Try-Tiny".to_string(), None))
+    );
+    assert_eq!(split_lang_dep("perl", PkgType::PerlDist), None);
+    assert_eq!(
+        split_lang_dep("ruby-metaclass>=0.0.1", PkgType::Gem),
+        Some(("metaclass".to_string(), Some(">= 0.0.1".to_string())))
+    );
+    assert_eq!(
+        split_lang_dep("ruby-rack<3", PkgType::Gem),
+        Some(("rack".to_string(), None))
+    );
+    assert_eq!(split_lang_dep("ruby", PkgType::Gem), None);
+    assert_eq!(split_lang_dep("pkg-config", PkgType::Crate), None);
+}
+
+#[test]
+fn test_check_update() {
+    let mut template = Template {
+        inner: include_str!("template_test_ruby.in").to_string(),
+        name: "ruby-mocha".to_string(),
+        subpackages: Vec::new(),
+    };
+
+    let tmpl_builder = TmplBuilder::from_template(&template).unwrap();
+
+    assert!(tmpl_builder.check_update(&template).unwrap().is_some());
+
+    template.inner = template.inner.replace("version=1.8.0", "version=999.0");
+    assert_eq!(tmpl_builder.check_update(&template).unwrap(), None);
+}
+
+#[test]
+fn test_dep_tree() {
+    let pkg_info = PkgInfo {
+        pkg_name: "rust-openssl-sys".to_string(),
+        dependencies: Some(Dependencies {
+            host: Some(vec!["pkg-config".to_string()]),
+            make: Some(vec!["libressl-devel".to_string()]),
+            run: None,
+        }),
+        ..PkgInfo::default()
+    };
+
+    let dep_tree = TmplBuilder::from_pkg_info(pkg_info)
+        .set_type(PkgType::Crate)
+        .get_dep_tree()
+        .unwrap();
+
+    assert_eq!(
+        dep_tree,
+        vec![
+            DepTree {
+                name: "pkg-config".to_string(),
+                field: "hostmakedepends".to_string(),
+                deps: Vec::new(),
+            },
+            DepTree {
+                name: "libressl-devel".to_string(),
+                field: "makedepends".to_string(),
+                deps: Vec::new(),
+            },
+        ]
+    );
+
+    let pkg_info = PkgInfo {
+        pkg_name: "ruby-mocha".to_string(),
+        dependencies: Some(Dependencies {
+            host: None,
+            make: None,
+            run: Some(vec!["ruby-metaclass>=0.0.1".to_string()]),
+        }),
+        ..PkgInfo::default()
+    };
+
+    let dep_tree = TmplBuilder::from_pkg_info(pkg_info)
+        .set_type(PkgType::Gem)
+        .get_dep_tree()
+        .unwrap();
+
+    assert_eq!(dep_tree.len(), 1);
+    assert_eq!(dep_tree[0].name, "ruby-metaclass>=0.0.1");
+    assert_eq!(dep_tree[0].field, "depends");
+}
+
+#[test]
+fn test_search() {
+    let results = TmplBuilder::new("tmplgen").search(None).unwrap();
+
+    assert_eq!(results[0].name, "tmplgen");
+    assert_eq!(results[0].pkg_type, PkgType::Crate);
+    assert!(!results[0].template_exists);
+
+    // tmplgen is a binary crate, so its template isn't prefixed
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("tmplgen")).unwrap();
+    std::fs::File::create(dir.path().join("tmplgen/template")).unwrap();
+
+    let results = TmplBuilder::new("tmplgen")
+        .set_type(PkgType::Crate)
+        .search(Some(dir.path().to_str().unwrap()))
+        .unwrap();
+
+    assert!(results[0].template_exists);
+
+    let results = TmplBuilder::new("Moo")
+        .set_type(PkgType::PerlDist)
+        .search(None)
+        .unwrap();
+
+    assert_eq!(results[0].name, "Moo");
+    assert!(results.iter().all(|x| x.pkg_type == PkgType::PerlDist));
+
+    let results = TmplBuilder::new("diff-lcs")
+        .set_type(PkgType::Gem)
+        .search(None)
+        .unwrap();
+
+    assert_eq!(results[0].name, "diff-lcs");
+    assert!(results[0].description.is_some());
+}
+
+#[test]
+fn test_get_licenses() {
+    let pkg_info = PkgInfo {
+        pkg_name: "perl-Moo".to_string(),
+        license: Some(vec!["perl_5".to_string(), "perl_5".to_string()]),
+        ..PkgInfo::default()
+    };
+
+    let mut tmpl_builder = TmplBuilder::from_pkg_info(pkg_info);
+
+    assert!(tmpl_builder.get_licenses().is_err());
+
+    assert_eq!(
+        tmpl_builder
+            .set_type(PkgType::PerlDist)
+            .get_licenses()
+            .unwrap(),
+        vec!["Artistic-1.0-Perl", "GPL-1.0-or-later"]
+    );
+}
+
+#[test]
+fn test_registries() {
+    let mut registries = Registries::default();
+    assert_eq!(
+        registries.api_url(PkgType::Crate, "crates/tmplgen"),
+        "https://crates.io/api/v1/crates/tmplgen"
+    );
+    assert_eq!(
+        registries.download_url("https://rubygems.org/downloads/rspec-3.8.0.gem"),
+        "https://rubygems.org/downloads/rspec-3.8.0.gem"
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("registries.toml");
+
+    assert!(read_registries_file(&path).unwrap().is_none());
+
+    std::fs::write(
+        &path,
+        "[crate]\ndownload = \"http://localhost:8080/crates/\"\n\n[perldist]\napi = \"http://localhost:8080/metacpan\"\n",
+    )
+    .unwrap();
+    registries.merge(read_registries_file(&path).unwrap().unwrap());
+
+    assert_eq!(
+        registries.registry(PkgType::Crate).api,
+        "https://crates.io/api/v1"
+    );
+    assert_eq!(
+        registries.download_url("https://static.crates.io/crates/tmplgen/tmplgen-1.3.1.crate"),
+        "http://localhost:8080/crates/tmplgen/tmplgen-1.3.1.crate"
+    );
+    assert_eq!(
+        registries.api_url(PkgType::PerlDist, "release/Moose"),
+        "http://localhost:8080/metacpan/release/Moose"
+    );
+    assert_eq!(
+        registries.download_url("https://github.com/Cogitri/tmplgen/archive/v1.3.1.tar.gz"),
+        "https://github.com/Cogitri/tmplgen/archive/v1.3.1.tar.gz"
+    );
+
+    std::fs::write(&path, "[crate]\napi = \"not a url\"\n").unwrap();
+    assert!(read_registries_file(&path).is_err());
+
+    std::fs::write(&path, "[cargo]\napi = \"http://localhost:8080\"\n").unwrap();
+    assert!(read_registries_file(&path).is_err());
+}
+
+#[test]
+fn test_http_fixtures() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = "https://crates.io/api/v1/crates/tmplgen";
+
+    assert!(replay(dir.path(), url).is_err());
+
+    record(
+        dir.path(),
+        &Response {
+            url: url.to_string(),
+            status: reqwest::StatusCode::NOT_FOUND,
+            body: b"{\"errors\":[]}".to_vec(),
+        },
+    )
+    .unwrap();
+
+    let response = replay(dir.path(), url).unwrap();
+    assert_eq!(response.url, url);
+    assert_eq!(response.status, reqwest::StatusCode::NOT_FOUND);
+    assert_eq!(response.text(), "{\"errors\":[]}");
+    assert!(response.error_for_status().is_err());
+
+    assert!(replay(dir.path(), "https://crates.io/api/v1/crates/libtmplgen").is_err());
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn test_fixtures_recorded() {
+    // With TMPLGEN_HTTP_MODE=replay all tests talking to the registries replay the recorded
+    // responses, so without them they fail with "No response has been recorded"
+    let dir = match http_mode() {
+        HttpMode::Replay(dir) => dir,
+        _ => return,
+    };
+
+    for host in &[
+        "crates.io",
+        "static.crates.io",
+        "rubygems.org",
+        "fastapi.metacpan.org",
+        "cpan.metacpan.org",
+    ] {
+        let recorded = std::fs::read_dir(dir.join(host))
+            .map(|x| {
+                x.filter_map(Result::ok)
+                    .any(|x| x.path().extension().map_or(false, |x| x == "json"))
+            })
+            .unwrap_or(false);
+
+        assert!(
+            recorded,
+            "No responses of {} have been recorded in {}, record them with `TMPLGEN_HTTP_MODE=record cargo test`",
+            host,
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn test_network_policy() {
+    let policy = NetworkPolicy {
+        retries: 2,
+        backoff: std::time::Duration::from_millis(100),
+        max_retry_after: std::time::Duration::from_secs(60),
+        timeout: None,
+        max_concurrent_requests: 8,
+    };
+
+    // Transport errors and timeouts
+    assert_eq!(
+        policy.retry_delay(0, None, None),
+        Some(std::time::Duration::from_millis(100))
+    );
+    assert_eq!(
+        policy.retry_delay(1, Some(reqwest::StatusCode::BAD_GATEWAY), None),
+        Some(std::time::Duration::from_millis(200))
+    );
+    assert_eq!(
+        policy.retry_delay(2, Some(reqwest::StatusCode::BAD_GATEWAY), None),
+        None
+    );
+    assert_eq!(
+        policy.retry_delay(0, Some(reqwest::StatusCode::NOT_FOUND), None),
+        None
+    );
+    assert_eq!(
+        policy.retry_delay(0, Some(reqwest::StatusCode::OK), None),
+        None
+    );
+    assert_eq!(
+        policy.retry_delay(
+            0,
+            Some(reqwest::StatusCode::TOO_MANY_REQUESTS),
+            Some(std::time::Duration::from_secs(5))
+        ),
+        Some(std::time::Duration::from_secs(5))
+    );
+    assert_eq!(
+        policy.retry_delay(
+            0,
+            Some(reqwest::StatusCode::TOO_MANY_REQUESTS),
+            Some(std::time::Duration::from_secs(3600))
+        ),
+        None
+    );
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    assert_eq!(retry_after(&headers), None);
+    headers.insert(
+        reqwest::header::RETRY_AFTER,
+        reqwest::header::HeaderValue::from_static("120"),
+    );
+    assert_eq!(
+        retry_after(&headers),
+        Some(std::time::Duration::from_secs(120))
+    );
+    headers.insert(
+        reqwest::header::RETRY_AFTER,
+        reqwest::header::HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+    );
+    assert_eq!(retry_after(&headers), None);
+}
+
+#[test]
+fn test_provider_error() {
+    let io_error = std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "unexpected end of file");
+
+    let err: Error = ProviderError::checksum("Failed to hash the distfile")
+        .with_url("https://static.crates.io/crates/tmplgen/tmplgen-1.3.1.crate")
+        .with_source(io_error)
+        .into();
+    let err = err
+        .for_pkg(PkgType::Crate, "tmplgen")
+        .for_pkg(PkgType::Gem, "rspec")
+        .for_url("https://example.org");
+
+    assert_eq!(err.kind(), Some(ErrorKind::Checksum));
+    assert!(!err.is_not_found());
+    assert!(!err.is_network());
+    assert_eq!(
+        err.to_string(),
+        "Failed to query the crate tmplgen! Error: Failed to hash the distfile: unexpected end of file (URL: https://static.crates.io/crates/tmplgen/tmplgen-1.3.1.crate)"
+    );
+
+    match &err {
+        Error::Provider(provider_err) => {
+            assert_eq!(provider_err.pkg_type(), Some(PkgType::Crate));
+            assert_eq!(provider_err.pkg_name(), Some("tmplgen"));
+            assert_eq!(
+                failure::Fail::cause(provider_err).map(|x| x.to_string()),
+                Some("unexpected end of file".to_string())
+            );
+        }
+        _ => panic!("Expected a ProviderError, got {:?}", err),
+    }
+
+    assert!(Error::NoSuchPkg("tmplgen".to_string()).is_not_found());
+    assert_eq!(Error::NoSuchPkg("tmplgen".to_string()).kind(), None);
+    assert!(Error::from(ProviderError::not_found(
+        "The crate doesn't exist on crates.io"
+    ))
+    .is_not_found());
+    assert!(Error::from(ProviderError::network("The request timed out")).is_network());
+
+    // Errors of get_json name the package its not-found error is about
+    let not_found: Error = ProviderError::not_found("The gem doesn't exist on rubygems.org")
+        .with_pkg(PkgType::Gem, "rspec")
+        .into();
+    let parse_err =
+        Error::from(ProviderError::parse("Failed to parse the answer")).for_pkg_of(&not_found);
+    assert_eq!(parse_err.kind(), Some(ErrorKind::Parse));
+    assert_eq!(
+        parse_err.to_string(),
+        "Failed to query the gem rspec! Error: Failed to parse the answer"
+    );
+    assert_eq!(
+        Error::NoSuchPkg("rspec".to_string()).for_pkg_of(&not_found),
+        Error::NoSuchPkg("rspec".to_string())
+    );
+}
+
+#[test]
+fn test_pkg_info_json() {
+    set_env();
+
+    let pkg_info = PkgInfo {
+        pkg_name: "rust-tmplgen".to_string(),
+        version: "0.3.1".to_string(),
+        description: Some(
+            "Void Linux template generator for language-specific package managers".to_string(),
+        ),
+        homepage: "https://github.com/Cogitri/tmplgen".to_string(),
+        license: Some(vec!["GPL-3.0-or-later".to_string()]),
+        dependencies: Some(Dependencies {
+            host: None,
+            make: Some(vec!["libgit2-devel".to_string()]),
+            run: None,
+        }),
+        sha: "dummy_sha".to_string(),
+        download_url: Some(
+            "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
+        ),
+        native: true,
+        license_files: None,
+        subpackages: Vec::new(),
+        make_install_args: None,
+        wrksrc: None,
+    };
+
+    let json = serde_json::to_string(&pkg_info).unwrap();
+    assert_eq!(serde_json::from_str::<PkgInfo>(&json).unwrap(), pkg_info);
+
+    // Fields missing from a hand-written PkgInfo are left empty
+    let edited: PkgInfo = serde_json::from_str(
+        r#"{
+            "pkg_name": "rust-tmplgen",
+            "version": "0.3.1",
+            "description": "Generates Void Linux templates",
+            "homepage": "https://github.com/Cogitri/tmplgen",
+            "license": ["GPL-3.0-or-later"],
+            "dependencies": { "make": ["libgit2-devel"] },
+            "sha": "dummy_sha",
+            "download_url": "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate",
+            "native": true
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        edited,
+        PkgInfo {
+            description: Some("Generates Void Linux templates".to_string()),
+            ..pkg_info.clone()
+        }
+    );
+
+    let template = TmplBuilder::from_pkg_info(edited.clone())
+        .set_type(PkgType::Crate)
+        .generate(true)
+        .unwrap();
+    assert!(template
+        .inner
+        .contains("short_desc=\"Generates Void Linux templates\""));
+    assert_eq!(
+        serde_json::from_str::<Template>(&serde_json::to_string(&template).unwrap()).unwrap(),
+        template
+    );
+
+    // PkgInfo doesn't contain the PkgType, so it has to be set
+    assert_eq!(
+        TmplBuilder::from_pkg_info(edited).generate(true).err(),
+        Some(Error::TooLittleInfo(
+            "Can't write a new template without setting the PkgType first!".to_string()
+        ))
+    );
+
+    assert!(serde_json::from_str::<PkgInfo>(r#"{ "pkgname": "rust-tmplgen" }"#).is_err());
+    assert_eq!(
+        serde_json::to_string(&PkgType::PerlDist).unwrap(),
+        "\"perldist\""
     );
 }
@@ -13,13 +13,30 @@
 //You should have received a copy of the GNU General Public License
 //along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
 
+use crate::crates::get_crate_features;
+use crate::data::KnownData;
 use crate::errors::Error;
+use crate::gems::compare_gem_versions;
 use crate::helpers::*;
+use crate::http::async_client;
+use crate::perldist::numify_perl_version;
+use crate::skeleton::{load_skeleton, render_skeleton};
 use crate::types::*;
+use futures::future::{self, Loop};
+use futures::{stream, Future, Stream};
 use log::{debug, info, warn};
 use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 
+/// How many results [search](crate::TmplBuilder::search) returns per registry at most
+const SEARCH_LIMIT: usize = 10;
+
+/// How many dependencies [gen_deps_async](crate::TmplBuilder::gen_deps_async) gets the PkgInfo
+/// of at the same time at most. The requests to every registry are limited separately, too.
+const MAX_CONCURRENT_DEPS: usize = 16;
+
 impl TmplBuilder {
     /// Initializes a new TmplBuilder with nothing but pkg_name set.
     pub fn new(pkg_name: &str) -> Self {
@@ -27,6 +44,10 @@ impl TmplBuilder {
             pkg_name: pkg_name.to_owned(),
             pkg_type: None,
             pkg_info: None,
+            features: CrateFeatures::default(),
+            runtime_versions: RuntimeVersions::default(),
+            skeleton: None,
+            variables: BTreeMap::new(),
         }
     }
 
@@ -36,9 +57,59 @@ impl TmplBuilder {
             pkg_name: pkg_info.pkg_name.clone(),
             pkg_type: None,
             pkg_info: Some(pkg_info),
+            features: CrateFeatures::default(),
+            runtime_versions: RuntimeVersions::default(),
+            skeleton: None,
+            variables: BTreeMap::new(),
         }
     }
 
+    /// Initializes a new TmplBuilder for the package the existing template `template` was written
+    /// for, with pkg_name and pkg_type set. The PkgType is determined from the `build_style` of
+    /// the template.
+    ///
+    /// # Errors
+    ///
+    /// * If the template doesn't use a `build_style` tmplgen writes templates for, e.g. because it
+    ///   isn't for a crate, gem or perldist
+    pub fn from_template(template: &Template) -> Result<Self, Error> {
+        let build_style = template_field(&template.inner, "build_style").unwrap_or_default();
+        let pkg_name =
+            template_field(&template.inner, "pkgname").unwrap_or_else(|| template.name.clone());
+
+        let (pkg_type, prefix) = match build_style.as_str() {
+            "cargo" => (PkgType::Crate, "rust-"),
+            "gem" | "gemspec" => (PkgType::Gem, "ruby-"),
+            "perl-module" => (PkgType::PerlDist, "perl-"),
+            _ => {
+                return Err(Error::WrongUsage {
+                    method: "from_template".to_string(),
+                    err: format!(
+                        "The template of {} uses the build_style '{}', which tmplgen doesn't write templates for!",
+                        pkg_name, build_style
+                    ),
+                });
+            }
+        };
+
+        // Packages of binary crates aren't prefixed, so we go by the distfile if it's from crates.io
+        let crate_name = template_field(&template.inner, "distfiles")
+            .filter(|_| pkg_type == PkgType::Crate)
+            .and_then(|x| {
+                x.split("/crates/")
+                    .nth(1)
+                    .and_then(|x| x.split('/').next())
+                    .map(|x| x.to_string())
+            });
+
+        let mut tmpl_builder = Self::new(
+            &crate_name.unwrap_or_else(|| pkg_name.trim_start_matches(prefix).to_string()),
+        );
+        tmpl_builder.set_type(pkg_type);
+
+        Ok(tmpl_builder)
+    }
+
     /// Gets the PkgType of the package of the TmplBuilder that's passed into the method
     ///
     /// # Errors
@@ -46,11 +117,25 @@ impl TmplBuilder {
     /// * If a package with the name of (self.pkg_name)[crate::TmplBuilder.pkg_name] can be
     ///   found on multiple platforms (e.g. on both (crates.io)[https://crates.io] and (rubygems.org)[https://rubygems.org])
     /// * If the package can't be found on any of the platforms
+    /// * If one of the platforms can't be reached
     pub fn get_type(&mut self) -> Result<&mut Self, Error> {
         self.pkg_type = Some(figure_out_provider(&self.pkg_name)?);
         Ok(self)
     }
 
+    /// Gets the platforms on which a package with the name of (self.pkg_name)[crate::TmplBuilder.pkg_name]
+    /// exists, together with its latest version there. All platforms are queried concurrently.
+    /// Unlike (self.get_type)[crate::tmplwriter::TmplBuilder::get_type] this lets you decide what
+    /// to do if the package exists on several platforms.
+    ///
+    /// # Errors
+    ///
+    /// * If one of the platforms can't be reached, since we can't tell whether the package exists
+    ///   there then
+    pub fn find_providers(&self) -> Result<Vec<ProviderMatch>, Error> {
+        find_providers(&self.pkg_name)
+    }
+
     /// Sets the PkgType of the package of the TmplBuilder that's passed into the method
     pub fn set_type(&mut self, pkg_type: PkgType) -> &mut Self {
         self.pkg_type = Some(pkg_type);
@@ -65,7 +150,12 @@ impl TmplBuilder {
     ///   (self.get_type)[crate::tmplwriter::TmplBuilder::get_type] or (self.set_type)[crate::tmplwriter::TmplBuilder::set_type]
     pub fn get_info(&mut self) -> Result<&mut Self, Error> {
         if self.pkg_type.is_some() {
-            self.pkg_info = Some(get_pkginfo(&self.pkg_name, self.pkg_type.unwrap())?);
+            self.pkg_info = Some(get_pkginfo(
+                &self.pkg_name,
+                self.pkg_type.unwrap(),
+                &self.features,
+                &self.runtime_versions,
+            )?);
             Ok(self)
         } else {
             Err(Error::TooLittleInfo(
@@ -74,60 +164,394 @@ impl TmplBuilder {
         }
     }
 
+    /// Like (self.get_info)[crate::tmplwriter::TmplBuilder::get_info], but returns a future
+    /// instead of blocking until all requests are done, so it can be used in async code. It has to
+    /// be run on a tokio runtime. At most a few requests are sent to every registry at the same
+    /// time.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use futures::Future;
+    /// use libtmplgen::*;
+    ///
+    /// fn get_template() -> Result<Template, Error> {
+    ///     let mut tmpl_builder = TmplBuilder::new("tmplgen");
+    ///     tmpl_builder.set_type(PkgType::Crate);
+    ///
+    ///     let mut runtime = tokio::runtime::Runtime::new()?;
+    ///
+    ///     runtime.block_on(tmpl_builder.get_info_async().and_then(|x| x.generate(true)))
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * The future resolves to the errors of (self.get_info)[crate::tmplwriter::TmplBuilder::get_info]
+    pub fn get_info_async(self) -> Box<dyn Future<Item = Self, Error = Error> + Send> {
+        match async_client() {
+            Ok(client) => self.get_info_with(&client),
+            Err(e) => Box::new(future::err(e)),
+        }
+    }
+
+    fn get_info_with(
+        mut self,
+        client: &reqwest::r#async::Client,
+    ) -> Box<dyn Future<Item = Self, Error = Error> + Send> {
+        let pkg_type = match self.pkg_type {
+            Some(pkg_type) => pkg_type,
+            None => {
+                return Box::new(future::err(Error::TooLittleInfo(
+                    "Can't get PkgInfo without setting/getting PkgType first!".to_string(),
+                )));
+            }
+        };
+
+        Box::new(
+            get_pkginfo_async(
+                client,
+                &self.pkg_name,
+                pkg_type,
+                &self.features,
+                &self.runtime_versions,
+            )
+            .map(move |pkg_info| {
+                self.pkg_info = Some(pkg_info);
+                self
+            }),
+        )
+    }
+
     /// Sets the PkgInfo of the package of the TmplBuilder that's passed into the method
     pub fn set_info(&mut self, pkg_info: PkgInfo) -> &mut Self {
         self.pkg_info = Some(pkg_info);
         self
     }
 
-    /// Checks if a Gem or PerlDist is built into Ruby/Perl.
+    /// Sets the Cargo features the template of a crate should be generated for. This influences
+    /// which native dependencies we add to the template, so it should be called before
+    /// (self.get_info)[crate::tmplwriter::TmplBuilder::get_info]
+    pub fn set_features(&mut self, features: CrateFeatures) -> &mut Self {
+        self.features = features;
+        self
+    }
+
+    /// Sets the perl version (e.g. `5.36.0` or `5.36`) the template of a perldist should be
+    /// generated for. Modules that are part of this version of perl won't be added as dependencies
+    /// (unless a newer version of them is required), so it should be called before
+    /// (self.get_info)[crate::tmplwriter::TmplBuilder::get_info]
+    pub fn set_perl_version(&mut self, perl_version: &str) -> &mut Self {
+        self.runtime_versions.perl = Some(perl_version.to_string());
+        self
+    }
+
+    /// Sets the Ruby version (e.g. `3.3.0` or `3.3`) the template of a gem should be generated
+    /// for. Default and bundled gems of this version of Ruby won't be added as dependencies
+    /// (unless a version of them Ruby doesn't ship is required), so it should be called before
+    /// (self.get_info)[crate::tmplwriter::TmplBuilder::get_info]
+    pub fn set_ruby_version(&mut self, ruby_version: &str) -> &mut Self {
+        self.runtime_versions.ruby = Some(ruby_version.to_string());
+        self
+    }
+
+    /// Sets the skeleton the template is rendered from by
+    /// (self.generate)[crate::tmplwriter::TmplBuilder::generate]. Without one the user-supplied
+    /// skeleton for the PkgType (e.g. `$XDG_CONFIG_HOME/tmplgen/skeletons/crate.in`) or the
+    /// default one is used. See the README for the skeleton syntax and the available variables.
+    pub fn set_skeleton(&mut self, skeleton: &str) -> &mut Self {
+        self.skeleton = Some(skeleton.to_string());
+        self
+    }
+
+    /// Sets the custom variable `name`, which the skeleton can use as `{{ name }}`. Custom
+    /// variables take precedence over the built-in ones of the same name.
+    pub fn set_variable(&mut self, name: &str, value: &str) -> &mut Self {
+        self.variables.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Gets the Cargo features of the crate and the features/optional dependencies each of them
+    /// enables.
     ///
     /// # Errors
     ///
     /// * If you try to call this method without setting/getting pkg_type first via either
     ///   (self.get_type)[crate::tmplwriter::TmplBuilder::get_type] or (self.set_type)[crate::tmplwriter::TmplBuilder::set_type]
-    pub fn is_built_in(&self) -> Result<bool, Error> {
-        if self.pkg_type.is_some() {
-            let data: TomlData = toml::from_str(include_str!("data.toml")).unwrap();
+    /// * If the package isn't a crate
+    /// * If crates.io can't be queried
+    pub fn get_features(&self) -> Result<BTreeMap<String, Vec<String>>, Error> {
+        match self.pkg_type {
+            Some(PkgType::Crate) => Ok(get_crate_features(&self.pkg_name)?.into_iter().collect()),
+            Some(_) => Err(Error::WrongUsage {
+                method: "get_features".to_string(),
+                err: "Only crates have features!".to_string(),
+            }),
+            None => Err(Error::TooLittleInfo(
+                "Can't get features without setting/getting PkgType first!".to_string(),
+            )),
+        }
+    }
 
-            let built_ins = BuiltInDeps {
-                perl: data.builtin.perl,
-                ruby: data.builtin.ruby,
-            };
+    /// Checks if a Gem or PerlDist is built into Ruby/Perl. For PerlDists the perl version set via
+    /// (self.set_perl_version)[crate::tmplwriter::TmplBuilder::set_perl_version] is checked, for
+    /// Gems the Ruby version set via
+    /// (self.set_ruby_version)[crate::tmplwriter::TmplBuilder::set_ruby_version].
+    ///
+    /// # Errors
+    ///
+    /// * If you try to call this method without setting/getting pkg_type first via either
+    ///   (self.get_type)[crate::tmplwriter::TmplBuilder::get_type] or (self.set_type)[crate::tmplwriter::TmplBuilder::set_type]
+    /// * If tmplgen doesn't know about the perl version set via
+    ///   (self.set_perl_version)[crate::tmplwriter::TmplBuilder::set_perl_version] or the Ruby
+    ///   version set via (self.set_ruby_version)[crate::tmplwriter::TmplBuilder::set_ruby_version]
+    pub fn is_built_in(&self) -> Result<bool, Error> {
+        match self.pkg_type {
+            Some(pkg_type) => {
+                let is_built_in = KnownData::get().is_built_in(
+                    &self.pkg_name,
+                    pkg_type,
+                    None,
+                    &self.runtime_versions,
+                )?;
 
-            if self.pkg_type.unwrap() == PkgType::Gem {
-                for x in built_ins.ruby {
-                    if self.pkg_name == x {
-                        info!(
-                            "Gem {} is part of ruby, won't write a template for it!",
-                            self.pkg_name
-                        );
-                        return Ok(true);
-                    }
-                }
-            } else if self.pkg_type.unwrap() == PkgType::PerlDist {
-                let pkg_name = self.pkg_name.replace("::", "-");
-
-                for x in built_ins.perl {
-                    if pkg_name == x {
-                        info!(
-                            "Perl distribution {} is part of perl, won't write a template for it!",
-                            pkg_name
-                        );
-                        return Ok(true);
-                    }
+                if is_built_in && pkg_type == PkgType::Gem {
+                    info!(
+                        "Gem {} is part of ruby, won't write a template for it!",
+                        self.pkg_name
+                    );
+                } else if is_built_in {
+                    info!(
+                        "Perl distribution {} is part of perl, won't write a template for it!",
+                        self.pkg_name.replace("::", "-")
+                    );
                 }
+
+                Ok(is_built_in)
             }
+            None => Err(Error::TooLittleInfo(
+                "Can't check if Pkg is built in without setting/getting PkgType first!".to_string(),
+            )),
+        }
+    }
 
-            // Crates can't be built in
-            Ok(false)
+    /// Gets the latest version of the package. This is a lot cheaper than getting its PkgInfo,
+    /// which requires downloading its distfile.
+    ///
+    /// # Errors
+    ///
+    /// * If you try to call this method without setting/getting pkg_type first via either
+    ///   (self.get_type)[crate::tmplwriter::TmplBuilder::get_type] or (self.set_type)[crate::tmplwriter::TmplBuilder::set_type]
+    /// * If the package can't be found in the registry of its PkgType
+    pub fn get_latest_version(&self) -> Result<String, Error> {
+        match self.pkg_type {
+            Some(pkg_type) => get_latest_version(&self.pkg_name, pkg_type),
+            None => Err(Error::TooLittleInfo(
+                "Can't get the latest version without setting/getting PkgType first!".to_string(),
+            )),
+        }
+    }
+
+    /// Checks if a newer version of the package than the one of the existing template
+    /// `template` is available. Returns the newer version if so.
+    ///
+    /// # Errors
+    ///
+    /// * If `template` doesn't contain a `version`
+    /// * If the latest version can't be determined, see
+    ///   (self.get_latest_version)[crate::tmplwriter::TmplBuilder::get_latest_version]
+    pub fn check_update(&self, template: &Template) -> Result<Option<String>, Error> {
+        let version = match template_field(&template.inner, "version") {
+            Some(version) => version,
+            None => {
+                return Err(Error::TmplUpdater(format!(
+                    "Couldn't find 'version' in the template of {}",
+                    template.name
+                )));
+            }
+        };
+
+        let latest_version = self.get_latest_version()?;
+
+        // Perl versions are decimals, e.g. 0.1 is newer than 0.08
+        let ordering = if self.pkg_type == Some(PkgType::PerlDist) {
+            numify_perl_version(&latest_version)
+                .partial_cmp(&numify_perl_version(&version))
+                .unwrap_or(Ordering::Equal)
         } else {
-            Err(Error::TooLittleInfo(
-                "Can't check if Pkg is built in without setting/getting PkgType first!".to_string(),
-            ))
+            compare_gem_versions(&latest_version, &version)
+        };
+
+        if ordering == Ordering::Greater {
+            Ok(Some(latest_version))
+        } else {
+            Ok(None)
         }
     }
 
+    /// Gets the licenses of the package as they're written to the template, i.e. converted to
+    /// the SPDX identifiers Void uses
+    ///
+    /// # Errors
+    ///
+    /// * If you try to call this method without setting/getting pkg_type and pkg_info first
+    pub fn get_licenses(&self) -> Result<Vec<String>, Error> {
+        let (pkg_info, pkg_type) = match (&self.pkg_info, self.pkg_type) {
+            (Some(pkg_info), Some(pkg_type)) => (pkg_info, pkg_type),
+            _ => {
+                return Err(Error::TooLittleInfo(
+                    "Can't get the licenses without setting/getting PkgType and PkgInfo first!"
+                        .to_string(),
+                ));
+            }
+        };
+
+        let mut licenses = pkg_info
+            .license
+            .as_ref()
+            .unwrap_or(&Vec::new())
+            .iter()
+            .flat_map(|x| correct_license(&pkg_info.pkg_name, pkg_type, x))
+            .collect::<Vec<String>>();
+
+        dedup_vec(&mut licenses);
+
+        Ok(licenses)
+    }
+
+    /// Searches the registries for packages matching pkg_name, or only the registry of pkg_type
+    /// if it's set. Packages whose name matches pkg_name exactly come first, the rest are in
+    /// the order the registries ranked them in.
+    ///
+    /// # Arguments
+    ///
+    /// Takes the optional argument 'tmpl_path', the srcpkgs directory which is used to check if a
+    /// template exists for the packages already
+    ///
+    /// # Errors
+    ///
+    /// * If none of the registries can be reached
+    pub fn search(&self, tmpl_path: Option<&str>) -> Result<Vec<SearchResult>, Error> {
+        let pkg_types = match self.pkg_type {
+            Some(pkg_type) => vec![pkg_type],
+            None => PkgType::all().to_vec(),
+        };
+
+        let responses = pkg_types
+            .par_iter()
+            .map(|pkg_type| search_registry(&self.pkg_name, *pkg_type, SEARCH_LIMIT))
+            .collect::<Vec<_>>();
+
+        let mut results = Vec::new();
+        let mut errors = Vec::new();
+
+        for response in responses {
+            match response {
+                Ok(mut found) => results.append(&mut found),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        // Only fail if we didn't get any answer, a registry being down shouldn't break searching the others
+        if errors.len() == pkg_types.len() {
+            return Err(errors.remove(0));
+        }
+
+        for e in errors {
+            warn!("Couldn't search all registries: {}", e);
+        }
+
+        if let Some(tmpl_path) = tmpl_path {
+            for result in &mut results {
+                let tmpl_name = match result.pkg_type {
+                    PkgType::Crate => format!("rust-{}", result.name),
+                    PkgType::Gem => format!("ruby-{}", result.name),
+                    PkgType::PerlDist => format!("perl-{}", result.name),
+                };
+
+                // Packages of binary crates aren't prefixed
+                result.template_exists =
+                    Path::new(&format!("{}/{}/template", tmpl_path, tmpl_name)).exists()
+                        || (result.pkg_type == PkgType::Crate
+                            && Path::new(&format!("{}/{}/template", tmpl_path, result.name))
+                                .exists());
+            }
+        }
+
+        let query = self.pkg_name.replace("::", "-");
+        results.sort_by_key(|x| !x.name.eq_ignore_ascii_case(&query));
+
+        Ok(results)
+    }
+
+    /// Gets the dependencies of the package as a tree. The dependencies of perldists and gems
+    /// are resolved recursively, which requires getting the PkgInfo of each of them.
+    ///
+    /// # Errors
+    ///
+    /// * If you try to call this method without setting/getting pkg_type and pkg_info first
+    /// * If the PkgInfo of a dependency can't be determined
+    pub fn get_dep_tree(&self) -> Result<Vec<DepTree>, Error> {
+        let mut seen = BTreeSet::new();
+        seen.insert(self.pkg_name.clone());
+
+        self.dep_tree(&mut seen)
+    }
+
+    fn dep_tree(&self, seen: &mut BTreeSet<String>) -> Result<Vec<DepTree>, Error> {
+        let (pkg_info, pkg_type) = match (&self.pkg_info, self.pkg_type) {
+            (Some(pkg_info), Some(pkg_type)) => (pkg_info, pkg_type),
+            _ => {
+                return Err(Error::TooLittleInfo(
+                    "Can't get the dependency tree without setting/getting PkgType and PkgInfo first!"
+                        .to_string(),
+                ));
+            }
+        };
+
+        let dependencies = match &pkg_info.dependencies {
+            Some(dependencies) => dependencies,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut tree = Vec::new();
+
+        for (field, deps) in &[
+            ("hostmakedepends", &dependencies.host),
+            ("makedepends", &dependencies.make),
+            ("depends", &dependencies.run),
+        ] {
+            for dep in deps.iter().flatten() {
+                let mut node = DepTree {
+                    name: gen_dep_string(&[dep.clone()], pkg_type),
+                    field: field.to_string(),
+                    deps: Vec::new(),
+                };
+
+                if let Some((dep_name, required_version)) = split_lang_dep(dep, pkg_type) {
+                    // Only resolve every dependency once, this also protects us from cycles
+                    if seen.insert(dep_name.clone())
+                        && !KnownData::get().is_built_in(
+                            &dep_name,
+                            pkg_type,
+                            required_version.as_ref().map(|x| x.as_str()),
+                            &self.runtime_versions,
+                        )?
+                    {
+                        let mut tmpl_builder = Self::new(&dep_name);
+                        tmpl_builder.runtime_versions = self.runtime_versions.clone();
+
+                        node.deps = tmpl_builder.set_type(pkg_type).get_info()?.dep_tree(seen)?;
+                    }
+                }
+
+                tree.push(node);
+            }
+        }
+
+        Ok(tree)
+    }
+
     /// Helper method to get a Vec<[Template](crate::types::Template)> of all dependencies a
     /// package has. Also includes recursive dependencies.
     ///
@@ -143,19 +567,95 @@ impl TmplBuilder {
     /// * If something went wrong while generating Templates for all dependencies of [self.pkg_name](crate::TmplBuilder.pkg_name)
     // TODO: Make this prettier so we don't needlessly generate templates twice if dep x and y both depend on z
     pub fn gen_deps(&self, tmpl_path: Option<&str>) -> Result<Vec<Template>, Error> {
-        if self.pkg_info.is_some() {
-            let deps = self
-                .pkg_info
-                .as_ref()
-                .unwrap()
-                .dependencies
-                .as_ref()
-                .unwrap();
+        let mut tmpl_vec = Vec::new();
+
+        for mut tmpl_builder in self.dep_builders(tmpl_path)? {
+            tmpl_vec.push(tmpl_builder.get_info()?.generate(true)?);
+
+            tmpl_vec.append(&mut tmpl_builder.gen_deps(tmpl_path)?)
+        }
+
+        Ok(tmpl_vec)
+    }
+
+    /// Like (self.gen_deps)[crate::tmplwriter::TmplBuilder::gen_deps], but returns a future
+    /// instead of blocking until all requests are done, so it can be used in async code. It has to
+    /// be run on a tokio runtime. The dependencies are resolved level by level, getting the
+    /// PkgInfo of several of them at the same time, and every dependency is only resolved once.
+    ///
+    /// # Errors
+    ///
+    /// * The future resolves to the errors of (self.gen_deps)[crate::tmplwriter::TmplBuilder::gen_deps]
+    pub fn gen_deps_async(
+        &self,
+        tmpl_path: Option<&str>,
+    ) -> Box<dyn Future<Item = Vec<Template>, Error = Error> + Send> {
+        let dep_builders = match self.dep_builders(tmpl_path) {
+            Ok(dep_builders) => dep_builders,
+            Err(e) => return Box::new(future::err(e)),
+        };
+
+        let client = match async_client() {
+            Ok(client) => client,
+            Err(e) => return Box::new(future::err(e)),
+        };
+
+        let tmpl_path = tmpl_path.map(|x| x.to_string());
 
+        let mut seen = BTreeSet::new();
+        seen.insert(self.pkg_name.clone());
+        seen.extend(dep_builders.iter().map(|x| x.pkg_name.clone()));
+
+        Box::new(future::loop_fn(
+            (dep_builders, seen, Vec::new()),
+            move |(level, mut seen, mut tmpl_vec)| {
+                let level_client = client.clone();
+                let tmpl_path = tmpl_path.clone();
+
+                stream::iter_ok(level)
+                    .map(move |tmpl_builder| tmpl_builder.get_info_with(&level_client))
+                    .buffered(MAX_CONCURRENT_DEPS)
+                    .collect()
+                    .and_then(move |tmpl_builders| {
+                        let mut next_level = Vec::new();
+
+                        for tmpl_builder in tmpl_builders {
+                            tmpl_vec.push(tmpl_builder.generate(true)?);
+
+                            for dep_builder in
+                                tmpl_builder.dep_builders(tmpl_path.as_ref().map(|x| x.as_str()))?
+                            {
+                                if seen.insert(dep_builder.pkg_name.clone()) {
+                                    next_level.push(dep_builder);
+                                }
+                            }
+                        }
+
+                        Ok(if next_level.is_empty() {
+                            Loop::Break(tmpl_vec)
+                        } else {
+                            Loop::Continue((next_level, seen, tmpl_vec))
+                        })
+                    })
+            },
+        ))
+    }
+
+    /// Returns TmplBuilders, with pkg_type set, for the dependencies of the package we have to
+    /// write templates for, i.e. those that aren't built-in and don't have a template in
+    /// `tmpl_path` yet
+    fn dep_builders(&self, tmpl_path: Option<&str>) -> Result<Vec<Self>, Error> {
+        if self.pkg_info.is_none() {
+            return Err(Error::TooLittleInfo(
+                "Can't create Templates for deps without setting/getting PkgInfo of the package first!".to_string(),
+            ));
+        }
+
+        if let Some(deps) = self.pkg_info.as_ref().and_then(|x| x.dependencies.as_ref()) {
             let mut run_vec = if let Some(run_deps) = &deps.run {
                 run_deps
                     .par_iter()
-                    .filter(|x| x == &&"perl".to_string() || x == &&"ruby".to_string())
+                    .filter(|x| x != &&"perl".to_string() && x != &&"ruby".to_string())
                     .collect::<Vec<&String>>()
             } else {
                 Vec::new()
@@ -164,7 +664,7 @@ impl TmplBuilder {
             let mut make_vec = if let Some(make_deps) = &deps.make {
                 make_deps
                     .par_iter()
-                    .filter(|x| x == &&"perl".to_string() || x == &&"ruby".to_string())
+                    .filter(|x| x != &&"perl".to_string() && x != &&"ruby".to_string())
                     .collect::<Vec<&String>>()
             } else {
                 Vec::new()
@@ -172,7 +672,7 @@ impl TmplBuilder {
 
             run_vec.append(&mut make_vec);
 
-            let mut tmpl_vec = Vec::new();
+            let mut dep_builders = Vec::new();
 
             for x in run_vec {
                 let pkg_tainted = x.split('>').collect::<Vec<&str>>()[0]
@@ -181,12 +681,26 @@ impl TmplBuilder {
 
                 let pkg = pkg_tainted.replace("perl-", "").replace("ruby-", "");
 
+                // Dependencies on a newer version of a built-in package look like `Foo>=1.0`
+                let required_version = x.split(">=").nth(1).map(|x| {
+                    if self.pkg_type == Some(PkgType::Gem) {
+                        format!(">= {}", x.trim())
+                    } else {
+                        x.trim().to_string()
+                    }
+                });
+
                 let mut tmpl_builder = Self::new(&pkg);
+                tmpl_builder.runtime_versions = self.runtime_versions.clone();
+                tmpl_builder.skeleton = self.skeleton.clone();
+                tmpl_builder.variables = self.variables.clone();
 
-                if tmpl_builder
-                    .set_type(self.pkg_type.unwrap())
-                    .is_built_in()?
-                {
+                if KnownData::get().is_built_in(
+                    &pkg,
+                    self.pkg_type.unwrap(),
+                    required_version.as_ref().map(|x| x.as_str()),
+                    &self.runtime_versions,
+                )? {
                     debug!("Won't write template for built-in package {}", pkg);
                     continue;
                 } else if tmpl_path.is_some() {
@@ -211,20 +725,13 @@ impl TmplBuilder {
                     }
                 }
 
-                tmpl_vec.push(tmpl_builder.get_info()?.generate(true)?);
-
-                tmpl_vec.append(
-                    &mut Self::new(&pkg)
-                        .set_type(self.pkg_type.unwrap())
-                        .get_info()?
-                        .gen_deps(tmpl_path)?,
-                )
+                tmpl_builder.set_type(self.pkg_type.unwrap());
+                dep_builders.push(tmpl_builder);
             }
-            Ok(tmpl_vec)
+            Ok(dep_builders)
         } else {
-            Err(Error::TooLittleInfo(
-                "Can't create Templates for deps without setting/getting PkgInfo of the package first!".to_string(),
-            ))
+            // E.g. crates without native dependencies or a PkgInfo read from JSON
+            Ok(Vec::new())
         }
     }
 
@@ -250,9 +757,14 @@ impl TmplBuilder {
     ///            download_url: Some(
     ///               "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
     ///            ),
+    ///            native: true,
+    ///            license_files: None,
+    ///            subpackages: Vec::new(),
+    ///            make_install_args: None,
+    ///            wrksrc: None,
     ///     };
     ///
-    ///     let mut old_template = Template { inner: String::new(), name: "tmplgen".to_string() };
+    ///     let mut old_template = Template { inner: String::new(), name: "tmplgen".to_string(), subpackages: Vec::new() };
     ///     // Open whatever file you want to below.
     ///     let mut file = File::open("src/lib/tests/template_test_crate.in")?;
     ///     file.read_to_string(&mut old_template.inner)?;
@@ -396,6 +908,7 @@ impl TmplBuilder {
         Ok(Template {
             inner: template_string.to_owned(),
             name: pkg_info.pkg_name.clone(),
+            subpackages: Vec::new(),
         })
     }
 
@@ -422,6 +935,11 @@ impl TmplBuilder {
     ///            download_url: Some(
     ///               "https://static.crates.io/crates/tmplgen/tmplgen-${version}.crate".to_string(),
     ///            ),
+    ///            native: true,
+    ///            license_files: None,
+    ///            subpackages: Vec::new(),
+    ///            make_install_args: None,
+    ///            wrksrc: None,
     ///     };
     ///
     ///     // Use TmplBuilder::new("tmplgen").get_type.generate() to do this automatically instead of
@@ -449,19 +967,33 @@ impl TmplBuilder {
             ))
         }?;
 
-        // Can't have pkg_info without pkg_type, so we only have to check for pkg_info's existance
-        let tmpl_type = self.pkg_type.unwrap();
+        // PkgInfo set via set_info (e.g. read from JSON) doesn't tell us the PkgType
+        let tmpl_type = match self.pkg_type {
+            Some(pkg_type) => pkg_type,
+            None => {
+                return Err(Error::TooLittleInfo(
+                    "Can't write a new template without setting the PkgType first!".to_string(),
+                ))
+            }
+        };
 
-        let template_in = include_str!("template.in");
+        let skeleton = match &self.skeleton {
+            Some(skeleton) => skeleton.clone(),
+            None => load_skeleton(tmpl_type)?,
+        };
 
         let maintainer = get_git_author()?;
 
-        let mut template_string = template_in
-            .replace("@version@", &pkg_info.version)
-            .replace("@maintainer@", &maintainer)
-            .replace("@pkgname@", &pkg_info.pkg_name)
-            .replace("@checksum@", &pkg_info.sha)
-            .replace("@homepage@", &pkg_info.homepage);
+        let mut vars = BTreeMap::new();
+        let mut set_var = |name: &str, value: &str| {
+            vars.insert(name.to_string(), SkeletonValue::Str(value.to_string()));
+        };
+
+        set_var("version", &pkg_info.version);
+        set_var("maintainer", &maintainer);
+        set_var("pkgname", &pkg_info.pkg_name);
+        set_var("checksum", &pkg_info.sha);
+        set_var("homepage", &pkg_info.homepage);
 
         if pkg_info.description.is_some() {
             let mut description = check_string_len(
@@ -474,117 +1006,170 @@ impl TmplBuilder {
                 description.pop();
             }
 
-            template_string = template_string.replace("@description@", &description)
+            set_var("short_desc", &description);
         } else {
             warn!(
                 "Couldn't determine field 'description'! Please add it to the template yourself.",
             );
+            // Leave a marker in the template for the maintainer
+            set_var("short_desc", "@description@");
         }
 
-        if pkg_info.license.is_some() {
-            let license_iter = pkg_info.license.as_ref().unwrap().par_iter();
-            let mut license = license_iter
-                .clone()
-                .take(1)
-                .map(|x| correct_license(x))
-                .collect::<String>();
-
-            license.push_str(
-                &license_iter
-                    .skip(1)
-                    .map(|x| format!(", {}", correct_license(x)))
-                    .collect::<String>(),
-            );
+        let licenses = self.get_licenses()?;
 
-            template_string = template_string.replace("@license@", &license)
+        if !licenses.is_empty() {
+            set_var("license", &licenses.join(", "));
         } else {
             warn!("Couldn't determine field 'license'! Please add it to the template yourself.",);
+            set_var("license", "@license@");
         }
 
-        if pkg_info.dependencies.is_some() {
-            let dependencies = pkg_info.dependencies.as_ref().unwrap();
-
-            if dependencies.host.is_some() {
-                let host_depends = gen_dep_string(dependencies.host.as_ref().unwrap(), tmpl_type);
-                template_string =
-                    template_string.replace("@hostmakedepends@", &host_depends.trim_end());
-            } else {
-                template_string =
-                    template_string.replace("\nhostmakedepends=\"@hostmakedepends@\"", "");
+        if let Some(dependencies) = &pkg_info.dependencies {
+            if let Some(host_deps) = &dependencies.host {
+                set_var(
+                    "hostmakedepends",
+                    gen_dep_string(host_deps, tmpl_type).trim_end(),
+                );
             }
-            if dependencies.make.is_some() {
-                let make_depends = gen_dep_string(dependencies.make.as_ref().unwrap(), tmpl_type);
-                template_string =
-                    template_string.replace("@makedepends@", &make_depends.trim_end());
-            } else {
-                template_string = template_string.replace("\nmakedepends=\"@makedepends@\"", "");
+            if let Some(make_deps) = &dependencies.make {
+                set_var(
+                    "makedepends",
+                    gen_dep_string(make_deps, tmpl_type).trim_end(),
+                );
             }
-            if dependencies.run.is_some() {
-                let run_depends = gen_dep_string(dependencies.run.as_ref().unwrap(), tmpl_type);
-                template_string = template_string.replace("@depends@", &run_depends.trim_end());
-            } else {
-                template_string = template_string.replace("\ndepends=\"@depends@\"", "");
+            if let Some(run_deps) = &dependencies.run {
+                set_var("depends", gen_dep_string(run_deps, tmpl_type).trim_end());
             }
-        } else {
-            template_string = template_string.replace("\ndepends=\"@depends@\"", "");
-            template_string = template_string.replace("\nmakedepends=\"@makedepends@\"", "");
-            template_string =
-                template_string.replace("\nhostmakedepends=\"@hostmakedepends@\"", "");
         }
 
-        if pkg_info.download_url.is_some() {
-            template_string =
-                template_string.replace("@distfiles@", &pkg_info.download_url.as_ref().unwrap());
-        } else {
-            template_string = template_string.replace("\ndistfiles=\"@distfiles@\"", "")
+        if let Some(download_url) = &pkg_info.download_url {
+            set_var("distfiles", download_url);
         }
 
-        if tmpl_type == PkgType::PerlDist {
-            template_string = template_string
-                .replace("@build_style@", "perl-module")
-                .replace("@archs@", "noarch");
-        } else if tmpl_type == PkgType::Gem {
-            template_string = template_string
-                .replace("@build_style@", "gem")
-                .replace("\nwrksrc=\"@wrksrc@\"", "")
-                .replace("\narchs=@archs@", "");
-        } else {
-            template_string = template_string
-                .replace("@pkgname@", &pkg_info.pkg_name)
-                .replace("\ndepends=\"@depends@\"", "")
-                .replace("@build_style@", "cargo")
-                .replace("\narchs=@archs@", "");
+        if tmpl_type == PkgType::Crate {
+            let mut configure_args = Vec::new();
+
+            if self.features.no_default_features {
+                configure_args.push("--no-default-features".to_string());
+            }
+
+            if !self.features.features.is_empty() {
+                configure_args.push(format!("--features {}", self.features.features.join(",")));
+            }
+
+            if !configure_args.is_empty() {
+                set_var("configure_args", &configure_args.join(" "));
+            }
         }
 
-        if prefix {
-            let prefix_str = if tmpl_type == PkgType::Crate {
-                "rust-"
-            //} else if tmpl_type == PkgType::Gem {
-            //    "ruby-"
-            } else {
-                "perl-"
-            };
+        let build_style = match tmpl_type {
+            PkgType::Crate => "cargo",
+            PkgType::PerlDist => "perl-module",
+            // gem_info only sets distfiles for gems with native extensions whose sources
+            // we know, which we build from their gemspec.
+            PkgType::Gem if pkg_info.download_url.is_some() => "gemspec",
+            PkgType::Gem => "gem",
+        };
+
+        set_var("build_style", build_style);
 
-            let wrksrc = format!("${{pkgname/{}/}}-${{version}}", prefix_str);
+        // crate_info adds cargo-c for C libraries, which have to be built and installed with it
+        let uses_cargo_c = pkg_info
+            .dependencies
+            .as_ref()
+            .and_then(|x| x.host.as_ref())
+            .map_or(false, |x| x.contains(&"cargo-c".to_string()));
 
-            template_string = template_string.replace("@wrksrc@", &wrksrc);
+        if tmpl_type == PkgType::Crate && uses_cargo_c {
+            set_var("cargo_c", "yes");
+        }
+
+        // XS perldists and gems with native extensions are architecture-dependent
+        if tmpl_type != PkgType::Crate && !pkg_info.native {
+            set_var("archs", "noarch");
+        }
+
+        let prefix_str = if tmpl_type == PkgType::Crate {
+            "rust-"
+        } else if tmpl_type == PkgType::Gem {
+            "ruby-"
         } else {
-            template_string = template_string.replace("\nwrksrc=\"@wrksrc@\"", "");
+            "perl-"
+        };
+
+        // Gems are built from the gem itself, which doesn't have a wrksrc. Packages of binary
+        // crates aren't prefixed, so their distfile extracts to the default wrksrc.
+        if let Some(wrksrc) = &pkg_info.wrksrc {
+            set_var("wrksrc", wrksrc);
+        } else if prefix && build_style != "gem" && pkg_info.pkg_name.starts_with(prefix_str) {
+            set_var(
+                "wrksrc",
+                &format!("${{pkgname/{}/}}-${{version}}", prefix_str),
+            );
+        }
+
+        if let Some(make_install_args) = &pkg_info.make_install_args {
+            set_var("make_install_args", make_install_args);
         }
 
-        let license = &pkg_info.license.as_ref().unwrap_or(&Vec::new()).join(", ");
+        let license = &licenses.join(", ");
         if license.contains(&"MIT".to_string())
             || license.contains(&"ISC".to_string())
             || license.contains(&"BSD".to_string())
         {
-            template_string.push_str("\n\npost_install() {\n\tvlicense LICENSE\n}");
+            match &pkg_info.license_files {
+                Some(license_files) if !license_files.is_empty() => {
+                    vars.insert(
+                        "license_files".to_string(),
+                        SkeletonValue::List(license_files.clone()),
+                    );
+                }
+                Some(_) => warn!(
+                    "{} doesn't ship a license file, but its license {} requires installing one! Please check the template.",
+                    &pkg_info.pkg_name, license
+                ),
+                None => warn!(
+                    "Couldn't determine the license files of {}, please install them in the template yourself!",
+                    &pkg_info.pkg_name
+                ),
+            }
         }
 
-        template_string.push_str("\n");
+        if !pkg_info.subpackages.is_empty() {
+            vars.insert(
+                "subpackages".to_string(),
+                SkeletonValue::List(
+                    pkg_info
+                        .subpackages
+                        .iter()
+                        .map(|x| gen_subpkg_string(&pkg_info.pkg_name, x))
+                        .collect(),
+                ),
+            );
+        }
+
+        for (name, value) in &self.variables {
+            vars.insert(name.clone(), SkeletonValue::Str(value.clone()));
+        }
+
+        let template_string = render_skeleton(&skeleton, &vars)?;
 
         Ok(Template {
             inner: template_string,
             name: pkg_info.pkg_name.clone(),
+            subpackages: pkg_info
+                .subpackages
+                .iter()
+                .map(|x| format!("{}-{}", pkg_info.pkg_name, x.suffix))
+                .collect(),
         })
     }
 }
+
+impl Template {
+    /// Gets the value of the field `field` (e.g. `version`) of the template, without surrounding
+    /// quotes. Returns `None` if the template doesn't set the field.
+    pub fn get_field(&self, field: &str) -> Option<String> {
+        template_field(&self.inner, field)
+    }
+}
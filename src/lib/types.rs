@@ -13,7 +13,8 @@
 //You should have received a copy of the GNU General Public License
 //along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
 
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::io::Read;
 
 /// The TemplateBuilder struct, which is used to build a [Template](crate::types::Template)
@@ -21,11 +22,22 @@ pub struct TmplBuilder {
     pub pkg_name: String,
     pub pkg_type: Option<PkgType>,
     pub pkg_info: Option<PkgInfo>,
+    pub features: CrateFeatures,
+    pub runtime_versions: RuntimeVersions,
+    /// The skeleton the template is rendered from, the default one for the PkgType if unset
+    pub skeleton: Option<String>,
+    /// Custom variables that can be used in the skeleton in addition to the built-in ones
+    pub variables: BTreeMap<String, String>,
 }
 
+/// A generated template, `inner` is its contents
+#[derive(Clone, Eq, Ord, PartialOrd, Hash, Default, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Template {
     pub inner: String,
     pub name: String,
+    /// The names of the subpackages defined in the template, which need a symlink to `name` in
+    /// `srcpkgs`
+    pub subpackages: Vec<String>,
 }
 
 impl Read for Template {
@@ -34,24 +46,64 @@ impl Read for Template {
     }
 }
 
-/// The PkgType enum, containing all types of packages tmplgen can handle
-#[derive(Copy, Clone, Eq, Ord, PartialOrd, Hash, Debug, PartialEq)]
+/// The PkgType enum, containing all types of packages tmplgen can handle. It's (de)serialized as
+/// `crate`, `gem` and `perldist`, like it's passed to `tmplgen -t`.
+#[derive(Copy, Clone, Eq, Ord, PartialOrd, Hash, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum PkgType {
     Crate,
     Gem,
     PerlDist,
 }
 
-/// The Dependencies struct that contains all dependencies a package might have
+impl PkgType {
+    /// All PkgTypes, in the order their registries are queried in
+    pub fn all() -> &'static [PkgType] {
+        &[PkgType::Crate, PkgType::Gem, PkgType::PerlDist]
+    }
+}
+
+/// A registry on which a package with the name that was looked up exists, see
+/// [find_providers](crate::TmplBuilder::find_providers)
+#[derive(Clone, Eq, Ord, PartialOrd, Hash, Debug, PartialEq)]
+pub struct ProviderMatch {
+    pub pkg_type: PkgType,
+    /// The latest version of the package on the registry
+    pub version: String,
+}
+
+/// The versions of the language runtimes templates are generated for, which determine what's
+/// built into them. `None` means the latest version tmplgen knows about.
+#[derive(Clone, Eq, Ord, PartialOrd, Hash, Default, Debug, PartialEq)]
+pub struct RuntimeVersions {
+    /// The perl version, e.g. `5.36.0` or `5.36`
+    pub perl: Option<String>,
+    /// The Ruby version, e.g. `3.3.0` or `3.3`
+    pub ruby: Option<String>,
+}
+
+/// The CrateFeatures struct, which contains the Cargo features a crate template is generated for.
+/// The default is the crate's default feature set.
 #[derive(Clone, Eq, Ord, PartialOrd, Hash, Default, Debug, PartialEq)]
+pub struct CrateFeatures {
+    pub features: Vec<String>,
+    pub no_default_features: bool,
+}
+
+/// The Dependencies struct that contains all dependencies a package might have
+#[derive(Clone, Eq, Ord, PartialOrd, Hash, Default, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Dependencies {
     pub host: Option<Vec<String>>,
     pub make: Option<Vec<String>>,
     pub run: Option<Vec<String>>,
 }
 
-/// The PkgInfo struct, that contains all info relevant to the package
-#[derive(Clone, Eq, Ord, PartialOrd, Hash, Default, Debug, PartialEq)]
+/// The PkgInfo struct, that contains all info relevant to the package. It can be written to JSON
+/// (e.g. by `tmplgen info --json`), edited and read back to generate a template from it, fields
+/// missing from the JSON are left empty.
+#[derive(Clone, Eq, Ord, PartialOrd, Hash, Default, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct PkgInfo {
     pub pkg_name: String,
     pub version: String,
@@ -61,6 +113,59 @@ pub struct PkgInfo {
     pub dependencies: Option<Dependencies>,
     pub sha: String,
     pub download_url: Option<String>,
+    /// Whether the package compiles native code (e.g. XS perldists or gems with C extensions) and
+    /// as such can't be `noarch`
+    pub native: bool,
+    /// The license files shipped in the distfile, None if we couldn't look into it
+    pub license_files: Option<Vec<String>>,
+    /// The subpackages split off the package, e.g. `foo-devel`
+    pub subpackages: Vec<SubPackage>,
+    /// The arguments for installing the package, e.g. `--path cli` for crates whose binary is in
+    /// a member of their workspace
+    pub make_install_args: Option<String>,
+    /// The directory the distfile extracts to, if it isn't named after the package, e.g. because
+    /// it's the tarball of a gem's GitHub repository
+    pub wrksrc: Option<String>,
+}
+
+/// A subpackage split off a package, e.g. `foo-devel` for the headers of `foo`
+#[derive(Clone, Eq, Ord, PartialOrd, Hash, Default, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SubPackage {
+    /// The suffix of the subpackage's name, e.g. `devel` for `foo-devel`
+    pub suffix: String,
+    /// What's appended to the short_desc of the package, e.g. `development files`
+    pub short_desc: String,
+    pub depends: Vec<String>,
+    /// Whether the subpackage is architecture-independent, e.g. because it only contains docs
+    pub noarch: bool,
+    /// The files moved into the subpackage, relative to DESTDIR. May contain globs.
+    pub files: Vec<String>,
+}
+
+/// A dependency of a package, see [get_dep_tree](crate::TmplBuilder::get_dep_tree)
+#[derive(Clone, Eq, Ord, PartialOrd, Hash, Default, Debug, PartialEq)]
+pub struct DepTree {
+    /// The dependency as it's written to the template, e.g. `perl-Moo>=2.0`
+    pub name: String,
+    /// The field of the template the dependency is written to, e.g. `makedepends`
+    pub field: String,
+    /// The dependencies of the dependency. Only determined for perldists and gems that aren't
+    /// built into perl/Ruby and only at their first occurrence in the tree.
+    pub deps: Vec<DepTree>,
+}
+
+/// A package found by [search](crate::TmplBuilder::search)
+#[derive(Clone, Eq, Ord, PartialOrd, Hash, Debug, PartialEq)]
+pub struct SearchResult {
+    pub name: String,
+    /// The latest version of the package
+    pub version: String,
+    pub description: Option<String>,
+    pub pkg_type: PkgType,
+    /// Whether there's a template for the package in srcpkgs already. Always `false` if no
+    /// srcpkgs was passed to [search](crate::TmplBuilder::search).
+    pub template_exists: bool,
 }
 
 pub(super) struct DownloadProgress<R> {
@@ -77,37 +182,340 @@ impl<R: std::io::Read> std::io::Read for DownloadProgress<R> {
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Hashes everything that's read through it
+pub(super) struct HashingReader<R> {
+    pub inner: R,
+    pub hasher: sha2::Sha256,
+}
+
+impl<R: std::io::Read> std::io::Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf).map(|n| {
+            sha2::Digest::input(&mut self.hasher, &buf[..n]);
+            n
+        })
+    }
+}
+
+/// What we found out about a distfile while downloading it
+#[derive(Debug)]
+pub(super) struct DistfileInfo {
+    pub sha: String,
+    pub license_files: Option<Vec<String>>,
+    pub doc_size: u64,
+    pub files: Vec<String>,
+    pub manifests: BTreeMap<String, String>,
+    pub build_scripts: BTreeMap<String, String>,
+}
+
+/// What we found out about a tarball by looking into it
+#[derive(Debug, Default)]
+pub(super) struct ArchiveContents {
+    pub license_files: Vec<String>,
+    /// The size of the `.pod` files and the files in the `doc/` directory in bytes
+    pub doc_size: u64,
+    /// The paths of the files, relative to the top level
+    pub files: Vec<String>,
+    /// The contents of the `Cargo.toml`s, keyed by their directory relative to the top level
+    /// (empty for the top-level one)
+    pub manifests: BTreeMap<String, String>,
+    /// The contents of the `.rs` files which might be the build script of the top-level crate
+    /// (see [is_build_script_candidate](crate::crates::is_build_script_candidate)), keyed by their
+    /// path relative to the top level
+    pub build_scripts: BTreeMap<String, String>,
+}
+
+/// The targets of a crate, determined from its `Cargo.toml` and files
+#[derive(Debug, Default, Eq, PartialEq)]
+pub(super) struct CrateTargets {
+    /// The names of the binaries
+    pub bins: Vec<String>,
+    pub lib: bool,
+}
+
+/// What we found out about a gem by looking into it
+#[derive(Debug, Default)]
+pub(super) struct GemContents {
+    pub extensions: Vec<String>,
+    pub license_files: Vec<String>,
+    pub doc_size: u64,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub(super) struct BuiltInDeps {
     pub perl: Vec<String>,
     pub ruby: Vec<String>,
+    /// Perldists mapped to the module of perl_corelist.toml whose version is theirs
+    pub perl_dists: BTreeMap<String, String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub(super) struct CorrectedVals {
-    pub licenses: Vec<CorrectedLicenses>,
+    pub common: Vec<CorrectedLicenses>,
+    pub perl: Vec<CorrectedLicenses>,
+    pub ruby: Vec<CorrectedLicenses>,
 }
 
-#[derive(Debug, Deserialize)]
-pub(super) struct CorrectedLicenses {
+/// A correction of a license name that isn't an SPDX identifier (e.g. the CPAN::Meta key
+/// `perl_5`) to SPDX identifiers
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CorrectedLicenses {
+    /// The license name as found on crates.io/metacpan.org/rubygems.org
     pub is: String,
-    pub should: String,
+    /// The SPDX identifiers `is` corresponds to, None if `is` is ambiguous
+    pub should: Option<String>,
+    /// The SPDX identifiers an ambiguous `is` could correspond to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub(super) struct NativeDepType {
     pub rust: Vec<NativeDeps>,
+    pub perl: Vec<NativeDeps>,
+    pub ruby: Vec<NativeDeps>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub(super) struct NativeDeps {
     pub name: String,
     pub dep: String,
 }
 
+/// The layout of `registries.toml`. All endpoints are optional, the ones that aren't set are
+/// taken from the official registries.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct TomlRegistries {
+    #[serde(rename = "crate")]
+    pub crate_registry: TomlRegistry,
+    pub gem: TomlRegistry,
+    pub perldist: TomlRegistry,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct TomlRegistry {
+    pub api: Option<String>,
+    pub download: Option<String>,
+}
+
+/// What metacpan.org tells us about the latest release of a perldist
+#[derive(Debug, Default)]
+pub(super) struct PerlRelease {
+    /// The name of the perldist, e.g. `Moose`
+    pub name: String,
+    pub version: String,
+    pub download_url: String,
+    pub homepage: Option<String>,
+    pub description: Option<String>,
+    pub license: Option<Vec<String>>,
+    pub dependencies: Vec<PerlDependency>,
+}
+
+/// A module a perldist depends on
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct PerlDependency {
+    pub module: String,
+    /// The phase the module is needed in, e.g. `configure` or `runtime`
+    pub phase: String,
+}
+
+/// What rubygems.org tells us about a gem
+#[derive(Debug)]
+pub(super) struct GemMeta {
+    pub version: String,
+    pub info: Option<String>,
+    pub homepage_uri: Option<String>,
+    pub licenses: Option<Vec<String>>,
+    pub sha: String,
+    pub runtime_deps: Vec<GemResponseDep>,
+}
+
+/// The response of rubygems.org's endpoint for a gem
+#[derive(Debug, Deserialize)]
+pub(super) struct GemResponse {
+    pub version: String,
+    pub info: Option<String>,
+    pub homepage_uri: Option<String>,
+    pub licenses: Option<Vec<String>>,
+    pub sha: String,
+    pub dependencies: GemResponseDeps,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct GemResponseDeps {
+    #[serde(default)]
+    pub runtime: Vec<GemResponseDep>,
+}
+
+/// A runtime dependency of a gem and the versions of it the gem requires, e.g. `>= 1.0`
+#[derive(Debug, Deserialize)]
+pub(super) struct GemResponseDep {
+    pub name: String,
+    pub requirements: String,
+}
+
+/// Info about a specific version of a gem, e.g. the platform it's built for
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct GemVersionInfo {
+    #[serde(default)]
+    pub platform: String,
+    pub source_code_uri: Option<String>,
+}
+
+/// Where the sources of a gem might be downloaded from, see
+/// [gem_sources](crate::gems::gem_sources)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct GemSource {
+    /// The URL of the source tarball, with `${version}` in place of the version
+    pub url: String,
+    /// The directory the tarball extracts to
+    pub wrksrc: String,
+}
+
+/// The response of crates.io's search endpoint
+#[derive(Debug, Deserialize)]
+pub(super) struct CrateSearchResponse {
+    pub crates: Vec<CrateSearchEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct CrateSearchEntry {
+    pub name: String,
+    pub max_version: String,
+    pub description: Option<String>,
+}
+
+/// An entry of the response of rubygems.org's search endpoint
+#[derive(Debug, Deserialize)]
+pub(super) struct GemSearchEntry {
+    pub name: String,
+    pub version: String,
+    pub info: Option<String>,
+}
+
+/// What crates.io tells us about a crate
+#[derive(Debug, Default)]
+pub(super) struct CrateMeta {
+    pub max_version: String,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub license: Option<String>,
+}
+
+/// The response of crates.io's endpoint for a crate
+#[derive(Debug, Deserialize)]
+pub(super) struct CrateResponse {
+    #[serde(rename = "crate")]
+    pub crate_data: CrateData,
+    pub versions: Vec<CrateVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct CrateData {
+    pub max_version: String,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct CrateVersion {
+    pub num: String,
+    pub license: Option<String>,
+    #[serde(default)]
+    pub features: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub yanked: bool,
+}
+
+/// The response of crates.io's endpoint for the dependencies of a version of a crate
+#[derive(Debug, Deserialize)]
+pub(super) struct CrateDependenciesResponse {
+    pub dependencies: Vec<CrateDependency>,
+}
+
+/// A dependency of a crate
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub(super) struct CrateDependency {
+    pub crate_id: String,
+    /// The version requirement, e.g. `^0.9`
+    pub req: String,
+    pub optional: bool,
+}
+
+/// The native dependencies of a crate we know about from the data tables and the crates whose
+/// build scripts we have to look at to find the rest
+#[derive(Debug, Default)]
+pub(super) struct CrateNativeDeps {
+    pub host: Vec<String>,
+    pub make: Vec<String>,
+    /// Whether the crate itself isn't in the data tables, so we have to look at its own build
+    /// script
+    pub own_build_script: bool,
+    /// The `-sys` crates the crate depends on which aren't in the data tables
+    pub unknown_crates: Vec<CrateDependency>,
+}
+
+/// Hints about native dependencies we found in a crate's `Cargo.toml` and build script
+#[derive(Debug, Default, Eq, PartialEq)]
+pub(super) struct BuildScriptInfo {
+    pub links: Option<String>,
+    pub pkg_config: Vec<String>,
+    pub uses_cc: bool,
+    pub uses_cmake: bool,
+    pub uses_bindgen: bool,
+}
+
+/// The value of a variable in a template skeleton
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) enum SkeletonValue {
+    Str(String),
+    List(Vec<String>),
+}
+
+/// The modules shipped with each perl version and their versions, generated from
+/// Module::CoreList by `tools/gen_perl_corelist.pl`
+#[derive(Debug, Deserialize)]
+pub(super) struct PerlCoreList {
+    pub default: String,
+    pub versions: BTreeMap<String, HashMap<String, String>>,
+}
+
+/// The gems shipped with each Ruby version, see `tools/gen_ruby_gems.rb`
+#[derive(Debug, Deserialize)]
+pub(super) struct RubyGemList {
+    pub default: String,
+    pub versions: BTreeMap<String, RubyGems>,
+}
+
+/// The default gems (which can't be uninstalled) and bundled gems (which are installed with Ruby,
+/// but can be upgraded) of a Ruby version and their versions
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct RubyGems {
+    pub default: HashMap<String, String>,
+    pub bundled: HashMap<String, String>,
+}
+
+/// The SPDX license list embedded via `spdx.toml`
 #[derive(Debug, Deserialize)]
+pub(super) struct SpdxData {
+    pub licenses: Vec<String>,
+    pub deprecated: Vec<String>,
+    pub exceptions: Vec<String>,
+}
+
+/// The data tables of `data.toml`. All tables are optional, so user-supplied files only have to
+/// contain the entries they want to add or override.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub(super) struct TomlData {
     pub builtin: BuiltInDeps,
-    pub licenses: Vec<CorrectedLicenses>,
+    pub licenses: CorrectedVals,
     pub native_deps: NativeDepType,
+    pub pkg_config: Vec<NativeDeps>,
 }
@@ -0,0 +1,99 @@
+#!/usr/bin/env perl
+#This file is part of tmplgen
+#
+#tmplgen is free software: you can redistribute it and/or modify
+#it under the terms of the GNU General Public License as published by
+#the Free Software Foundation, either version 3 of the License, or
+#(at your option) any later version.
+#
+#tmplgen is distributed in the hope that it will be useful,
+#but WITHOUT ANY WARRANTY; without even the implied warranty of
+#MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#GNU General Public License for more details.
+#
+#You should have received a copy of the GNU General Public License
+#along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
+
+# Generates src/lib/perl_corelist.toml, the modules (and their versions) shipped with perl,
+# from Module::CoreList.
+#
+# Usage:
+#
+#   tools/gen_perl_corelist.pl [PERL_VERSION...] > src/lib/perl_corelist.toml
+#
+# PERL_VERSIONs look like `5.36.0`. Without any, the latest release of every stable series
+# since 5.26 known to Module::CoreList is used. The Module::CoreList of the local perl is used,
+# to use a vendored snapshot of it (e.g. an extracted Module-CoreList tarball from CPAN) run
+# `perl -I Module-CoreList-5.20240609/lib tools/gen_perl_corelist.pl` instead.
+
+use strict;
+use warnings;
+
+use Module::CoreList;
+
+# 5.036000 -> 5.36.0
+sub dotted {
+    my ($numeric) = @_;
+    my ( $major, $minor, $patch ) = sprintf( '%.6f', $numeric ) =~ /^(\d+)\.(\d{3})(\d{3})$/;
+
+    return sprintf( '%d.%d.%d', $major, $minor, $patch );
+}
+
+# 5.36.0 -> 5.036000
+sub numeric {
+    my ($dotted) = @_;
+    my ( $major, $minor, $patch ) = split /\./, $dotted;
+
+    return sprintf( '%d.%03d%03d', $major, $minor, $patch // 0 );
+}
+
+sub toml_string {
+    my ($string) = @_;
+    $string =~ s/(["\\])/\\$1/g;
+
+    return "\"$string\"";
+}
+
+my @releases =
+  sort { $a <=> $b }
+  grep { $_ >= 5.026 }
+  map  { numeric( dotted($_) ) } keys %Module::CoreList::released;
+
+my @versions;
+
+if (@ARGV) {
+    for my $version (@ARGV) {
+        die "Module::CoreList doesn't know perl $version!\n"
+          unless exists $Module::CoreList::version{ numeric($version) };
+
+        push @versions, numeric($version);
+    }
+}
+else {
+    my %latest;
+
+    for my $release (@releases) {
+        my ( undef, $minor ) = split /\./, dotted($release);
+
+        # Odd minor versions are development releases
+        $latest{$minor} = $release if $minor % 2 == 0;
+    }
+
+    @versions = map { $latest{$_} } sort { $a <=> $b } keys %latest;
+}
+
+print "# Generated by tools/gen_perl_corelist.pl from Module::CoreList $Module::CoreList::VERSION, don't edit!\n";
+print "# Maps the modules shipped with a perl version to their version (empty if they don't have one).\n";
+print "default = ", toml_string( dotted( $versions[-1] ) ), "\n";
+
+for my $version (@versions) {
+    my $modules = $Module::CoreList::version{$version};
+
+    print "\n[versions.", toml_string( dotted($version) ), "]\n";
+
+    for my $module ( sort keys %$modules ) {
+        my $module_version = $modules->{$module} // '';
+
+        print toml_string($module), " = ", toml_string($module_version), "\n";
+    }
+}
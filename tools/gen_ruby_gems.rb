@@ -0,0 +1,56 @@
+#!/usr/bin/env ruby
+#This file is part of tmplgen
+#
+#tmplgen is free software: you can redistribute it and/or modify
+#it under the terms of the GNU General Public License as published by
+#the Free Software Foundation, either version 3 of the License, or
+#(at your option) any later version.
+#
+#tmplgen is distributed in the hope that it will be useful,
+#but WITHOUT ANY WARRANTY; without even the implied warranty of
+#MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#GNU General Public License for more details.
+#
+#You should have received a copy of the GNU General Public License
+#along with tmplgen.  If not, see <http://www.gnu.org/licenses/>.
+
+# Prints the default and bundled gems of a Ruby installation as a section of
+# src/lib/ruby_gems.toml.
+#
+# Usage:
+#
+#   tools/gen_ruby_gems.rb [SPECIFICATIONS_DIR RUBY_VERSION] >> src/lib/ruby_gems.toml
+#
+# Without arguments the `specifications` directory of the running Ruby is used. To use a vendored
+# snapshot (e.g. extracted from a Ruby package) pass its `lib/ruby/gems/X.Y.0/specifications`
+# directory and the version of the Ruby it belongs to. Update `default` in ruby_gems.toml when
+# adding a newer Ruby version.
+
+spec_dir, ruby_version =
+  if ARGV.empty?
+    [File.join(Gem.default_dir, 'specifications'), RUBY_VERSION]
+  elsif ARGV.length == 2
+    ARGV
+  else
+    abort 'Usage: tools/gen_ruby_gems.rb [SPECIFICATIONS_DIR RUBY_VERSION]'
+  end
+
+# Spec files are named `{name}-{version}.gemspec`
+def gems_in(dir)
+  Dir.glob(File.join(dir, '*.gemspec'))
+     .map { |path| File.basename(path, '.gemspec').match(/\A(.+)-([^-]+)\z/) }
+     .compact
+     .map { |match| [match[1], match[2]] }
+     .sort
+end
+
+# Default gems have their specs in `specifications/default`, bundled gems are installed like
+# regular gems.
+{ 'default' => File.join(spec_dir, 'default'), 'bundled' => spec_dir }.each do |kind, dir|
+  puts
+  puts "[versions.\"#{ruby_version}\".#{kind}]"
+
+  gems_in(dir).each do |name, version|
+    puts "#{name.inspect} = #{version.inspect}"
+  end
+end